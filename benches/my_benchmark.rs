@@ -1,19 +1,47 @@
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, SocketAddr};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use matscan::scanner::targets::{Ipv4Range, Ipv4Ranges, ScanRange, ScanRanges, StaticScanRanges};
+use matscan::{
+    net::{
+        raw_sockets::PacketBatch,
+        tcp_template::{PacketRepr, TemplatePacket, TemplatePacketRepr},
+    },
+    scanner::targets::{IpRange, IpRanges, ScanRange, ScanRanges, StaticScanRanges},
+};
+use pnet::{packet::tcp::TcpFlags, util::MacAddr};
 use rand::Rng;
 
-fn scan_ranges_index(scan_ranges: &StaticScanRanges, n: usize) -> SocketAddrV4 {
+const SEND_BATCH_SIZE: usize = 256;
+
+fn scan_ranges_index(scan_ranges: &StaticScanRanges, n: usize) -> SocketAddr {
     scan_ranges.index(n)
 }
 
+/// Building a batch of SYNs from the template and copying them into a
+/// [`PacketBatch`]. The syscalls aren't measured, so this isn't the rate that
+/// any of the send modes can reach.
+fn queue_syn_batch(template: &mut TemplatePacket, batch: &mut PacketBatch, first_ip: u32) {
+    for i in 0..SEND_BATCH_SIZE as u32 {
+        let packet = template.build(PacketRepr {
+            dest_addr: Ipv4Addr::from(first_ip.wrapping_add(i)).into(),
+            dest_port: 25565,
+            source_port: 61000,
+            sequence: i,
+            acknowledgement: 0,
+            timestamp: 0,
+            payload: &[],
+        });
+        batch.push(packet);
+    }
+    batch.clear();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let mut ranges = ScanRanges::new();
     for i in 0..100_000 {
         ranges.extend(vec![ScanRange {
-            addr_start: Ipv4Addr::from(i),
-            addr_end: Ipv4Addr::from(i),
+            addr_start: Ipv4Addr::from(i).into(),
+            addr_end: Ipv4Addr::from(i).into(),
             port_start: 1024,
             port_end: 65535,
         }])
@@ -21,12 +49,12 @@ fn criterion_benchmark(c: &mut Criterion) {
 
     let mut excluded = Vec::new();
     for i in 0..100_000 {
-        excluded.push(Ipv4Range {
-            start: Ipv4Addr::from(i),
-            end: Ipv4Addr::from(i),
+        excluded.push(IpRange {
+            start: Ipv4Addr::from(i).into(),
+            end: Ipv4Addr::from(i).into(),
         })
     }
-    let excluded = Ipv4Ranges::new(excluded);
+    let excluded = IpRanges::new(excluded);
 
     c.bench_function("exclude", |b| {
         b.iter(|| {
@@ -40,6 +68,21 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("scan_ranges_index", |b| {
         b.iter(|| scan_ranges_index(&ranges, black_box(rng.gen_range(0..ranges.count))))
     });
+
+    let mut template = TemplatePacket::new(TemplatePacketRepr {
+        flags: TcpFlags::SYN,
+        window: 64240,
+        urgent_ptr: 0,
+        options: vec![],
+        initial_ttl: 64,
+        gateway_mac: Some(MacAddr::zero()),
+        interface_mac: Some(MacAddr::zero()),
+        source_addr: Ipv4Addr::new(10, 0, 0, 1).into(),
+    });
+    let mut batch = PacketBatch::new(SEND_BATCH_SIZE, 1514);
+    c.bench_function("queue_syn_batch", |b| {
+        b.iter(|| queue_syn_batch(&mut template, &mut batch, black_box(rng.gen())))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -1,12 +1,17 @@
 // download https://iptoasn.com/data/ip2asn-v4-u32.tsv.gz and cache it
 
-use std::{io::BufRead, net::Ipv4Addr, sync::OnceLock, time::Duration};
+use std::{
+    io::BufRead,
+    net::{IpAddr, Ipv4Addr},
+    sync::OnceLock,
+    time::Duration,
+};
 
-use crate::scanner::targets::Ipv4Range;
+use crate::scanner::targets::IpRange;
 
 /// A vec of (range, asn) pairs
 #[derive(Debug)]
-pub struct AsnRanges(pub Vec<(Ipv4Range, u32)>);
+pub struct AsnRanges(pub Vec<(IpRange, u32)>);
 
 pub async fn download() -> anyhow::Result<AsnRanges> {
     let client = reqwest::Client::new();
@@ -35,7 +40,13 @@ pub async fn download() -> anyhow::Result<AsnRanges> {
         let end = Ipv4Addr::from(end.parse::<u32>()?);
         let asn = asn.parse::<u32>()?;
 
-        ranges.push((Ipv4Range { start, end }, asn));
+        ranges.push((
+            IpRange {
+                start: start.into(),
+                end: end.into(),
+            },
+            asn,
+        ));
     }
 
     Ok(AsnRanges(ranges))
@@ -65,6 +76,7 @@ pub async fn get() -> anyhow::Result<&'static AsnRanges> {
 
 impl AsnRanges {
     pub fn get_asn(&self, ip: Ipv4Addr) -> Option<u32> {
+        let ip = IpAddr::V4(ip);
         // do a binary search
 
         let mut start = 0;
@@ -89,7 +101,7 @@ impl AsnRanges {
         None
     }
 
-    pub fn get_ranges_for_asn(&self, asn: u32) -> Vec<Ipv4Range> {
+    pub fn get_ranges_for_asn(&self, asn: u32) -> Vec<IpRange> {
         self.0
             .iter()
             .filter(|(_, a)| *a == asn)
@@ -105,18 +117,18 @@ mod tests {
     #[tokio::test]
     async fn test_asns() {
         let asns = AsnRanges(vec![(
-            Ipv4Range {
-                start: Ipv4Addr::new(0, 0, 0, 0),
-                end: Ipv4Addr::new(0, 0, 0, 255),
+            IpRange {
+                start: Ipv4Addr::new(0, 0, 0, 0).into(),
+                end: Ipv4Addr::new(0, 0, 0, 255).into(),
             },
             1,
         )]);
         assert_eq!(asns.get_asn(Ipv4Addr::new(0, 0, 0, 128)), Some(1));
         assert_eq!(
             asns.get_ranges_for_asn(1),
-            vec![Ipv4Range {
-                start: Ipv4Addr::new(0, 0, 0, 0),
-                end: Ipv4Addr::new(0, 0, 0, 255),
+            vec![IpRange {
+                start: Ipv4Addr::new(0, 0, 0, 0).into(),
+                end: Ipv4Addr::new(0, 0, 0, 255).into(),
             }]
         );
     }
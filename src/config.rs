@@ -1,4 +1,8 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
 use serde::Deserialize;
 use crate::scanner::SourcePort;
 
@@ -28,6 +32,41 @@ pub struct Config {
     #[serde(default)]
     pub source_port: SourcePort,
 
+    /// How the SYNs are handed to the kernel. Defaults to `send`.
+    #[serde(default)]
+    pub send_mode: SendMode,
+
+    /// The number of packets per sendmmsg, or the number of frames in the TX
+    /// ring. Defaults to 256.
+    #[serde(default)]
+    pub send_batch_size: Option<usize>,
+
+    /// The number of threads that send packets, each with its own socket and
+    /// an equal share of the rate. Defaults to 1.
+    #[serde(default)]
+    pub sender_threads: Option<usize>,
+
+    /// The number of threads that receive and reply to packets. They're
+    /// joined with PACKET_FANOUT so each one handles its own connections.
+    /// Defaults to 1.
+    #[serde(default)]
+    pub receiver_threads: Option<usize>,
+
+    /// How many more SYNs are sent to every target in case the first one is
+    /// lost, like masscan's `--retries`. Defaults to 0.
+    #[serde(default)]
+    pub retries: Option<u64>,
+    /// The number of seconds between the SYNs to the same target. Retries are
+    /// mixed in with the other targets, so they're not sent in bursts.
+    /// Defaults to 1 second.
+    #[serde(default)]
+    pub retry_spacing_secs: Option<u64>,
+
+    /// The interface, source addresses and gateway to use. Everything is
+    /// detected automatically by default.
+    #[serde(default)]
+    pub network: NetworkConfig,
+
     /// The maximum amount of time each scan will take. Defaults to 5 minutes.
     /// You should probably leave it as the default unless you're debugging
     /// something to do with switching modes.
@@ -75,6 +114,49 @@ pub struct Config {
     #[serde(default)]
     pub fingerprinting: FingerprintingConfig,
 
+    #[serde(default)]
+    pub legacy: LegacyConfig,
+
+    #[serde(default)]
+    pub bedrock: BedrockConfig,
+
+    #[serde(default)]
+    pub query: QueryConfig,
+
+    #[serde(default)]
+    pub login: LoginConfig,
+
+    #[serde(default)]
+    pub protocol_range: ProtocolRangeConfig,
+
+    #[serde(default)]
+    pub forge: ForgeConfig,
+
+    #[serde(default)]
+    pub rcon: RconConfig,
+
+    #[serde(default)]
+    pub webmap: WebmapConfig,
+
+    /// Probes that are defined here instead of in code, as `[[probes]]`.
+    #[serde(default)]
+    pub probes: Vec<ProbeConfig>,
+
+    #[serde(default)]
+    pub generic_probes: GenericProbesConfig,
+
+    #[serde(default)]
+    pub geyser: GeyserConfig,
+
+    #[serde(default)]
+    pub proxy_protocol: ProxyProtocolConfig,
+
+    #[serde(default)]
+    pub transfer: TransferConfig,
+
+    #[serde(default)]
+    pub ipv6: Ipv6Config,
+
     /// The directory where the rotating matscan.log files should be written to.
     /// None to disable logging to a file. Note that these logs aren't the same
     /// as the ones that are shown in stdout.
@@ -98,7 +180,49 @@ pub struct FingerprintConfig {
 pub struct TargetConfig {
     pub addr: String,
     pub port: u16,
+    /// The protocol version used when we don't know the server's version
+    /// already.
     pub protocol_version: i32,
+    /// What hostname (and port) to put in the handshake. Defaults to `fixed`,
+    /// which always uses `addr` and `port`.
+    #[serde(default)]
+    pub hostname: HostnameStrategy,
+    /// A file with hostnames that point to servers, one `hostname` or
+    /// `hostname:port` per line. They're resolved on startup, and when we
+    /// find a server on one of the addresses the hostname is stored in its
+    /// `hostname` field for the `virtual_host` strategy.
+    #[serde(default)]
+    pub virtual_hosts: Option<String>,
+    /// Whether to send a ping request after getting the status, to measure
+    /// the server list ping latency. Defaults to true.
+    #[serde(default)]
+    pub ping: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SendMode {
+    /// One send syscall per packet.
+    #[default]
+    Send,
+    /// Send the packets in batches with sendmmsg.
+    Sendmmsg,
+    /// Write the packets to a PACKET_TX_RING and let the kernel send them.
+    TxRing,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HostnameStrategy {
+    /// Use `target.addr` and `target.port`.
+    #[default]
+    Fixed,
+    /// Use the IP and port of the server we're pinging, like a client that
+    /// connected directly would.
+    TargetIp,
+    /// Use the `hostname` field of the server in the database (from
+    /// `target.virtual_hosts`), or the IP if we don't know one.
+    VirtualHost,
 }
 
 #[derive(Deserialize, Clone)]
@@ -106,7 +230,8 @@ pub struct TargetConfig {
 pub struct ScannerConfig {
     pub enabled: bool,
     /// The list of modes that we'll use to scan. By default, all modes are
-    /// included. Refer to modes.rs for a list of modes.
+    /// included. Refer to modes.rs for a list of modes. These only scan
+    /// around known IPv4 servers, IPv6 is covered by `[ipv6]` instead.
     #[serde(default)]
     pub modes: Option<Vec<String>>,
 }
@@ -154,3 +279,287 @@ pub struct FingerprintingConfig {
     /// be able to gather as much information as active fingerprinting.
     pub enabled: bool,
 }
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyConfig {
+    /// Ping servers that replied with a SYN+ACK but never sent a valid server
+    /// list ping response again with the 1.4-1.6 and beta pings.
+    pub enabled: bool,
+
+    /// The maximum number of targets that are remembered for the next legacy
+    /// scan. Defaults to 1,000,000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BedrockConfig {
+    /// Scan for Bedrock Edition servers over UDP. The source port must also be
+    /// firewalled for UDP if this is enabled.
+    pub enabled: bool,
+    /// The list of Bedrock modes that we'll use. By default, all modes are
+    /// included. Refer to modes/bedrock.rs for a list of modes. Only
+    /// `Rescan` pings IPv6 servers, the others are IPv4-only.
+    #[serde(default)]
+    pub modes: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QueryConfig {
+    /// Send a GameSpy4 query to servers that are online, which gets us the
+    /// plugins, map, and full player list if `enable-query` is on. The source
+    /// port must also be firewalled for UDP if this is enabled.
+    pub enabled: bool,
+    /// How often the same server is queried. Defaults to 1 day.
+    #[serde(default)]
+    pub query_every_secs: Option<u64>,
+    /// The maximum number of servers to query in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoginConfig {
+    /// Start logging in to online servers to find out for sure whether
+    /// they're in online mode. This will show up in the consoles of servers.
+    pub enabled: bool,
+    /// The username we log in with. Defaults to "matscan".
+    #[serde(default)]
+    pub username: Option<String>,
+    /// How often the same server is probed. Defaults to 1 week.
+    #[serde(default)]
+    pub login_every_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProtocolRangeConfig {
+    /// Ping online servers with other protocol versions to find out which
+    /// versions they support (for example with ViaVersion). One version is
+    /// tried per scan.
+    pub enabled: bool,
+    /// The protocol versions to try. Refer to modes/protocol_range.rs for the
+    /// default list.
+    #[serde(default)]
+    pub versions: Option<Vec<i32>>,
+    /// Start logging in to servers whose status always has their own version.
+    /// Uses the username from `[login]`. Defaults to true.
+    #[serde(default)]
+    pub login_fallback: Option<bool>,
+    /// How often the same server is probed with the same version. Defaults to
+    /// 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// The maximum number of servers to probe in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ForgeConfig {
+    /// Log in to modded servers whose status didn't have the whole mod list,
+    /// and read the mods from the Forge/NeoForge handshake. Uses the username
+    /// from `[login]`. This will show up in the consoles of servers.
+    pub enabled: bool,
+    /// How often the same server is probed. Defaults to 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// The maximum number of servers to probe in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RconConfig {
+    /// Check whether the IPs of online servers have RCON exposed, so owners
+    /// can be told about it. We only send one login with a random password.
+    pub enabled: bool,
+    /// The ports that RCON is checked on. Defaults to 25575.
+    #[serde(default)]
+    pub ports: Option<Vec<u16>>,
+    /// How often the same IP is checked. Defaults to 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// The maximum number of IPs to check in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WebmapConfig {
+    /// Look for web maps (Dynmap, BlueMap, Pl3xMap, etc) on the IPs of online
+    /// servers with an HTTP request.
+    pub enabled: bool,
+    /// The ports that are checked. Defaults to 8123, 8100, and 8080.
+    #[serde(default)]
+    pub ports: Option<Vec<u16>>,
+    /// How often the same IP is checked. Defaults to 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// The maximum number of IPs to check in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeConfig {
+    /// The name that modes use for the probe. The results are stored in
+    /// `services.<name>`, so it can't be `rcon` or `webmap`.
+    pub name: String,
+    /// The payload with escapes like `\r\n` and `\x00`. If neither this nor
+    /// `payload_hex` is set then we send nothing and wait for the server.
+    #[serde(default)]
+    pub payload: Option<String>,
+    /// The payload as hex, like `fefd09`.
+    #[serde(default)]
+    pub payload_hex: Option<String>,
+    /// The ports that are probed on the IPs of online servers.
+    pub ports: Vec<u16>,
+    /// The response is cut off after this many bytes. Defaults to 4096.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// A regex that matches when the response is complete. Without it we
+    /// wait until the connection closes or we get `max_bytes`.
+    #[serde(default)]
+    pub complete: Option<String>,
+    /// The fields that are stored, and the regexes for them. The first
+    /// capture group is used, or the whole match if there isn't one. If none
+    /// of them match then only the port is stored.
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GenericProbesConfig {
+    /// Run the probes from `[[probes]]` against the IPs of online servers.
+    /// One probe is run per scan.
+    pub enabled: bool,
+    /// The names of the probes to run. Defaults to all of them.
+    #[serde(default)]
+    pub probes: Option<Vec<String>>,
+    /// How often the same IP is probed with the same probe. Defaults to 1
+    /// week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// The maximum number of IPs to probe in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GeyserConfig {
+    /// Send a Bedrock ping to the IPs of Java servers to find the ones with
+    /// cross-play through Geyser. Like `[bedrock]`, the source port must also
+    /// be firewalled for UDP.
+    pub enabled: bool,
+    /// The Bedrock ports that are pinged. Defaults to 19132.
+    #[serde(default)]
+    pub ports: Option<Vec<u16>>,
+    /// How often the same IP is pinged. Defaults to 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+    /// Also log in to online servers with a Floodgate handshake that can't be
+    /// decrypted to find the ones with Floodgate, which kicks us for it. Uses
+    /// the username from `[login]` and the same `probe_every_secs`.
+    #[serde(default)]
+    pub floodgate: bool,
+    /// The maximum number of IPs to ping in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// The interface to send and receive on. Defaults to the one with the
+    /// default route.
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// The addresses that we send from, each target gets one of them. They
+    /// have to be routed to the interface but don't have to be assigned to
+    /// it. At least one has to be IPv4. Defaults to the first IPv4 address
+    /// and the public IPv6 address of the interface.
+    #[serde(default)]
+    pub source_ips: Vec<IpAddr>,
+    /// The MAC address that packets are sent to, like `00:11:22:33:44:55`. If
+    /// it's not set, the gateway is asked for it with ARP.
+    #[serde(default)]
+    pub gateway_mac: Option<String>,
+    /// The gateway that's resolved with ARP when `gateway_mac` isn't set.
+    /// Defaults to the default route of the interface.
+    #[serde(default)]
+    pub gateway_ip: Option<Ipv4Addr>,
+    /// The MAC address that IPv6 packets are sent to. If it's not set, the
+    /// IPv6 gateway is asked for it with NDP, and IPv6 targets are skipped if
+    /// it doesn't reply.
+    #[serde(default)]
+    pub gateway_mac_v6: Option<String>,
+    /// The gateway that's resolved with NDP when `gateway_mac_v6` isn't set.
+    /// Defaults to the default IPv6 route of the interface.
+    #[serde(default)]
+    pub gateway_ip_v6: Option<Ipv6Addr>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyProtocolConfig {
+    /// Ping servers that replied with a SYN+ACK but never sent a valid server
+    /// list ping response again with a PROXY protocol header, since servers
+    /// with `proxy-protocol: true` ignore anything without it.
+    pub enabled: bool,
+    /// The version of the PROXY protocol header, 1 or 2. Defaults to 2.
+    #[serde(default)]
+    pub version: Option<u8>,
+    /// The maximum number of targets that are remembered for the next proxy
+    /// protocol scan. Defaults to 1,000,000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TransferConfig {
+    /// Log in to 1.20.5+ servers with the transfer intent to find out whether
+    /// they have `accepts-transfers` on. Uses the username from `[login]`.
+    pub enabled: bool,
+    /// How often the same server is probed. Defaults to 1 week.
+    #[serde(default)]
+    pub probe_every_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Ipv6Config {
+    /// Scan IPv6 targets. This needs a public IPv6 address on the interface,
+    /// and the source port has to be firewalled for IPv6 too.
+    pub enabled: bool,
+    /// The list of IPv6 modes that we'll use. By default, all modes are
+    /// included. Refer to modes/ipv6.rs for a list of modes.
+    #[serde(default)]
+    pub modes: Option<Vec<String>>,
+    /// A file of IPv6 addresses or small prefixes to scan, in the same format
+    /// as the exclude file.
+    #[serde(default)]
+    pub hitlist: Option<PathBuf>,
+    /// The ports that are scanned on every address. Defaults to 25565.
+    #[serde(default)]
+    pub ports: Option<Vec<u16>>,
+    /// How many addresses around known servers are scanned with the `Nearby`
+    /// mode. Defaults to 256.
+    #[serde(default)]
+    pub nearby: Option<u32>,
+    /// The maximum number of hitlist entries or known addresses that are used
+    /// in one scan.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
@@ -15,6 +15,9 @@ pub struct BulkUpdate {
     pub query: Document,
     pub update: Document,
     pub options: Option<UpdateOptions>,
+    /// Update every document that matches the query instead of only the first
+    /// one, like for updating all the servers on an IP.
+    pub multi: bool,
 }
 
 /// Result of a `bulk_update` operation.
@@ -65,7 +68,7 @@ impl<M: Send + Sync> CollectionExt for mongodb::Collection<M> {
             let mut doc = doc! {
                 "q": &u.query,
                 "u": &u.update,
-                "multi": false,
+                "multi": u.multi,
             };
             if let Some(options) = &u.options {
                 if let Some(upsert) = &options.upsert {
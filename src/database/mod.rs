@@ -2,7 +2,7 @@ pub mod bulk_write;
 
 use std::{
     collections::HashSet,
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{IpAddr, SocketAddr, SocketAddrV4},
     sync::Arc,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
@@ -25,13 +25,13 @@ pub struct Database {
 }
 
 pub struct DatabaseSharedData {
-    pub ips_with_same_hash: LruCache<Ipv4Addr, (CachedIpHash, HashSet<u16>)>,
+    pub ips_with_same_hash: LruCache<IpAddr, (CachedIpHash, HashSet<u16>)>,
 
-    pub bad_ips: HashSet<Ipv4Addr>,
+    pub bad_ips: HashSet<IpAddr>,
 
-    cached_all_servers_30_days: Option<(Vec<SocketAddrV4>, Instant)>,
-    cached_all_servers_365_days: Option<(Vec<SocketAddrV4>, Instant)>,
-    cached_all_servers_new: Option<(Vec<SocketAddrV4>, Instant)>,
+    cached_all_servers_30_days: Option<(Vec<SocketAddr>, Instant)>,
+    cached_all_servers_365_days: Option<(Vec<SocketAddr>, Instant)>,
+    cached_all_servers_new: Option<(Vec<SocketAddr>, Instant)>,
 }
 
 pub struct CachedIpHash {
@@ -63,7 +63,7 @@ impl Database {
             .expect("bad servers collection must exist");
         while let Some(Ok(doc)) = cursor.next().await {
             if let Some(Bson::String(ip)) = doc.get("ip") {
-                bad_ips.insert(Ipv4Addr::from_str(ip.as_str())?);
+                bad_ips.insert(IpAddr::from_str(ip.as_str())?);
             }
         }
 
@@ -178,7 +178,11 @@ impl Database {
         self.matscan_database().collection::<Document>("servers")
     }
 
-    pub async fn add_to_bad_ips(self, addr: Ipv4Addr) -> anyhow::Result<()> {
+    pub fn bedrock_servers_coll(&self) -> Collection<Document> {
+        self.matscan_database().collection::<Document>("bedrock_servers")
+    }
+
+    pub async fn add_to_bad_ips(self, addr: IpAddr) -> anyhow::Result<()> {
         self.shared.lock().bad_ips.insert(addr);
 
         self.client
@@ -242,10 +246,27 @@ pub enum CollectServersFilter {
     New,
 }
 
+/// Get the IPv4 servers that match the filter. This is what the modes that
+/// scan around known servers use, since they only work for IPv4.
 pub async fn collect_all_servers(
     database: &Database,
     filter: CollectServersFilter,
 ) -> anyhow::Result<Vec<SocketAddrV4>> {
+    Ok(collect_all_servers_with_ipv6(database, filter)
+        .await?
+        .into_iter()
+        .filter_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(addr),
+            SocketAddr::V6(_) => None,
+        })
+        .collect())
+}
+
+/// Like [`collect_all_servers`], but the IPv6 servers are included.
+pub async fn collect_all_servers_with_ipv6(
+    database: &Database,
+    filter: CollectServersFilter,
+) -> anyhow::Result<Vec<SocketAddr>> {
     let doc_filter: Document = match filter {
         CollectServersFilter::Active30d => {
             if let Some((cached, cached_time)) = &database.shared.lock().cached_all_servers_30_days
@@ -326,7 +347,7 @@ pub async fn collect_all_servers(
         let Some(port) = get_u32(&doc, "port") else {
             continue;
         };
-        servers.push(SocketAddrV4::new(Ipv4Addr::from_str(ip.as_str())?, port as u16));
+        servers.push(SocketAddr::new(IpAddr::from_str(ip.as_str())?, port as u16));
 
         if servers.len() % 10000 == 0 {
             println!("Collected {} servers", servers.len());
@@ -349,3 +370,36 @@ pub async fn collect_all_servers(
 
     Ok(servers)
 }
+
+/// Get the Bedrock servers that replied between `last_ping_ago_max_secs` and
+/// `last_ping_ago_min_secs` ago.
+pub async fn collect_bedrock_servers(
+    database: &Database,
+    last_ping_ago_min_secs: u64,
+    last_ping_ago_max_secs: u64,
+) -> anyhow::Result<Vec<SocketAddr>> {
+    let mut cursor = database
+        .bedrock_servers_coll()
+        .find(doc! {
+            "timestamp": {
+                "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(last_ping_ago_max_secs)),
+                "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(last_ping_ago_min_secs)),
+            }
+        })
+        .projection(doc! {"ip": 1, "port": 1, "_id": 0})
+        .batch_size(2000)
+        .await?;
+
+    let mut servers = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        let Some(Bson::String(ip)) = doc.get("ip") else {
+            continue;
+        };
+        let Some(port) = get_u32(&doc, "port") else {
+            continue;
+        };
+        servers.push(SocketAddr::new(IpAddr::from_str(ip.as_str())?, port as u16));
+    }
+
+    Ok(servers)
+}
@@ -1,12 +1,12 @@
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{net::IpAddr, str::FromStr};
 use std::collections::HashSet;
-use crate::scanner::targets::{Ipv4Range, Ipv4Ranges};
+use crate::scanner::targets::{ip_add, ip_sub, ip_to_u128, IpRange, IpRanges};
 
 use anyhow::anyhow;
 
 // no need to change anything here really
 // it should be interpreted properly anyway
-pub fn parse(input: &HashSet<String>) -> anyhow::Result<Ipv4Ranges> {
+pub fn parse(input: &HashSet<String>) -> anyhow::Result<IpRanges> {
     let mut ranges = Vec::new();
 
     for line in input {
@@ -16,7 +16,8 @@ pub fn parse(input: &HashSet<String>) -> anyhow::Result<Ipv4Ranges> {
             continue;
         }
 
-        // can be either like 0.0.0.0-0.0.0.0 or 0.0.0.0/32
+        // can be either like 0.0.0.0-0.0.0.0 or 0.0.0.0/32 (or the same with
+        // ipv6 addresses)
 
         let is_slash = line.contains('/');
         let is_hypen = line.contains('-');
@@ -37,16 +38,21 @@ pub fn parse(input: &HashSet<String>) -> anyhow::Result<Ipv4Ranges> {
             let ip = parts.next().unwrap();
             let mask = parts.next().unwrap();
 
-            let mask = 32 - mask.parse::<u8>()?;
+            let ip = IpAddr::from_str(ip)?;
+            let bits: u32 = if ip.is_ipv4() { 32 } else { 128 };
 
-            let mask_bits = 2u32.pow(mask as u32) - 1;
+            let mask = mask.parse::<u32>()?;
+            if mask > bits {
+                return Err(anyhow!("Invalid exclude range: {} (prefix is too long)", line));
+            }
+            let mask = bits - mask;
 
-            let ip_u32 = u32::from(Ipv4Addr::from_str(ip)?);
+            let mask_bits = u128::MAX.checked_shr(128 - mask).unwrap_or(0);
 
-            let addr_start = Ipv4Addr::from(ip_u32 & !mask_bits);
-            let addr_end = Ipv4Addr::from(ip_u32 | mask_bits);
+            let addr_start = ip_sub(ip, ip_to_u128(ip) & mask_bits);
+            let addr_end = ip_add(addr_start, mask_bits);
 
-            Ipv4Range {
+            IpRange {
                 start: addr_start,
                 end: addr_end,
             }
@@ -56,9 +62,15 @@ pub fn parse(input: &HashSet<String>) -> anyhow::Result<Ipv4Ranges> {
             let ip_start = parts.next().unwrap();
             let ip_end = parts.next().unwrap();
 
-            let ip_start = Ipv4Addr::from_str(ip_start)?;
-            let ip_end = Ipv4Addr::from_str(ip_end)?;
+            let ip_start = IpAddr::from_str(ip_start.trim())?;
+            let ip_end = IpAddr::from_str(ip_end.trim())?;
 
+            if ip_start.is_ipv4() != ip_end.is_ipv4() {
+                return Err(anyhow!(
+                    "Invalid exclude range: {} (start and end must both be ipv4 or ipv6)",
+                    line
+                ));
+            }
             if ip_start > ip_end {
                 return Err(anyhow!(
                     "Invalid exclude range: {} (start cannot be greater than end)",
@@ -66,16 +78,16 @@ pub fn parse(input: &HashSet<String>) -> anyhow::Result<Ipv4Ranges> {
                 ));
             }
 
-            Ipv4Range {
+            IpRange {
                 start: ip_start,
                 end: ip_end,
             }
         } else {
-            Ipv4Range::single(Ipv4Addr::from_str(line)?)
+            IpRange::single(IpAddr::from_str(line)?)
         };
 
         ranges.push(range);
     }
 
-    Ok(Ipv4Ranges::new(ranges))
+    Ok(IpRanges::new(ranges))
 }
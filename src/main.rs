@@ -1,6 +1,8 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    env, fs, path,
+    collections::{HashMap, HashSet, VecDeque},
+    env, fs, mem,
+    net::SocketAddr,
+    path,
     str::FromStr,
     sync::{atomic::AtomicBool, Arc},
     thread,
@@ -16,11 +18,13 @@ use matscan::{
     config::{Config, RescanConfig},
     database::Database,
     exclude,
-    modes::{ModePicker, ScanMode},
+    modes::{bedrock::BedrockScanMode, ipv6::Ipv6ScanMode, ModePicker, ScanMode},
+    net::{interface::Network, udp::StatelessUdp},
     processing::{process_pings, SharedData},
     scanner::{
         protocols::{self},
-        targets::{Ipv4Range, Ipv4Ranges, ScanRange, ScanRanges},
+        targets::{IpRange, IpRanges, ScanRange, ScanRanges},
+        udp::UdpScannerReceiver,
         ScanSession, Scanner, ScannerReceiver,
     },
     terminal_colors::*,
@@ -55,14 +59,39 @@ async fn main() -> anyhow::Result<()> {
         info!("Prometheus listening on {address}");
     }
 
+    let virtual_hosts = match &config.target.virtual_hosts {
+        Some(path) => {
+            let virtual_hosts = resolve_virtual_hosts(&fs::read_to_string(path)?).await;
+            println!("Resolved {} virtual host addresses", virtual_hosts.len());
+            virtual_hosts
+        }
+        None => HashMap::new(),
+    };
+    // new servers on those addresses are pinged with their hostname too
     let minecraft_protocol = protocols::Minecraft::new(
         &config.target.addr,
         config.target.port,
         config.target.protocol_version,
+        config.target.hostname,
+    )
+    .with_ping(config.target.ping.unwrap_or(true))
+    .with_targets(
+        virtual_hosts
+            .iter()
+            .map(|(&addr, hostname)| {
+                let target = protocols::TargetInfo {
+                    protocol_version: None,
+                    hostname: Some(hostname.clone()),
+                };
+                (addr, target)
+            })
+            .collect(),
     );
 
     let mut database = Database::connect(&config.mongodb_uri).await?;
-    let scanner = Scanner::new(config.source_port, config.fingerprint.clone());
+    let network = Network::from_config(&config.network)?;
+    let (scanner, scanner_reader) =
+        Scanner::new(&network, config.source_port, config.fingerprint.clone());
     let mut mode_picker = ModePicker::default();
 
     // the number of times we've done a scan, used for switching between different
@@ -77,9 +106,13 @@ async fn main() -> anyhow::Result<()> {
 
     // used by the sender loop
     let scanner_seed = scanner.seed;
-    let scanner_writer = scanner.client.write.clone();
+    // only the writer that sends the syns batches them
+    let mut scanner_writer = scanner.write.clone();
+    scanner_writer.set_send_mode(config.send_mode, config.send_batch_size.unwrap_or(256))?;
 
     let has_ended = Arc::new(AtomicBool::new(false));
+    // set for every scan, see the end of the loop for what they're used for
+    let track_silent_targets = Arc::new(AtomicBool::new(false));
 
     // we pick a different mode category each scan
     let mut mode_categories = vec![];
@@ -92,9 +125,77 @@ async fn main() -> anyhow::Result<()> {
     if config.fingerprinting.enabled {
         mode_categories.push(ModeCategory::Fingerprint);
     }
+    if config.legacy.enabled {
+        mode_categories.push(ModeCategory::Legacy);
+    }
+    if config.bedrock.enabled {
+        mode_categories.push(ModeCategory::Bedrock);
+    }
+    if config.query.enabled {
+        mode_categories.push(ModeCategory::Query);
+    }
+    if config.login.enabled {
+        mode_categories.push(ModeCategory::Login);
+    }
+    if config.protocol_range.enabled {
+        mode_categories.push(ModeCategory::ProtocolRange);
+    }
+    if config.forge.enabled {
+        mode_categories.push(ModeCategory::Forge);
+    }
+    if config.rcon.enabled {
+        mode_categories.push(ModeCategory::Rcon);
+    }
+    if config.webmap.enabled {
+        mode_categories.push(ModeCategory::Webmap);
+    }
+
+    let generic_probes = match &config.generic_probes.probes {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                config
+                    .probes
+                    .iter()
+                    .find(|probe| &probe.name == name)
+                    .unwrap_or_else(|| panic!("Invalid probe {name:?} specified in config"))
+            })
+            .map(protocols::GenericProbe::new)
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => config
+            .probes
+            .iter()
+            .map(protocols::GenericProbe::new)
+            .collect::<anyhow::Result<Vec<_>>>()?,
+    };
+    let mut generic_probe_i = 0;
+    if config.generic_probes.enabled && !generic_probes.is_empty() {
+        mode_categories.push(ModeCategory::GenericProbe);
+    }
+    if config.geyser.enabled {
+        mode_categories.push(ModeCategory::Geyser);
+        if config.geyser.floodgate {
+            mode_categories.push(ModeCategory::Floodgate);
+        }
+    }
+    if config.proxy_protocol.enabled {
+        mode_categories.push(ModeCategory::ProxyProtocol);
+    }
+    if config.transfer.enabled {
+        mode_categories.push(ModeCategory::Transfer);
+    }
+    if config.ipv6.enabled {
+        if scanner_writer.source_ipv6().is_some() {
+            mode_categories.push(ModeCategory::Ipv6);
+        } else {
+            println!("IPv6 is enabled in the config but there's no public IPv6 address, ignoring");
+        }
+    }
 
     if mode_categories.is_empty() {
-        println!("Scanner, rescanner, and fingerprinting are all disabled in the config.");
+        println!(
+            "Scanner, rescanner, fingerprinting, legacy, bedrock, query, login, protocol range, forge, rcon, webmap, generic probes, geyser, proxy protocol, transfer, and ipv6 are all disabled in the config."
+        );
         println!("You must enable at least one mode for matscan to function.");
         return Ok(());
     }
@@ -109,6 +210,12 @@ async fn main() -> anyhow::Result<()> {
         // we use the cache to check if someone just joined a server, so this
         // will always stay empty if snipe mode is off
         cached_servers: HashMap::new(),
+        silent_targets: HashSet::new(),
+        known_statuses: HashMap::new(),
+        geyser_targets: HashMap::new(),
+        geyser_answered: HashSet::new(),
+        generic_probe: None,
+        virtual_hosts: virtual_hosts.clone(),
 
         total_new: 0,
         total_new_on_default_port: 0,
@@ -120,15 +227,53 @@ async fn main() -> anyhow::Result<()> {
         mode: None
     }));
 
-    let mut receiver = ScannerReceiver {
-        protocol: protocol.clone(),
-        shared_process_data: shared_process_data.clone(),
-        scanner,
-        has_ended: has_ended.clone(),
+    // every receiver thread gets its own share of the connections
+    let receiver_threads = config.receiver_threads.unwrap_or(1).max(1);
+    let ping_timeout = Duration::from_secs(config.ping_timeout_secs.unwrap_or(60));
+    let retries = config.retries.unwrap_or(0);
+    let retry_spacing = Duration::from_secs(config.retry_spacing_secs.unwrap_or(1));
+    // the last retry can get a response a while after the first one did
+    let duplicate_window = if retries == 0 {
+        Duration::ZERO
+    } else {
+        retry_spacing * retries as u32 + Duration::from_secs(10)
+    };
+    let recv_loop_threads = scanner_reader
+        .fanout(receiver_threads)?
+        .into_iter()
+        .map(|mut scanner_reader| {
+            let mut receiver = ScannerReceiver {
+                protocol: protocol.clone(),
+                shared_process_data: shared_process_data.clone(),
+                scanner: scanner.shard(),
+                has_ended: has_ended.clone(),
+                track_silent_targets: track_silent_targets.clone(),
+                duplicate_window,
+            };
+            thread::spawn(move || receiver.recv_loop(&mut scanner_reader, ping_timeout))
+        })
+        .collect::<Vec<_>>();
+
+    // bedrock and query are over udp so they get their own socket and receiver,
+    // which are only created if they're enabled
+    let bedrock_protocol = protocols::Bedrock::new();
+    let udp_protocol: Arc<RwLock<Box<dyn protocols::UdpProtocol>>> =
+        Arc::new(RwLock::new(Box::new(bedrock_protocol.clone())));
+    let (udp_writer, udp_recv_loop_thread) = if mode_categories.iter().any(|c| c.is_udp()) {
+        let udp = StatelessUdp::new(&network, config.source_port, config.fingerprint.clone());
+        let mut udp_receiver = UdpScannerReceiver {
+            protocol: udp_protocol.clone(),
+            shared_process_data: shared_process_data.clone(),
+            read: udp.read,
+            write: udp.write.clone(),
+            seed: scanner_seed,
+            has_ended: has_ended.clone(),
+        };
+        let udp_recv_loop_thread = thread::spawn(move || udp_receiver.recv_loop());
+        (Some(udp.write), Some(udp_recv_loop_thread))
+    } else {
+        (None, None)
     };
-    let recv_loop_thread = thread::spawn(move || {
-        receiver.recv_loop(Duration::from_secs(config.ping_timeout_secs.unwrap_or(60)))
-    });
 
     let mut processing_task = ProcessingTask::new(shared_process_data.clone(), config.clone());
 
@@ -142,7 +287,53 @@ async fn main() -> anyhow::Result<()> {
             })
             .collect::<Vec<_>>()
     });
+    let bedrock_scan_modes = match &config.bedrock.modes {
+        Some(modes) => modes
+            .iter()
+            .map(|mode| {
+                BedrockScanMode::from_str(mode)
+                    .unwrap_or_else(|_| panic!("Invalid bedrock mode {mode:?} specified in config"))
+            })
+            .collect::<Vec<_>>(),
+        None => BedrockScanMode::iter().collect(),
+    };
+    let mut bedrock_i = 0;
+    let ipv6_scan_modes = match &config.ipv6.modes {
+        Some(modes) => modes
+            .iter()
+            .map(|mode| {
+                Ipv6ScanMode::from_str(mode)
+                    .unwrap_or_else(|_| panic!("Invalid ipv6 mode {mode:?} specified in config"))
+            })
+            .collect::<Vec<_>>(),
+        None => Ipv6ScanMode::iter().collect(),
+    };
+    let mut ipv6_i = 0;
+    // alternates between the error fingerprinting and the proxy fingerprinting
+    let mut fingerprint_i = 0;
 
+    let protocol_range_versions = config
+        .protocol_range
+        .versions
+        .clone()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| matscan::modes::protocol_range::DEFAULT_VERSIONS.to_vec());
+    let mut protocol_range_i = 0;
+
+    // servers that replied with a SYN+ACK but no valid ping, these are pinged
+    // with the 1.4-1.6 ping first and then the beta ping if they still don't
+    // reply
+    let mut legacy_targets = HashSet::<SocketAddr>::new();
+    let mut beta_targets = HashSet::<SocketAddr>::new();
+    let legacy_limit = config.legacy.limit.unwrap_or(1_000_000);
+    // the same servers are also pinged with a proxy protocol header
+    let mut proxy_protocol_targets = HashSet::<SocketAddr>::new();
+    let proxy_protocol_limit = config.proxy_protocol.limit.unwrap_or(1_000_000);
+    let proxy_header_version = match config.proxy_protocol.version.unwrap_or(2) {
+        1 => protocols::ProxyHeaderVersion::V1,
+        2 => protocols::ProxyHeaderVersion::V2,
+        version => panic!("Invalid proxy protocol version {version} specified in config"),
+    };
     loop {
         let start_time = Instant::now();
 
@@ -154,6 +345,7 @@ async fn main() -> anyhow::Result<()> {
         // if the mode is none then that means it's a special mode (either rescanning or
         // fingerprinting)
         let mut mode: Option<ScanMode> = None;
+        let mut is_beta_round = false;
         match mode_category {
             ModeCategory::Normal => {
                 let chosen_mode = mode_picker.pick_mode(scan_modes.clone());
@@ -173,6 +365,7 @@ async fn main() -> anyhow::Result<()> {
                 println!("Chosen mode: rescanning");
 
                 // add the ranges we're rescanning
+                let mut rescan_targets = HashMap::new();
                 for rescan_config in [
                     &config.rescan,
                     &config.rescan2,
@@ -180,13 +373,39 @@ async fn main() -> anyhow::Result<()> {
                     &config.rescan4,
                     &config.rescan5,
                 ] {
-                    maybe_rescan_with_config(&database, &mut ranges, rescan_config).await?;
+                    maybe_rescan_with_config(
+                        &database,
+                        &mut ranges,
+                        &mut rescan_targets,
+                        rescan_config,
+                    )
+                    .await?;
                 }
 
-                *protocol.write() = Box::new(minecraft_protocol.clone());
+                // use the protocol version (and maybe hostname) the servers had last time
+                *protocol.write() = Box::new(minecraft_protocol.with_targets(rescan_targets));
                 processing_task.set_protocol::<protocols::Minecraft>();
             }
+            ModeCategory::Fingerprint if fingerprint_i % 2 == 1 => {
+                fingerprint_i += 1;
+                println!("Chosen mode: fingerprinting (proxies)");
+
+                let mut proxy_ranges = Vec::new();
+                let mut known_statuses = HashMap::new();
+                for (addr, known_status) in
+                    matscan::modes::fingerprint::get_proxy_targets(&database).await?
+                {
+                    proxy_ranges.push(ScanRange::single(addr.ip(), addr.port()));
+                    known_statuses.insert(addr, known_status);
+                }
+                ranges.extend(proxy_ranges);
+                shared_process_data.lock().known_statuses = known_statuses;
+
+                *protocol.write() = Box::new(protocols::MinecraftProxyFingerprinting);
+                processing_task.set_protocol::<protocols::MinecraftProxyFingerprinting>();
+            }
             ModeCategory::Fingerprint => {
+                fingerprint_i += 1;
                 println!("Chosen mode: fingerprinting");
 
                 let mut fingerprint_ranges = Vec::new();
@@ -197,7 +416,7 @@ async fn main() -> anyhow::Result<()> {
                         .into_iter()
                         .collect::<Vec<_>>()
                 {
-                    fingerprint_ranges.push(ScanRange::single(*addr.ip(), addr.port()));
+                    fingerprint_ranges.push(ScanRange::single(addr.ip(), addr.port()));
                     fingerprint_protocol_versions.insert(addr, protocol_version);
                 }
                 ranges.extend(fingerprint_ranges);
@@ -207,10 +426,324 @@ async fn main() -> anyhow::Result<()> {
                 ));
                 processing_task.set_protocol::<protocols::MinecraftFingerprinting>();
             }
+            ModeCategory::Legacy => {
+                // alternate between the two pings, the targets that didn't
+                // reply to the first one get the second one next time
+                let targets = if beta_targets.is_empty() {
+                    println!("Chosen mode: legacy (1.4)");
+                    *protocol.write() = Box::new(protocols::LegacyMinecraft::new(
+                        &config.target.addr,
+                        config.target.port,
+                    ));
+                    processing_task.set_protocol::<protocols::LegacyMinecraft>();
+                    mem::take(&mut legacy_targets)
+                } else {
+                    println!("Chosen mode: legacy (beta)");
+                    is_beta_round = true;
+                    *protocol.write() = Box::new(protocols::BetaMinecraft);
+                    processing_task.set_protocol::<protocols::BetaMinecraft>();
+                    mem::take(&mut beta_targets)
+                };
+                if targets.is_empty() {
+                    println!("No silent servers to ping, skipping");
+                    // so we don't spin if nothing else is enabled
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                ranges.extend(
+                    targets
+                        .into_iter()
+                        .map(|addr| ScanRange::single(addr.ip(), addr.port()))
+                        .collect(),
+                );
+            }
+            ModeCategory::Bedrock => {
+                let chosen_mode = bedrock_scan_modes[bedrock_i % bedrock_scan_modes.len()];
+                bedrock_i += 1;
+
+                println!("Chosen mode: bedrock ({chosen_mode:?})");
+
+                ranges.extend(chosen_mode.get_ranges(&database).await?);
+                *udp_protocol.write() = Box::new(bedrock_protocol.clone());
+                processing_task.set_protocol::<protocols::Bedrock>();
+            }
+            ModeCategory::Query => {
+                println!("Chosen mode: query");
+
+                ranges.extend(
+                    matscan::modes::query::get_ranges(
+                        &database,
+                        config.query.query_every_secs.unwrap_or(60 * 60 * 24),
+                        config.query.limit,
+                    )
+                    .await?,
+                );
+                *udp_protocol.write() = Box::new(protocols::Query);
+                processing_task.set_protocol::<protocols::Query>();
+            }
+            ModeCategory::Login => {
+                println!("Chosen mode: login");
+
+                let mut login_ranges = Vec::new();
+                let mut login_protocol_versions = HashMap::new();
+                for (addr, protocol_version) in
+                    matscan::modes::fingerprint::get_online_addrs_and_protocol_versions(
+                        &database,
+                        "login",
+                        config.login.login_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                    )
+                    .await?
+                {
+                    login_ranges.push(ScanRange::single(addr.ip(), addr.port()));
+                    login_protocol_versions.insert(addr, protocol_version);
+                }
+                ranges.extend(login_ranges);
+
+                *protocol.write() = Box::new(protocols::MinecraftLogin::new(
+                    login_protocol_versions,
+                    config.login.username.as_deref().unwrap_or("matscan"),
+                ));
+                processing_task.set_protocol::<protocols::MinecraftLogin>();
+            }
+            ModeCategory::ProtocolRange => {
+                // go through every version with the status first, and then
+                // again with logins for the servers where that didn't work
+                let candidate =
+                    protocol_range_versions[protocol_range_i % protocol_range_versions.len()];
+                let is_login_cycle = config.protocol_range.login_fallback.unwrap_or(true)
+                    && (protocol_range_i / protocol_range_versions.len()) % 2 == 1;
+                protocol_range_i += 1;
+                let method = if is_login_cycle {
+                    protocols::ProbeMethod::Login
+                } else {
+                    protocols::ProbeMethod::Status
+                };
+
+                println!("Chosen mode: protocol range ({candidate}, {method:?})");
+
+                ranges.extend(
+                    matscan::modes::protocol_range::get_targets(
+                        &database,
+                        candidate,
+                        method,
+                        config
+                            .protocol_range
+                            .probe_every_secs
+                            .unwrap_or(60 * 60 * 24 * 7),
+                        config.protocol_range.limit,
+                    )
+                    .await?
+                    .into_iter()
+                    .map(|addr| ScanRange::single(addr.ip(), addr.port()))
+                    .collect(),
+                );
+
+                *protocol.write() = Box::new(protocols::MinecraftProtocolRange::new(
+                    candidate,
+                    method,
+                    config.login.username.as_deref().unwrap_or("matscan"),
+                ));
+                processing_task.set_protocol::<protocols::MinecraftProtocolRange>();
+            }
+            ModeCategory::Forge => {
+                println!("Chosen mode: forge");
+
+                let mut forge_ranges = Vec::new();
+                let mut forge_targets = HashMap::new();
+                for (addr, target) in matscan::modes::forge::get_targets(
+                    &database,
+                    config.forge.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                    config.forge.limit,
+                )
+                .await?
+                {
+                    forge_ranges.push(ScanRange::single(addr.ip(), addr.port()));
+                    forge_targets.insert(addr, target);
+                }
+                ranges.extend(forge_ranges);
+
+                *protocol.write() = Box::new(protocols::MinecraftForge::new(
+                    forge_targets,
+                    config.login.username.as_deref().unwrap_or("matscan"),
+                ));
+                processing_task.set_protocol::<protocols::MinecraftForge>();
+            }
+            ModeCategory::Rcon => {
+                println!("Chosen mode: rcon");
+
+                ranges.extend(
+                    matscan::modes::services::get_ranges(
+                        &database,
+                        "rcon",
+                        config.rcon.ports.as_deref().unwrap_or(&[25575]),
+                        config.rcon.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                        config.rcon.limit,
+                    )
+                    .await?,
+                );
+                *protocol.write() = Box::new(protocols::Rcon::new());
+                processing_task.set_protocol::<protocols::Rcon>();
+            }
+            ModeCategory::Webmap => {
+                println!("Chosen mode: webmap");
+
+                ranges.extend(
+                    matscan::modes::services::get_ranges(
+                        &database,
+                        "webmap",
+                        config.webmap.ports.as_deref().unwrap_or(&[8123, 8100, 8080]),
+                        config.webmap.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                        config.webmap.limit,
+                    )
+                    .await?,
+                );
+                *protocol.write() = Box::new(protocols::Http);
+                processing_task.set_protocol::<protocols::Http>();
+            }
+            ModeCategory::GenericProbe => {
+                let probe = generic_probes[generic_probe_i % generic_probes.len()].clone();
+                generic_probe_i += 1;
+
+                println!("Chosen mode: generic probe ({})", probe.name);
+
+                ranges.extend(
+                    matscan::modes::services::get_ranges(
+                        &database,
+                        &probe.name,
+                        &probe.ports,
+                        config.generic_probes.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                        config.generic_probes.limit,
+                    )
+                    .await?,
+                );
+                shared_process_data.lock().generic_probe = Some(Arc::new(probe.clone()));
+                *protocol.write() = Box::new(probe);
+                processing_task.set_protocol::<protocols::GenericProbe>();
+            }
+            ModeCategory::Transfer => {
+                println!("Chosen mode: transfer");
+
+                let mut transfer_ranges = Vec::new();
+                let mut transfer_protocol_versions = HashMap::new();
+                for (addr, protocol_version) in
+                    matscan::modes::fingerprint::get_online_addrs_and_protocol_versions(
+                        &database,
+                        "transfer",
+                        config.transfer.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                    )
+                    .await?
+                {
+                    // the transfer intent was added in 1.20.5, older servers
+                    // would just think it's invalid
+                    if !(766..0x40000000).contains(&protocol_version) {
+                        continue;
+                    }
+                    transfer_ranges.push(ScanRange::single(addr.ip(), addr.port()));
+                    transfer_protocol_versions.insert(addr, protocol_version);
+                }
+                ranges.extend(transfer_ranges);
+
+                *protocol.write() = Box::new(protocols::MinecraftTransfer::new(
+                    transfer_protocol_versions,
+                    config.login.username.as_deref().unwrap_or("matscan"),
+                ));
+                processing_task.set_protocol::<protocols::MinecraftTransfer>();
+            }
+            ModeCategory::Floodgate => {
+                println!("Chosen mode: floodgate");
+
+                let mut floodgate_ranges = Vec::new();
+                let mut floodgate_protocol_versions = HashMap::new();
+                for (addr, protocol_version) in
+                    matscan::modes::fingerprint::get_online_addrs_and_protocol_versions(
+                        &database,
+                        "crossplay.floodgateLogin",
+                        config.geyser.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                    )
+                    .await?
+                {
+                    floodgate_ranges.push(ScanRange::single(addr.ip(), addr.port()));
+                    floodgate_protocol_versions.insert(addr, protocol_version);
+                }
+                ranges.extend(floodgate_ranges);
+
+                *protocol.write() = Box::new(protocols::MinecraftFloodgate::new(
+                    floodgate_protocol_versions,
+                    config.login.username.as_deref().unwrap_or("matscan"),
+                ));
+                processing_task.set_protocol::<protocols::MinecraftFloodgate>();
+            }
+            ModeCategory::Ipv6 => {
+                let chosen_mode = ipv6_scan_modes[ipv6_i % ipv6_scan_modes.len()];
+                ipv6_i += 1;
+
+                println!("Chosen mode: ipv6 ({chosen_mode:?})");
+
+                ranges.extend(
+                    chosen_mode
+                        .get_ranges(
+                            &database,
+                            config.ipv6.hitlist.as_deref(),
+                            config.ipv6.ports.as_deref().unwrap_or(&[25565]),
+                            config.ipv6.nearby.unwrap_or(256),
+                            config.ipv6.limit,
+                        )
+                        .await?,
+                );
+                *protocol.write() = Box::new(minecraft_protocol.clone());
+                processing_task.set_protocol::<protocols::Minecraft>();
+            }
+            ModeCategory::ProxyProtocol => {
+                println!("Chosen mode: proxy protocol ({proxy_header_version:?})");
+
+                if proxy_protocol_targets.is_empty() {
+                    println!("No silent servers to ping, skipping");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                ranges.extend(
+                    mem::take(&mut proxy_protocol_targets)
+                        .into_iter()
+                        .map(|addr| ScanRange::single(addr.ip(), addr.port()))
+                        .collect(),
+                );
+                *protocol.write() = Box::new(protocols::MinecraftProxyProtocol::new(
+                    minecraft_protocol.clone(),
+                    proxy_header_version,
+                    &network,
+                    config.source_port,
+                    scanner_seed,
+                ));
+                processing_task.set_protocol::<protocols::MinecraftProxyProtocol>();
+            }
+            ModeCategory::Geyser => {
+                println!("Chosen mode: geyser");
+
+                let (geyser_ranges, geyser_targets) = matscan::modes::geyser::get_ranges(
+                    &database,
+                    config.geyser.ports.as_deref().unwrap_or(&[19132]),
+                    config.geyser.probe_every_secs.unwrap_or(60 * 60 * 24 * 7),
+                    config.geyser.limit,
+                )
+                .await?;
+                ranges.extend(geyser_ranges);
+                shared_process_data.lock().geyser_targets = geyser_targets;
+                *udp_protocol.write() = Box::new(protocols::Geyser::default());
+                processing_task.set_protocol::<protocols::Geyser>();
+            }
         }
 
         shared_process_data.lock().mode = mode;
         shared_process_data.lock().category = Option::from(mode_category);
+        let tracks_silent_targets = match mode_category {
+            ModeCategory::Normal | ModeCategory::Rescan => {
+                config.legacy.enabled || config.proxy_protocol.enabled
+            }
+            // the servers that don't reply to the 1.4 ping get the beta one
+            ModeCategory::Legacy => !is_beta_round,
+            _ => false,
+        };
+        track_silent_targets.store(tracks_silent_targets, std::sync::atomic::Ordering::Relaxed);
         
         let count_before_exclude = ranges.count();
         let exclude_ranges = exclude::parse(&database.get_exclusions().await.unwrap())?;
@@ -221,28 +754,31 @@ async fn main() -> anyhow::Result<()> {
         );
         ranges.apply_exclude(&exclude_ranges);
 
-        let bad_ips = Ipv4Ranges::new(
+        let bad_ips = IpRanges::new(
             database
                 .shared
                 .lock()
                 .bad_ips
                 .clone()
                 .into_iter()
-                .map(Ipv4Range::single)
+                .map(IpRange::single)
                 .collect::<Vec<_>>(),
         );
 
-        let mut default_port_ranges = Vec::new();
-        for excluded_range in ranges.apply_exclude(&bad_ips) {
-            // we still scan port 25565 on bad ips (ips that have the same
-            // server on every port)
-            default_port_ranges.push(ScanRange::single_port(
-                excluded_range.start,
-                excluded_range.end,
-                25565,
-            ));
+        // bad ips are only tracked for java servers
+        if !matches!(mode_category, ModeCategory::Bedrock | ModeCategory::Geyser) {
+            let mut default_port_ranges = Vec::new();
+            for excluded_range in ranges.apply_exclude(&bad_ips) {
+                // we still scan port 25565 on bad ips (ips that have the same
+                // server on every port)
+                default_port_ranges.push(ScanRange::single_port(
+                    excluded_range.start,
+                    excluded_range.end,
+                    25565,
+                ));
+            }
+            ranges.extend(default_port_ranges);
         }
-        ranges.extend(default_port_ranges);
 
         let target_count = ranges.count();
         let range_count = ranges.ranges().len();
@@ -252,18 +788,40 @@ async fn main() -> anyhow::Result<()> {
             count_before_exclude - target_count
         );
 
-        // this just spews out syn packets so it doesn't need to know what protocol
-        // we're using
-        let session = ScanSession::new(ranges);
-        let mut scanner_writer = scanner_writer.clone();
-        let scanner_thread = thread::spawn(move || {
-            session.run(
-                config.rate,
-                &mut scanner_writer,
-                scanner_seed,
-                config.scan_duration_secs.unwrap_or(60 * 5),
-            )
-        });
+        let mut session = ScanSession::new(ranges);
+        let scan_duration_secs = config.scan_duration_secs.unwrap_or(60 * 5);
+        let sender_threads = config.sender_threads.unwrap_or(1).max(1);
+        let scanner_thread = if mode_category.is_udp() {
+            // udp has no handshake, so the payload is sent right away
+            let udp_writer = udp_writer.clone().expect("udp is enabled");
+            let udp_protocol = udp_protocol.clone();
+            thread::spawn(move || {
+                session.run_udp(
+                    config.rate,
+                    udp_writer,
+                    udp_protocol.read().as_ref(),
+                    scanner_seed,
+                    scan_duration_secs,
+                    sender_threads,
+                )
+            })
+        } else {
+            // udp responses aren't deduplicated, so only syns are retried
+            session.retries = retries;
+            session.retry_spacing = retry_spacing;
+            // this just spews out syn packets so it doesn't need to know what protocol
+            // we're using
+            let scanner_writer = scanner_writer.clone();
+            thread::spawn(move || {
+                session.run(
+                    config.rate,
+                    scanner_writer,
+                    scanner_seed,
+                    scan_duration_secs,
+                    sender_threads,
+                )
+            })
+        };
 
         // wait until the scanner thread is done
         while !scanner_thread.is_finished() {
@@ -288,6 +846,42 @@ async fn main() -> anyhow::Result<()> {
         // the thread should've finished by now so it'll join instantly
         let packets_sent = scanner_thread.join().unwrap();
 
+        let silent_targets = mem::take(&mut shared_process_data.lock().silent_targets);
+        match mode_category {
+            ModeCategory::Normal | ModeCategory::Rescan => {
+                for target in silent_targets {
+                    if config.legacy.enabled && legacy_targets.len() < legacy_limit {
+                        legacy_targets.insert(target);
+                    }
+                    if config.proxy_protocol.enabled
+                        && proxy_protocol_targets.len() < proxy_protocol_limit
+                    {
+                        proxy_protocol_targets.insert(target);
+                    }
+                }
+            }
+            ModeCategory::Legacy if !is_beta_round => {
+                // these didn't reply to the 1.4 ping either
+                beta_targets = silent_targets;
+            }
+            ModeCategory::Geyser => {
+                let unanswered = {
+                    let mut shared_process_data = shared_process_data.lock();
+                    let answered = mem::take(&mut shared_process_data.geyser_answered);
+                    mem::take(&mut shared_process_data.geyser_targets)
+                        .into_keys()
+                        .filter(|ip| !answered.contains(ip))
+                        .collect::<Vec<_>>()
+                };
+                matscan::modes::geyser::mark_unanswered(&database, unanswered).await?;
+            }
+            ModeCategory::Fingerprint => {
+                // only the proxy round needs these, and they're replaced then
+                shared_process_data.lock().known_statuses = HashMap::new();
+            }
+            _ => {}
+        }
+
         let mut shared_process_data = shared_process_data.lock();
         process_results(
             &mut shared_process_data,
@@ -304,7 +898,12 @@ async fn main() -> anyhow::Result<()> {
     }
 
     has_ended.store(true, std::sync::atomic::Ordering::Relaxed);
-    recv_loop_thread.join().unwrap();
+    for recv_loop_thread in recv_loop_threads {
+        recv_loop_thread.join().unwrap();
+    }
+    if let Some(udp_recv_loop_thread) = udp_recv_loop_thread {
+        udp_recv_loop_thread.join().unwrap();
+    }
 
     Ok(())
 }
@@ -388,27 +987,62 @@ fn process_results(
     }
 }
 
-/// Get targets to rescan based on the given config and add them to ranges
+/// Resolve the hostnames in a virtual hosts file, which has one `hostname` or
+/// `hostname:port` per line. Hostnames that don't resolve are skipped.
+async fn resolve_virtual_hosts(file: &str) -> HashMap<SocketAddr, String> {
+    let mut virtual_hosts = HashMap::new();
+    for line in file.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hostname, port) = match line.split_once(':') {
+            Some((hostname, port)) => match port.parse::<u16>() {
+                Ok(port) => (hostname, port),
+                Err(_) => {
+                    println!("Invalid port in virtual host {line:?}");
+                    continue;
+                }
+            },
+            None => (line, 25565),
+        };
+        match tokio::net::lookup_host((hostname, port)).await {
+            Ok(addrs) => {
+                for addr in addrs {
+                    virtual_hosts.insert(addr, hostname.to_string());
+                }
+            }
+            Err(err) => println!("Couldn't resolve virtual host {hostname}: {err}"),
+        }
+    }
+    virtual_hosts
+}
+
+/// Get targets to rescan based on the given config and add them to ranges and
+/// targets
 async fn maybe_rescan_with_config(
     database: &Database,
     ranges: &mut ScanRanges,
+    targets: &mut HashMap<SocketAddr, protocols::TargetInfo>,
     rescan: &RescanConfig,
 ) -> anyhow::Result<()> {
     if rescan.enabled {
+        let rescan_targets = matscan::modes::rescan::get_targets(
+            database,
+            &rescan.filter,
+            rescan.rescan_every_secs,
+            rescan.players_online_ago_max_secs,
+            rescan.last_ping_ago_max_secs.unwrap_or(60 * 60 * 2),
+            rescan.limit,
+            rescan.sort,
+        )
+        .await?;
         ranges.extend(
-            matscan::modes::rescan::get_ranges(
-                database,
-                &rescan.filter,
-                rescan.rescan_every_secs,
-                rescan.players_online_ago_max_secs,
-                rescan.last_ping_ago_max_secs.unwrap_or(60 * 60 * 2),
-                rescan.limit,
-                rescan.sort,
-            )
-            .await?
-            .into_iter()
-            .collect::<Vec<_>>(),
+            rescan_targets
+                .iter()
+                .map(|(addr, _)| ScanRange::single(addr.ip(), addr.port()))
+                .collect::<Vec<_>>(),
         );
+        targets.extend(rescan_targets);
     }
     Ok(())
 }
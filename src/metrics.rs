@@ -1,8 +1,10 @@
 use lazy_static::lazy_static;
 use prometheus_exporter::{
     self,
+    prometheus::HistogramVec,
     prometheus::IntCounter,
     prometheus::IntCounterVec,
+    prometheus::register_histogram_vec,
     prometheus::register_int_counter,
     prometheus::register_int_counter_vec
 };
@@ -14,4 +16,45 @@ lazy_static! {
         register_int_counter!("so_matscan_rescanned", "Number of servers rescanned").unwrap();
     pub static ref SERVERS_FINGERPRINTED_COUNTER: IntCounter =
         register_int_counter!("so_matscan_fingerprint", "Number of servers fingerprinted").unwrap();
+    pub static ref SERVERS_LEGACY_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_legacy", "Number of servers found with a legacy ping").unwrap();
+    pub static ref SERVERS_BEDROCK_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_bedrock", "Number of Bedrock Edition servers pinged").unwrap();
+    pub static ref SERVERS_QUERIED_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_query", "Number of servers that replied to a query").unwrap();
+    pub static ref SERVERS_LOGIN_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_login", "Number of servers probed with a login").unwrap();
+    pub static ref SERVERS_PROTOCOL_RANGE_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_protocol_range", "Number of servers probed with another protocol version").unwrap();
+    pub static ref SERVERS_FORGE_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_forge", "Number of modded servers that we got the mod list from").unwrap();
+    pub static ref SERVERS_RCON_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_rcon", "Number of IPs found with RCON exposed").unwrap();
+    pub static ref SERVERS_WEBMAP_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_webmap", "Number of web maps found on the IPs of servers").unwrap();
+    pub static ref SERVERS_PROBED_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_generic_probe", "Number of IPs that matched a probe from the config").unwrap();
+    pub static ref SERVERS_GEYSER_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_geyser", "Number of IPs with a Java server that replied to a Bedrock ping").unwrap();
+    pub static ref SERVERS_FLOODGATE_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_floodgate", "Number of servers probed for Floodgate").unwrap();
+    pub static ref SERVERS_PROXY_PROTOCOL_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_proxy_protocol", "Number of servers that only replied with a PROXY protocol header").unwrap();
+    pub static ref SERVERS_TRANSFER_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_transfer", "Number of servers probed with the transfer intent").unwrap();
+    pub static ref SERVERS_IPV6_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_ipv6", "Number of servers found with the IPv6 strategies").unwrap();
+    pub static ref PACKETS_RECEIVED_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_kernel_received", "Number of packets that reached the receiving socket").unwrap();
+    pub static ref PACKETS_DROPPED_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_kernel_drops", "Number of received packets that the kernel dropped because the ring was full").unwrap();
+    pub static ref PACKETS_TRUNCATED_COUNTER: IntCounter =
+        register_int_counter!("so_matscan_truncated", "Number of received packets that were bigger than an RX ring frame, usually because of GRO").unwrap();
+    pub static ref LATENCY_HISTOGRAM: HistogramVec =
+        register_histogram_vec!(
+            "so_matscan_latency_ms",
+            "Round-trip time to servers in milliseconds",
+            &["kind"],
+            vec![5., 10., 25., 50., 100., 150., 250., 500., 1000., 2500., 5000.]
+        ).unwrap();
 }
\ No newline at end of file
@@ -6,8 +6,15 @@ use crate::{database::Database, scanner::targets::ScanRange};
 
 use self::rescan::Sort;
 
+pub mod bedrock;
 pub mod fingerprint;
+pub mod forge;
+pub mod geyser;
+pub mod ipv6;
+pub mod protocol_range;
+pub mod query;
 pub mod rescan;
+pub mod services;
 pub mod slash0;
 pub mod slash0_few_ports;
 pub mod slash0_filtered_by_asn;
@@ -36,6 +43,30 @@ pub enum ModeCategory {
     Normal,
     Rescan,
     Fingerprint,
+    Legacy,
+    Bedrock,
+    Query,
+    Login,
+    ProtocolRange,
+    Forge,
+    Rcon,
+    Webmap,
+    GenericProbe,
+    Geyser,
+    Floodgate,
+    ProxyProtocol,
+    Transfer,
+    Ipv6,
+}
+
+impl ModeCategory {
+    /// Whether the category's protocol is sent over UDP instead of TCP.
+    pub fn is_udp(&self) -> bool {
+        matches!(
+            self,
+            ModeCategory::Bedrock | ModeCategory::Query | ModeCategory::Geyser
+        )
+    }
 }
 
 #[derive(
@@ -0,0 +1,79 @@
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use rand::{seq::IteratorRandom, thread_rng};
+
+use crate::{
+    database::{self, Database},
+    scanner::targets::ScanRange,
+};
+
+use super::slash24::to_ranges;
+
+/// The default port for Bedrock Edition servers.
+pub const DEFAULT_PORT: u16 = 19132;
+
+/// Modes for scanning Bedrock Edition servers over UDP. These are kept separate
+/// from [`ScanMode`](super::ScanMode) since they use a different protocol and
+/// collection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, enum_utils::FromStr, enum_utils::IterVariants)]
+pub enum BedrockScanMode {
+    /// Scan the world on the default port.
+    Slash0,
+    /// Scan the /24s around known IPv4 servers, on the ports that were seen
+    /// there.
+    Slash24,
+    /// Ping known servers that weren't updated in the past hour.
+    Rescan,
+}
+
+impl BedrockScanMode {
+    pub async fn get_ranges(&self, database: &Database) -> anyhow::Result<Vec<ScanRange>> {
+        match self {
+            BedrockScanMode::Slash0 => Ok(vec![ScanRange::single_port(
+                Ipv4Addr::new(0, 0, 0, 0),
+                Ipv4Addr::new(255, 255, 255, 255),
+                DEFAULT_PORT,
+            )]),
+            BedrockScanMode::Slash24 => {
+                // /24s only make sense for ipv4
+                let known_servers =
+                    database::collect_bedrock_servers(database, 0, 60 * 60 * 24 * 30)
+                        .await?
+                        .into_iter()
+                        .filter_map(|addr| match addr {
+                            SocketAddr::V4(addr) => Some(addr),
+                            SocketAddr::V6(_) => None,
+                        })
+                        .collect::<Vec<_>>();
+                let ranges = to_ranges(&known_servers);
+
+                let mut rng = thread_rng();
+                let mut target_ranges = Vec::new();
+                for (range_prefix, group) in ranges.into_iter().choose_multiple(&mut rng, 1024) {
+                    let mut ports = group.ports.into_iter().collect::<HashSet<_>>();
+                    ports.insert(DEFAULT_PORT);
+                    for port in ports {
+                        target_ranges.push(ScanRange::single_port(
+                            Ipv4Addr::new(range_prefix.0, range_prefix.1, range_prefix.2, 0),
+                            Ipv4Addr::new(range_prefix.0, range_prefix.1, range_prefix.2, 255),
+                            port,
+                        ));
+                    }
+                }
+                Ok(target_ranges)
+            }
+            BedrockScanMode::Rescan => {
+                let known_servers =
+                    database::collect_bedrock_servers(database, 60 * 60, 60 * 60 * 24 * 30)
+                        .await?;
+                Ok(known_servers
+                    .into_iter()
+                    .map(|addr| ScanRange::single(addr.ip(), addr.port()))
+                    .collect())
+            }
+        }
+    }
+}
@@ -1,16 +1,31 @@
 use std::{
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{IpAddr, SocketAddr},
     time::{Duration, SystemTime},
 };
 use std::str::FromStr;
 use bson::{doc, Bson, Document};
 use futures_util::StreamExt;
 
-use crate::database::{self, Database};
+use crate::{
+    database::{self, Database},
+    processing::minecraft_fingerprinting::KnownStatus,
+};
 
 pub async fn get_addrs_and_protocol_versions(
     database: &Database,
-) -> anyhow::Result<Vec<(SocketAddrV4, i32)>> {
+) -> anyhow::Result<Vec<(SocketAddr, i32)>> {
+    // the last active fingerprint must've been over a week ago
+    get_online_addrs_and_protocol_versions(database, "fingerprint.active", 60 * 60 * 24 * 7).await
+}
+
+/// Get the servers that are online and weren't probed in the past
+/// `probe_every_secs`, along with their protocol version. `probe_field` is the
+/// document that has the `timestamp` of the last probe.
+pub async fn get_online_addrs_and_protocol_versions(
+    database: &Database,
+    probe_field: &str,
+    probe_every_secs: u64,
+) -> anyhow::Result<Vec<(SocketAddr, i32)>> {
     let mut results = Vec::new();
 
     let filter = doc! {
@@ -20,12 +35,11 @@ pub async fn get_addrs_and_protocol_versions(
         },
         "$or": [
             {
-                "fingerprint.active.timestamp": {
-                    // the last active fingerprint must've been over a week ago
-                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 7)),
+                format!("{probe_field}.timestamp"): {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(probe_every_secs)),
                 }
             },
-            { "fingerprint.active": { "$exists": false } },
+            { probe_field: { "$exists": false } },
         ]
     };
 
@@ -54,8 +68,8 @@ pub async fn get_addrs_and_protocol_versions(
                 };
                 let protocol_version = version.get_i32("protocol").unwrap_or(47);
 
-                let addr = Ipv4Addr::from_str(ip.as_str())?;
-                results.push((SocketAddrV4::new(addr, port as u16), protocol_version));
+                let addr = IpAddr::from_str(ip.as_str())?;
+                results.push((SocketAddr::new(addr, port as u16), protocol_version));
                 if results.len() % 1000 == 0 {
                     //println!("{} ips", results.len());
                 }
@@ -65,3 +79,66 @@ pub async fn get_addrs_and_protocol_versions(
 
     Ok(results)
 }
+
+/// Get the servers that are online and weren't checked for being a proxy in
+/// the past week, along with what their status said.
+pub async fn get_proxy_targets(
+    database: &Database,
+) -> anyhow::Result<Vec<(SocketAddr, KnownStatus)>> {
+    let mut results = Vec::new();
+
+    let filter = doc! {
+        "timestamp": {
+            // must be online
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 2)),
+        },
+        "$or": [
+            {
+                "fingerprint.active.proxy.timestamp": {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 7)),
+                }
+            },
+            { "fingerprint.active.proxy.timestamp": { "$exists": false } },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    pipeline.push(doc! { "$project": {
+        "ip": 1,
+        "port": 1,
+        "minecraft.version.name": 1,
+        "minecraft.isForge": 1,
+        "_id": 0
+    } });
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("ip") else {
+            continue;
+        };
+        let Some(port) = database::get_u32(&doc, "port") else {
+            continue;
+        };
+        let minecraft = doc.get_document("minecraft").ok();
+        let known_status = KnownStatus {
+            version_name: minecraft
+                .and_then(|m| m.get_document("version").ok())
+                .and_then(|v| v.get_str("name").ok())
+                .unwrap_or_default()
+                .to_string(),
+            is_forge: minecraft
+                .and_then(|m| m.get_bool("isForge").ok())
+                .unwrap_or_default(),
+        };
+
+        let addr = IpAddr::from_str(ip.as_str())?;
+        results.push((SocketAddr::new(addr, port as u16), known_status));
+    }
+
+    Ok(results)
+}
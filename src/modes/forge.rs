@@ -0,0 +1,102 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use bson::{doc, Bson, Document};
+use futures_util::StreamExt;
+
+use crate::{
+    database::{self, Database},
+    scanner::protocols::ForgeTarget,
+};
+
+/// Get the modded servers that are online and whose status didn't have the
+/// whole mod list, and that we didn't log in to in the past
+/// `probe_every_secs`.
+pub async fn get_targets(
+    database: &Database,
+    probe_every_secs: u64,
+    limit: Option<usize>,
+) -> anyhow::Result<Vec<(SocketAddr, ForgeTarget)>> {
+    let mut results = Vec::new();
+
+    let filter = doc! {
+        "timestamp": {
+            // must be online
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 2)),
+        },
+        "$and": [
+            {
+                "$or": [
+                    { "minecraft.forgeData.truncated": true },
+                    // newer neoforge versions don't have forgeData
+                    { "minecraft.isModded": true, "minecraft.isForge": false },
+                ]
+            },
+            {
+                "$or": [
+                    {
+                        "minecraft.forgeData.modsTimestamp": {
+                            "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(probe_every_secs)),
+                        }
+                    },
+                    { "minecraft.forgeData.modsTimestamp": { "$exists": false } },
+                ]
+            },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    pipeline.push(doc! { "$project": {
+        "ip": 1,
+        "port": 1,
+        "minecraft.version.protocol": 1,
+        "minecraft.forgeData.fmlNetworkVersion": 1,
+        "_id": 0
+    } });
+    if let Some(limit) = limit {
+        pipeline.push(doc! { "$sample": { "size": limit as i64 } });
+    }
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("ip") else {
+            continue;
+        };
+        let Some(port) = database::get_u32(&doc, "port") else {
+            continue;
+        };
+        let Ok(minecraft) = doc.get_document("minecraft") else {
+            continue;
+        };
+        let Some(protocol_version) = minecraft
+            .get_document("version")
+            .ok()
+            .and_then(|v| database::get_i32(v, "protocol"))
+        else {
+            continue;
+        };
+        let fml_network_version = minecraft
+            .get_document("forgeData")
+            .ok()
+            .and_then(|f| database::get_i32(f, "fmlNetworkVersion"));
+
+        let addr = IpAddr::from_str(ip.as_str())?;
+        results.push((
+            SocketAddr::new(addr, port as u16),
+            ForgeTarget {
+                protocol_version,
+                fml_network_version,
+            },
+        ));
+    }
+
+    Ok(results)
+}
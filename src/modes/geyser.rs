@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use bson::{doc, Bson, Document};
+use futures_util::StreamExt;
+
+use crate::{database::Database, scanner::targets::ScanRange};
+
+/// Get the IPs with a Java server that was online in the past 30 days and that
+/// we didn't ping the Bedrock ports of in the past `probe_every_secs`, on every
+/// port in `ports`.
+///
+/// The clean descriptions of the Java servers on each IP are returned too, so
+/// the processing can tell Geyser apart from a Bedrock server that just runs on
+/// the same host.
+pub async fn get_ranges(
+    database: &Database,
+    ports: &[u16],
+    probe_every_secs: u64,
+    limit: Option<usize>,
+) -> anyhow::Result<(Vec<ScanRange>, HashMap<IpAddr, Vec<String>>)> {
+    let filter = doc! {
+        "timestamp": {
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 30)),
+        },
+        "$or": [
+            {
+                "crossplay.checkedAt": {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(probe_every_secs)),
+                }
+            },
+            { "crossplay.checkedAt": { "$exists": false } },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    // there can be lots of servers on one ip
+    pipeline.push(doc! { "$group": {
+        "_id": "$ip",
+        "descriptions": { "$addToSet": "$minecraft.cleanDescription" },
+    } });
+    if let Some(limit) = limit {
+        pipeline.push(doc! { "$sample": { "size": limit as i64 } });
+    }
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    let mut ranges = Vec::new();
+    let mut descriptions = HashMap::new();
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("_id") else {
+            continue;
+        };
+        let addr = IpAddr::from_str(ip.as_str())?;
+        for &port in ports {
+            ranges.push(ScanRange::single(addr, port));
+        }
+        let ip_descriptions = doc
+            .get_array("descriptions")
+            .map(|d| {
+                d.iter()
+                    .filter_map(|d| d.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default();
+        descriptions.insert(addr, ip_descriptions);
+    }
+
+    Ok((ranges, descriptions))
+}
+
+/// Mark the servers on the IPs that didn't reply to any of the Bedrock pings
+/// as not having Geyser. The Bedrock server we saw on them before is removed
+/// since it's gone now.
+pub async fn mark_unanswered(database: &Database, ips: Vec<IpAddr>) -> anyhow::Result<()> {
+    let ips = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+    let now = bson::DateTime::from(SystemTime::now());
+    // so the filter doesn't get too big
+    for ips in ips.chunks(10_000) {
+        database
+            .servers_coll()
+            .update_many(
+                doc! { "ip": { "$in": ips } },
+                doc! {
+                    "$set": {
+                        "crossplay.geyser": false,
+                        "crossplay.checkedAt": now,
+                    },
+                    "$unset": {
+                        "crossplay.bedrock": "",
+                        "crossplay.bedrockOnSameHost": "",
+                    },
+                },
+            )
+            .await?;
+    }
+    Ok(())
+}
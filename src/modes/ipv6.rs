@@ -0,0 +1,162 @@
+use std::{
+    collections::HashSet,
+    net::{Ipv6Addr, SocketAddr},
+    path::Path,
+};
+
+use rand::seq::IteratorRandom;
+
+use crate::{
+    database::{self, CollectServersFilter, Database},
+    exclude,
+    scanner::targets::ScanRange,
+};
+
+/// Prefixes in the hitlist with more addresses than this are skipped, since
+/// there's no point in scanning a whole /64.
+const MAX_HITLIST_RANGE_SIZE: usize = 65536;
+
+/// Ways of finding IPv6 servers. We can't go through all of IPv6 like we do
+/// with IPv4, so the targets come from addresses that we know are in use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, enum_utils::FromStr, enum_utils::IterVariants)]
+pub enum Ipv6ScanMode {
+    /// The addresses (or small prefixes) in the hitlist file.
+    Hitlist,
+    /// The IPv6 addresses of servers we already know, on the configured
+    /// ports.
+    Known,
+    /// The addresses next to known IPv6 servers and the first addresses of
+    /// their /64s, since hosts usually hand them out in order.
+    Nearby,
+}
+
+impl Ipv6ScanMode {
+    pub async fn get_ranges(
+        &self,
+        database: &Database,
+        hitlist: Option<&Path>,
+        ports: &[u16],
+        nearby: u32,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<ScanRange>> {
+        let mut ranges = Vec::new();
+        match self {
+            Ipv6ScanMode::Hitlist => {
+                let Some(hitlist) = hitlist else {
+                    println!("No IPv6 hitlist in the config, skipping");
+                    return Ok(vec![]);
+                };
+                // same format as the exclusions
+                let lines = std::fs::read_to_string(hitlist)?
+                    .lines()
+                    .map(|line| line.to_string())
+                    .collect::<HashSet<_>>();
+                let hitlist = exclude::parse(&lines)?;
+
+                let mut hitlist_ranges = Vec::new();
+                for range in hitlist.ranges() {
+                    let range = ScanRange::single_port(range.start, range.end, 0);
+                    if range.count_addresses() > MAX_HITLIST_RANGE_SIZE {
+                        println!("Skipping {range:?} from the hitlist since it's too big");
+                        continue;
+                    }
+                    hitlist_ranges.push(range);
+                }
+                for range in choose(hitlist_ranges, limit) {
+                    for &port in ports {
+                        ranges.push(ScanRange {
+                            port_start: port,
+                            port_end: port,
+                            ..range.clone()
+                        });
+                    }
+                }
+            }
+            Ipv6ScanMode::Known => {
+                for address in choose(known_ipv6_addrs(database).await?, limit) {
+                    for &port in ports {
+                        ranges.push(ScanRange::single(address, port));
+                    }
+                }
+            }
+            Ipv6ScanMode::Nearby => {
+                let mut intervals = Vec::new();
+                for address in choose(known_ipv6_addrs(database).await?, limit) {
+                    intervals.extend(nearby_intervals(address, nearby));
+                }
+                for (start, end) in merge_intervals(intervals) {
+                    for &port in ports {
+                        ranges.push(ScanRange::single_port(
+                            Ipv6Addr::from(start),
+                            Ipv6Addr::from(end),
+                            port,
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(ranges)
+    }
+}
+
+/// The unique IPv6 addresses of servers that were online in the past year.
+async fn known_ipv6_addrs(database: &Database) -> anyhow::Result<Vec<Ipv6Addr>> {
+    let known_servers =
+        database::collect_all_servers_with_ipv6(database, CollectServersFilter::Active365d).await?;
+    let known_ips = known_servers
+        .iter()
+        .filter_map(|target| match target {
+            SocketAddr::V6(target) => Some(*target.ip()),
+            SocketAddr::V4(_) => None,
+        })
+        .collect::<HashSet<_>>();
+    println!("Found {} unique IPv6 addresses", known_ips.len());
+    Ok(known_ips.into_iter().collect())
+}
+
+fn choose<T>(items: Vec<T>, limit: Option<usize>) -> Vec<T> {
+    match limit {
+        Some(limit) => items
+            .into_iter()
+            .choose_multiple(&mut rand::thread_rng(), limit),
+        None => items,
+    }
+}
+
+/// The addresses within `nearby` of the address, and the first `nearby`
+/// addresses of its /64. These never leave the /64.
+fn nearby_intervals(address: Ipv6Addr, nearby: u32) -> Vec<(u128, u128)> {
+    if nearby == 0 {
+        return vec![];
+    }
+    let bits = u128::from(address);
+    let prefix = bits & !(u64::MAX as u128);
+    let prefix_end = prefix | u64::MAX as u128;
+    let nearby = nearby as u128;
+
+    vec![
+        (
+            bits.saturating_sub(nearby).max(prefix),
+            bits.saturating_add(nearby).min(prefix_end),
+        ),
+        // ::0 is the subnet-router anycast address so it's skipped
+        (prefix + 1, prefix + nearby),
+    ]
+}
+
+/// Merge overlapping intervals so the same address isn't scanned twice.
+fn merge_intervals(mut intervals: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    intervals.sort();
+    let mut merged: Vec<(u128, u128)> = Vec::new();
+    for (start, end) in intervals {
+        if let Some(last) = merged.last_mut() {
+            if start <= last.1.saturating_add(1) {
+                last.1 = last.1.max(end);
+                continue;
+            }
+        }
+        merged.push((start, end));
+    }
+    merged
+}
@@ -0,0 +1,152 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime},
+};
+use std::str::FromStr;
+use bson::{doc, Bson, Document};
+use futures_util::StreamExt;
+
+use crate::{
+    database::{self, Database},
+    scanner::protocols::ProbeMethod,
+};
+
+/// The protocol versions we try by default, which is the last release of most
+/// major versions since 1.7.
+pub const DEFAULT_VERSIONS: &[i32] = &[
+    5,   // 1.7.10
+    47,  // 1.8.9
+    110, // 1.9.4
+    210, // 1.10.2
+    316, // 1.11.2
+    340, // 1.12.2
+    404, // 1.13.2
+    498, // 1.14.4
+    578, // 1.15.2
+    754, // 1.16.5
+    756, // 1.17.1
+    758, // 1.18.2
+    760, // 1.19.2
+    762, // 1.19.4
+    763, // 1.20.1
+    765, // 1.20.4
+    766, // 1.20.6
+    767, // 1.21.1
+    769, // 1.21.4
+    772, // 1.21.8
+];
+
+/// The number of status probes that have to come back with the server's own
+/// version before we start trying to log in instead.
+const STATUS_PROBES_BEFORE_LOGIN: i32 = 3;
+
+/// The field with when the server was last probed with the candidate and
+/// method, like `minecraft.supportedProtocols.probed.status.47`.
+pub fn probed_field(candidate: i32, method: ProbeMethod) -> String {
+    let method = match method {
+        ProbeMethod::Status => "status",
+        ProbeMethod::Login => "login",
+    };
+    format!("minecraft.supportedProtocols.probed.{method}.{candidate}")
+}
+
+/// Get the servers that are online and that we should probe with the
+/// `candidate` protocol version using `method`.
+///
+/// Servers that we already know support the candidate or that we probed with
+/// it in the past `probe_every_secs` are skipped. Logins are only done for
+/// servers whose status never changed with the protocol version we sent,
+/// since either they only support one version or they don't tell us in the
+/// status.
+pub async fn get_targets(
+    database: &Database,
+    candidate: i32,
+    method: ProbeMethod,
+    probe_every_secs: u64,
+    limit: Option<usize>,
+) -> anyhow::Result<Vec<SocketAddr>> {
+    let mut results = Vec::new();
+
+    let probed_field = probed_field(candidate, method);
+    let filter = doc! {
+        "timestamp": {
+            // must be online
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 2)),
+        },
+        "minecraft.version.protocol": { "$exists": true },
+        "$or": [
+            {
+                &probed_field: {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(probe_every_secs)),
+                }
+            },
+            { &probed_field: { "$exists": false } },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    pipeline.push(doc! { "$project": {
+        "ip": 1,
+        "port": 1,
+        "minecraft.version.protocol": 1,
+        "minecraft.supportedProtocols": 1,
+        "_id": 0
+    } });
+    if let Some(limit) = limit {
+        pipeline.push(doc! { "$sample": { "size": limit as i64 } });
+    }
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("ip") else {
+            continue;
+        };
+        let Some(port) = database::get_u32(&doc, "port") else {
+            continue;
+        };
+        let Ok(minecraft) = doc.get_document("minecraft") else {
+            continue;
+        };
+        let Some(protocol_version) = minecraft
+            .get_document("version")
+            .ok()
+            .and_then(|v| database::get_i32(v, "protocol"))
+        else {
+            continue;
+        };
+        if protocol_version == candidate {
+            continue;
+        }
+
+        let supported = minecraft.get_document("supportedProtocols").ok();
+        let min = supported.and_then(|s| database::get_i32(s, "min"));
+        let max = supported.and_then(|s| database::get_i32(s, "max"));
+        let status_probes = supported
+            .and_then(|s| database::get_i32(s, "statusProbes"))
+            .unwrap_or_default();
+
+        if let (Some(min), Some(max)) = (min, max) {
+            if (min..=max).contains(&candidate) {
+                // already know it's supported
+                continue;
+            }
+        }
+
+        let status_never_differed = min.is_none_or(|min| Some(min) == max);
+        if method == ProbeMethod::Login
+            && !(status_never_differed && status_probes >= STATUS_PROBES_BEFORE_LOGIN)
+        {
+            continue;
+        }
+
+        let addr = IpAddr::from_str(ip.as_str())?;
+        results.push(SocketAddr::new(addr, port as u16));
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,83 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+use std::str::FromStr;
+use bson::{doc, Bson, Document};
+use futures_util::StreamExt;
+
+use crate::{
+    database::{self, Database},
+    scanner::targets::ScanRange,
+};
+
+/// Get servers that are online but haven't been queried in the past
+/// `query_every_secs`.
+///
+/// We send the query to the same port as the server, since that's the default
+/// for `query.port`.
+///
+/// The servers are marked with `query.enabled: false` right away, since most
+/// of them never reply. The processing sets it back to true if they do.
+pub async fn get_ranges(
+    database: &Database,
+    query_every_secs: u64,
+    limit: Option<usize>,
+) -> anyhow::Result<Vec<ScanRange>> {
+    let mut ranges = Vec::new();
+
+    let filter = doc! {
+        "timestamp": {
+            // must be online
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 2)),
+        },
+        "$or": [
+            {
+                "query.timestamp": {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(query_every_secs)),
+                }
+            },
+            { "query": { "$exists": false } },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    pipeline.push(doc! { "$project": { "ip": 1, "port": 1, "_id": 1 } });
+    if let Some(limit) = limit {
+        pipeline.push(doc! { "$sample": { "size": limit as i64 } });
+    }
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    let mut ids = Vec::new();
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("ip") else {
+            continue;
+        };
+        let Some(port) = database::get_u32(&doc, "port") else {
+            continue;
+        };
+        ranges.push(ScanRange::single(IpAddr::from_str(ip.as_str())?, port as u16));
+        if let Some(id) = doc.get("_id") {
+            ids.push(id.clone());
+        }
+    }
+
+    // the rest of what we got from the last query is kept
+    let now = bson::DateTime::from(SystemTime::now());
+    for ids in ids.chunks(10_000) {
+        database
+            .servers_coll()
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$set": { "query.enabled": false, "query.timestamp": now } },
+            )
+            .await?;
+    }
+
+    Ok(ranges)
+}
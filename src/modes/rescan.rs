@@ -1,5 +1,5 @@
 use std::{
-    net::Ipv4Addr,
+    net::{IpAddr, SocketAddr},
     time::{Duration, SystemTime},
 };
 use std::str::FromStr;
@@ -10,7 +10,7 @@ use tracing::warn;
 
 use crate::{
     database::{self, Database},
-    scanner::targets::ScanRange,
+    scanner::{protocols::TargetInfo, targets::ScanRange},
 };
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -29,7 +29,33 @@ pub async fn get_ranges(
     limit: Option<usize>,
     sort: Option<Sort>,
 ) -> anyhow::Result<Vec<ScanRange>> {
-    let mut ranges = Vec::new();
+    Ok(get_targets(
+        database,
+        extra_filter,
+        rescan_every_secs,
+        players_online_ago_max_secs,
+        last_ping_ago_max_secs,
+        limit,
+        sort,
+    )
+    .await?
+    .into_iter()
+    .map(|(addr, _)| ScanRange::single(addr.ip(), addr.port()))
+    .collect())
+}
+
+/// Like [`get_ranges`], but also returns what we know about every server so
+/// the handshake can match it.
+pub async fn get_targets(
+    database: &Database,
+    extra_filter: &toml::Table,
+    rescan_every_secs: u64,
+    players_online_ago_max_secs: Option<u64>,
+    last_ping_ago_max_secs: u64,
+    limit: Option<usize>,
+    sort: Option<Sort>,
+) -> anyhow::Result<Vec<(SocketAddr, TargetInfo)>> {
+    let mut targets = Vec::new();
 
     let mut filter = doc! {
         "timestamp": {
@@ -57,7 +83,9 @@ pub async fn get_ranges(
 
     let mut pipeline: Vec<Document> = Vec::new();
     pipeline.push(doc! { "$match": filter });
-    pipeline.push(doc! { "$project": { "ip": 1, "port": 1, "_id": 0 } });
+    pipeline.push(doc! {
+        "$project": { "ip": 1, "port": 1, "minecraft.version.protocol": 1, "hostname": 1, "_id": 0 }
+    });
 
     let sort = sort.unwrap_or(Sort::Oldest);
 
@@ -91,7 +119,7 @@ pub async fn get_ranges(
         };
 
         // there shouldn't be any bad ips...
-        let addr = Ipv4Addr::from_str(ip.as_str())?;
+        let addr = IpAddr::from_str(ip.as_str())?;
         if bad_ips.contains(&addr) && port != 25565 {
             println!("Found {addr} in bad IPs when it shouldn't be, deleting it");
             database
@@ -109,11 +137,24 @@ pub async fn get_ranges(
             continue;
         }
 
-        ranges.push(ScanRange::single(addr, port as u16));
-        if ranges.len() % 1000 == 0 {
-            //println!("{} ips", ranges.len());
+        let protocol_version = doc
+            .get_document("minecraft")
+            .and_then(|m| m.get_document("version"))
+            .ok()
+            .and_then(|v| database::get_i32(v, "protocol"));
+        let hostname = doc.get_str("hostname").ok().map(str::to_string);
+
+        targets.push((
+            SocketAddr::new(addr, port as u16),
+            TargetInfo {
+                protocol_version,
+                hostname,
+            },
+        ));
+        if targets.len() % 1000 == 0 {
+            //println!("{} ips", targets.len());
         }
     }
 
-    Ok(ranges)
+    Ok(targets)
 }
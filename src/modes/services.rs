@@ -0,0 +1,91 @@
+use std::{
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use bson::{doc, Bson, Document};
+use futures_util::StreamExt;
+
+use crate::{database::Database, scanner::targets::ScanRange};
+
+/// Get the IPs of servers that are online and that we didn't check for the
+/// service (like `rcon`) in the past `probe_every_secs`, on every port in
+/// `ports`.
+///
+/// The IPs are marked as checked with `services.<service>.open: false` right
+/// away, since closed ports never reply and so never get processed. The
+/// processing sets it back to true if the service is there.
+pub async fn get_ranges(
+    database: &Database,
+    service: &str,
+    ports: &[u16],
+    probe_every_secs: u64,
+    limit: Option<usize>,
+) -> anyhow::Result<Vec<ScanRange>> {
+    let mut ranges = Vec::new();
+
+    let filter = doc! {
+        "timestamp": {
+            // must be online
+            "$gt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(60 * 60 * 2)),
+        },
+        "$or": [
+            {
+                format!("services.{service}.timestamp"): {
+                    "$lt": bson::DateTime::from(SystemTime::now() - Duration::from_secs(probe_every_secs)),
+                }
+            },
+            { format!("services.{service}"): { "$exists": false } },
+        ]
+    };
+
+    let mut pipeline: Vec<Document> = vec![doc! { "$match": filter }];
+    // there can be lots of servers on one ip
+    pipeline.push(doc! { "$group": { "_id": "$ip" } });
+    if let Some(limit) = limit {
+        pipeline.push(doc! { "$sample": { "size": limit as i64 } });
+    }
+
+    let mut cursor = database
+        .servers_coll()
+        .aggregate(pipeline)
+        .batch_size(2000)
+        .await?;
+
+    let mut ips = Vec::new();
+    while let Some(Ok(doc)) = cursor.next().await {
+        let Some(Bson::String(ip)) = doc.get("_id") else {
+            continue;
+        };
+        let addr = IpAddr::from_str(ip.as_str())?;
+        for &port in ports {
+            ranges.push(ScanRange::single(addr, port));
+        }
+        ips.push(ip.clone());
+    }
+
+    mark_checked(database, service, ips).await?;
+
+    Ok(ranges)
+}
+
+/// Set `services.<service>.open` to false and update its timestamp for every
+/// server on the IPs. Whatever else we knew about the service is kept.
+async fn mark_checked(database: &Database, service: &str, ips: Vec<String>) -> anyhow::Result<()> {
+    let now = bson::DateTime::from(SystemTime::now());
+    // so the filter doesn't get too big
+    for ips in ips.chunks(10_000) {
+        database
+            .servers_coll()
+            .update_many(
+                doc! { "ip": { "$in": ips } },
+                doc! { "$set": {
+                    format!("services.{service}.open"): false,
+                    format!("services.{service}.timestamp"): now,
+                } },
+            )
+            .await?;
+    }
+    Ok(())
+}
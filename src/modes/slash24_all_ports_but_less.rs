@@ -23,8 +23,8 @@ pub async fn get_ranges(database: &Database) -> anyhow::Result<Vec<ScanRange>> {
             continue;
         }
         target_ranges.push(ScanRange {
-            addr_start: Ipv4Addr::new(a, b, c, 0),
-            addr_end: Ipv4Addr::new(a, b, c, 255),
+            addr_start: Ipv4Addr::new(a, b, c, 0).into(),
+            addr_end: Ipv4Addr::new(a, b, c, 255).into(),
             port_start: 1024,
             port_end: 65535,
         });
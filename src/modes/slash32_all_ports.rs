@@ -22,8 +22,8 @@ pub async fn get_ranges(database: &Database) -> anyhow::Result<Vec<ScanRange>> {
 
     for &address in known_ips {
         target_ranges.push(ScanRange {
-            addr_start: address,
-            addr_end: address,
+            addr_start: address.into(),
+            addr_end: address.into(),
             port_start: 1024,
             port_end: 65535,
         });
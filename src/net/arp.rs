@@ -0,0 +1,116 @@
+use std::{
+    io,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use pnet::{
+    packet::{
+        arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket},
+        ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket},
+        Packet,
+    },
+    util::MacAddr,
+};
+
+use super::{raw_sockets::RawSocket, tcp::ETH_HEADER_LEN};
+
+const ARP_PACKET_LEN: usize = 28;
+
+/// Find the MAC address of `target_ip` by asking the whole network with an
+/// ARP request, like the OS would. The request is sent a few times in case
+/// one gets lost.
+pub fn resolve(
+    interface_name: &str,
+    interface_mac: MacAddr,
+    source_ip: Ipv4Addr,
+    target_ip: Ipv4Addr,
+) -> io::Result<Option<MacAddr>> {
+    let mut socket = RawSocket::new(interface_name)?;
+    let request = build_request(interface_mac, source_ip, target_ip);
+
+    let mut buffer = vec![0; 1 << 16];
+    for _ in 0..3 {
+        socket.send_blocking(&request);
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if !socket.poll(remaining)? {
+                break;
+            }
+            let len = match socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err),
+            };
+            if let Some(mac) = parse_reply(&buffer[..len], target_ip) {
+                return Ok(Some(mac));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn build_request(interface_mac: MacAddr, source_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Vec<u8> {
+    let mut packet = vec![0; ETH_HEADER_LEN + ARP_PACKET_LEN];
+
+    let mut ethernet = MutableEthernetPacket::new(&mut packet).unwrap();
+    ethernet.set_destination(MacAddr::broadcast());
+    ethernet.set_source(interface_mac);
+    ethernet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp = MutableArpPacket::new(&mut packet[ETH_HEADER_LEN..]).unwrap();
+    arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp.set_protocol_type(EtherTypes::Ipv4);
+    arp.set_hw_addr_len(6);
+    arp.set_proto_addr_len(4);
+    arp.set_operation(ArpOperations::Request);
+    arp.set_sender_hw_addr(interface_mac);
+    arp.set_sender_proto_addr(source_ip);
+    arp.set_target_hw_addr(MacAddr::zero());
+    arp.set_target_proto_addr(target_ip);
+
+    packet
+}
+
+/// Get the MAC address from the packet if it's an ARP reply from `target_ip`.
+fn parse_reply(packet: &[u8], target_ip: Ipv4Addr) -> Option<MacAddr> {
+    let ethernet = EthernetPacket::new(packet)?;
+    if ethernet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp = ArpPacket::new(ethernet.payload())?;
+    if arp.get_operation() != ArpOperations::Reply || arp.get_sender_proto_addr() != target_ip {
+        return None;
+    }
+    Some(arp.get_sender_hw_addr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        let our_mac = MacAddr::new(2, 0, 0, 0, 0, 1);
+        let gateway_mac = MacAddr::new(2, 0, 0, 0, 0, 2);
+        let our_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let gateway_ip = Ipv4Addr::new(192, 168, 1, 1);
+
+        // the request isn't a reply
+        let request = build_request(our_mac, our_ip, gateway_ip);
+        assert_eq!(parse_reply(&request, gateway_ip), None);
+
+        // turn the request into the reply that the gateway would send
+        let mut reply = request.clone();
+        let mut arp = MutableArpPacket::new(&mut reply[ETH_HEADER_LEN..]).unwrap();
+        arp.set_operation(ArpOperations::Reply);
+        arp.set_sender_hw_addr(gateway_mac);
+        arp.set_sender_proto_addr(gateway_ip);
+        arp.set_target_hw_addr(our_mac);
+        arp.set_target_proto_addr(our_ip);
+        assert_eq!(parse_reply(&reply, gateway_ip), Some(gateway_mac));
+        assert_eq!(parse_reply(&reply, Ipv4Addr::new(192, 168, 1, 3)), None);
+    }
+}
@@ -1,5 +1,7 @@
 use pnet::packet::tcp::TcpOption;
 
+use super::p0f::{Signature, WindowMatch};
+
 #[derive(Debug, Clone)]
 pub struct TcpFingerprint {
     pub initial_ttl: u8,
@@ -9,44 +11,32 @@ pub struct TcpFingerprint {
 
 impl TcpFingerprint {
     pub fn parse_signature(sig: &str, mss_arg: Option<u16>) -> Self {
-        let parts: Vec<&str> = sig.split(":").collect();
-        if parts.len() != 8 {
-            panic!("Invalid p0f signature specified (expected 8 parts)");
-        }
+        let Some(signature) = Signature::parse(sig) else {
+            panic!("Invalid p0f signature specified");
+        };
 
-        if parts[0] != "4" && parts[0] != "*" {
+        if signature.ip_version.is_some_and(|v| v != 4) {
             panic!("Invalid p0f signature specified (only IPv4 is supported)");
         }
 
-        let mut mss: u16 = 0;
-        if let Some(mss_arg) = mss_arg {
-            mss = mss_arg;
-        }
-
-        let initial_ttl = parts[1].parse::<u8>().unwrap();
-        if parts[3] != "*" {
-            mss = parts[3].parse::<u16>().unwrap();
-        }
+        let mss = signature.mss.or(mss_arg).unwrap_or(0);
 
-        let window_size: u16;
-        let window_scaling: u8;
-        if parts[4].starts_with("mss*") {
-            let split: Vec<&str> = parts[4][4..].split(",").collect();
-            window_size = mss * split[0].parse::<u16>().unwrap();
-            window_scaling = split[1].parse::<u8>().unwrap();
-        } else if parts[4].starts_with("mtu*") {
-            panic!("mtu*N window sizes are not supported");
-        } else if parts[4] == "*" {
-            window_size = 0;
-            window_scaling = 0;
-        } else {
-            let split: Vec<&str> = parts[4].split(",").collect();
-            window_size = split[0].parse::<u16>().unwrap();
-            window_scaling = split[1].parse::<u8>().unwrap();
-        }
+        let initial_ttl = signature.ttl;
+        let window_size = match signature.window {
+            WindowMatch::Any => 0,
+            WindowMatch::Value(window) => window,
+            WindowMatch::MssMultiple(n) => mss * n as u16,
+            WindowMatch::MtuMultiple(_) => panic!("mtu*N window sizes are not supported"),
+            WindowMatch::Modulo(_) => panic!("%N window sizes are not supported"),
+        };
+        // a wildcard window never had a scale
+        let window_scaling = match signature.window {
+            WindowMatch::Any => 0,
+            _ => signature.window_scale.unwrap_or(0),
+        };
 
         let mut options: Vec<TcpOption> = Vec::new();
-        let layout: Vec<&str> = parts[5].split(",").collect();
+        let layout: Vec<&str> = signature.layout.split(",").collect();
         for item in layout {
             match item {
                 "nop" => {
@@ -0,0 +1,290 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use pnet::{
+    datalink::{self, NetworkInterface},
+    util::MacAddr,
+};
+
+use super::{arp, ndp};
+use crate::config::NetworkConfig;
+
+/// The interface, addresses and gateway that packets are sent with. This is
+/// shared by TCP and UDP so the gateway only has to be resolved once.
+#[derive(Clone)]
+pub struct Network {
+    pub interface: NetworkInterface,
+    /// There's always at least one.
+    pub source_ipv4s: Vec<Ipv4Addr>,
+    /// Empty if we don't have a public IPv6 address, IPv6 targets are skipped
+    /// then.
+    pub source_ipv6s: Vec<Ipv6Addr>,
+    /// None if the interface doesn't use ethernet headers.
+    pub gateway_mac: Option<MacAddr>,
+    /// The next hop for IPv6, which is usually the same router.
+    pub gateway_mac_v6: Option<MacAddr>,
+}
+
+impl Network {
+    pub fn from_config(config: &NetworkConfig) -> anyhow::Result<Self> {
+        let interface = get_interface(config.interface.as_deref())?;
+        println!("Network interface: {}", interface.name);
+
+        let (source_ipv4s, mut source_ipv6s) = if config.source_ips.is_empty() {
+            let source_ipv4 = interface
+                .ips
+                .iter()
+                .find_map(|ip| match ip.ip() {
+                    IpAddr::V4(ip) => Some(ip),
+                    IpAddr::V6(_) => None,
+                })
+                .with_context(|| format!("{} doesn't have an IPv4 address", interface.name))?;
+            (
+                vec![source_ipv4],
+                get_interface_ipv6(&interface).into_iter().collect(),
+            )
+        } else {
+            let mut source_ipv4s = Vec::new();
+            let mut source_ipv6s = Vec::new();
+            for &ip in &config.source_ips {
+                match ip {
+                    IpAddr::V4(ip) => source_ipv4s.push(ip),
+                    IpAddr::V6(ip) => source_ipv6s.push(ip),
+                }
+            }
+            if source_ipv4s.is_empty() {
+                bail!("network.source_ips needs at least one IPv4 address");
+            }
+            (source_ipv4s, source_ipv6s)
+        };
+        println!("Source IPv4 addresses: {source_ipv4s:?}");
+
+        let gateway_mac = match (&config.gateway_mac, interface.mac) {
+            (Some(gateway_mac), _) => Some(
+                MacAddr::from_str(gateway_mac)
+                    .with_context(|| format!("invalid gateway MAC address {gateway_mac}"))?,
+            ),
+            // no interface mac = no ethernet header, so there's nothing to
+            // resolve
+            (None, None) => None,
+            (None, Some(interface_mac)) => {
+                let gateway_ip = match config.gateway_ip {
+                    Some(gateway_ip) => gateway_ip,
+                    None => get_gateway_ip(&interface.name)?.with_context(|| {
+                        format!(
+                            "no default route on {}, set network.gateway_ip or network.gateway_mac in the config",
+                            interface.name
+                        )
+                    })?,
+                };
+                let gateway_mac = arp::resolve(
+                    &interface.name,
+                    interface_mac,
+                    source_ipv4s[0],
+                    gateway_ip,
+                )?
+                .with_context(|| {
+                    format!(
+                        "{gateway_ip} didn't reply to ARP, set network.gateway_mac in the config"
+                    )
+                })?;
+                println!("Gateway: {gateway_ip} ({gateway_mac})");
+                Some(gateway_mac)
+            }
+        };
+
+        let gateway_mac_v6 = match (&config.gateway_mac_v6, interface.mac) {
+            (Some(gateway_mac_v6), _) => Some(
+                MacAddr::from_str(gateway_mac_v6)
+                    .with_context(|| format!("invalid gateway MAC address {gateway_mac_v6}"))?,
+            ),
+            (None, None) => None,
+            // it's never used then
+            (None, Some(_)) if source_ipv6s.is_empty() => None,
+            (None, Some(interface_mac)) => {
+                let gateway_mac_v6 =
+                    resolve_gateway_mac_v6(config, &interface, interface_mac, source_ipv6s[0])?;
+                if gateway_mac_v6.is_none() {
+                    // ipv4 still works, so this isn't worth failing over
+                    source_ipv6s.clear();
+                }
+                gateway_mac_v6
+            }
+        };
+        if source_ipv6s.is_empty() {
+            println!("No public IPv6 address, IPv6 targets will be skipped");
+        } else {
+            println!("Source IPv6 addresses: {source_ipv6s:?}");
+        }
+
+        Ok(Network {
+            interface,
+            source_ipv4s,
+            source_ipv6s,
+            gateway_mac,
+            gateway_mac_v6,
+        })
+    }
+
+    /// The MAC address that packets from or to the address are sent to.
+    pub fn gateway_mac_for(&self, ip: IpAddr) -> Option<MacAddr> {
+        match ip {
+            IpAddr::V4(_) => self.gateway_mac,
+            IpAddr::V6(_) => self.gateway_mac_v6,
+        }
+    }
+
+    /// Every address that we send from, replies to anything else are ignored.
+    pub fn source_ips(&self) -> Vec<IpAddr> {
+        self.source_ipv4s
+            .iter()
+            .map(|&ip| IpAddr::V4(ip))
+            .chain(self.source_ipv6s.iter().map(|&ip| IpAddr::V6(ip)))
+            .collect()
+    }
+}
+
+/// Which of the `count` source addresses we send to the target from. It only
+/// depends on the target, so every packet in a connection comes from the same
+/// address without us having to remember it.
+pub fn source_index(target: SocketAddr, count: usize) -> usize {
+    if count <= 1 {
+        return 0;
+    }
+    let mut hasher = DefaultHasher::new();
+    target.hash(&mut hasher);
+    (hasher.finish() % count as u64) as usize
+}
+
+/// Ask the IPv6 gateway for its MAC address with NDP. None if we don't know
+/// the gateway or it didn't reply, the reason is printed.
+fn resolve_gateway_mac_v6(
+    config: &NetworkConfig,
+    interface: &NetworkInterface,
+    interface_mac: MacAddr,
+    source_ip: Ipv6Addr,
+) -> anyhow::Result<Option<MacAddr>> {
+    let gateway_ip = match config.gateway_ip_v6 {
+        Some(gateway_ip) => gateway_ip,
+        None => {
+            let routes = fs::read_to_string("/proc/net/ipv6_route")?;
+            let Some(gateway_ip) = parse_default_gateway_v6(&routes, &interface.name) else {
+                println!(
+                    "No default IPv6 route on {}, set network.gateway_ip_v6 or network.gateway_mac_v6 in the config",
+                    interface.name
+                );
+                return Ok(None);
+            };
+            gateway_ip
+        }
+    };
+    // gateways are usually link-local, so we have to ask from a link-local
+    // address for the reply to come back
+    let source_ip = interface
+        .ips
+        .iter()
+        .find_map(|ip| match ip.ip() {
+            IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => Some(ip),
+            _ => None,
+        })
+        .unwrap_or(source_ip);
+
+    let gateway_mac = ndp::resolve(&interface.name, interface_mac, source_ip, gateway_ip)?;
+    match gateway_mac {
+        Some(gateway_mac) => println!("IPv6 gateway: {gateway_ip} ({gateway_mac})"),
+        None => println!(
+            "{gateway_ip} didn't reply to the neighbor solicitation, set network.gateway_mac_v6 in the config"
+        ),
+    }
+    Ok(gateway_mac)
+}
+
+/// The interface with the given name, or the one with the default route.
+fn get_interface(name: Option<&str>) -> anyhow::Result<NetworkInterface> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            default_net::get_default_interface()
+                .map_err(anyhow::Error::msg)
+                .context(
+                    "couldn't find the default interface, set network.interface in the config",
+                )?
+                .name
+        }
+    };
+
+    datalink::interfaces()
+        .into_iter()
+        .find(|i| i.name == name)
+        .with_context(|| format!("no network interface named {name}"))
+}
+
+/// Get the IPv6 address of the interface that we can send to the internet
+/// from, if it has one. Link-local and unique local addresses are skipped.
+fn get_interface_ipv6(interface: &NetworkInterface) -> Option<Ipv6Addr> {
+    interface.ips.iter().find_map(|ip| match ip.ip() {
+        IpAddr::V6(ip) => {
+            let first_segment = ip.segments()[0];
+            let is_link_local = first_segment & 0xffc0 == 0xfe80;
+            let is_unique_local = first_segment & 0xfe00 == 0xfc00;
+            if ip.is_loopback() || ip.is_unspecified() || is_link_local || is_unique_local {
+                None
+            } else {
+                Some(ip)
+            }
+        }
+        IpAddr::V4(_) => None,
+    })
+}
+
+/// The gateway of the default route on the interface, from /proc/net/route.
+fn get_gateway_ip(interface_name: &str) -> anyhow::Result<Option<Ipv4Addr>> {
+    let routes = fs::read_to_string("/proc/net/route")?;
+    Ok(parse_default_gateway(&routes, interface_name))
+}
+
+fn parse_default_gateway(routes: &str, interface_name: &str) -> Option<Ipv4Addr> {
+    // the first line is the header
+    routes.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (iface, destination, gateway) = (fields.next()?, fields.next()?, fields.next()?);
+        if iface != interface_name || destination != "00000000" {
+            return None;
+        }
+        // the addresses are written as hex in the machine's byte order
+        let gateway = u32::from_str_radix(gateway, 16).ok()?;
+        if gateway == 0 {
+            return None;
+        }
+        Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    })
+}
+
+/// The next hop of the default IPv6 route on the interface, from
+/// /proc/net/ipv6_route.
+fn parse_default_gateway_v6(routes: &str, interface_name: &str) -> Option<Ipv6Addr> {
+    routes.lines().find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [destination, prefix_len, _, _, next_hop, .., iface] = fields[..] else {
+            return None;
+        };
+        if iface != interface_name
+            || prefix_len != "00"
+            || u128::from_str_radix(destination, 16) != Ok(0)
+        {
+            return None;
+        }
+        // unlike /proc/net/route, these are written in network byte order
+        let next_hop = u128::from_str_radix(next_hop, 16).ok()?;
+        if next_hop == 0 {
+            return None;
+        }
+        Some(Ipv6Addr::from(next_hop))
+    })
+}
@@ -1,4 +1,11 @@
+pub mod arp;
+pub mod interface;
+pub mod ndp;
+pub mod p0f;
 pub mod raw_sockets;
+pub mod receive;
 pub mod tcp;
 pub mod tcp_template;
+pub mod udp;
+pub mod udp_template;
 pub(crate) mod fingerprint;
\ No newline at end of file
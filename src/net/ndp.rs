@@ -0,0 +1,171 @@
+use std::{
+    io,
+    net::Ipv6Addr,
+    time::{Duration, Instant},
+};
+
+use pnet::{
+    packet::{
+        ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket},
+        icmpv6::{
+            self,
+            ndp::{MutableNeighborSolicitPacket, NdpOption, NdpOptionTypes, NeighborAdvertPacket},
+            Icmpv6Code, Icmpv6Packet, Icmpv6Types,
+        },
+        ip::IpNextHeaderProtocols,
+        ipv6::{Ipv6Packet, MutableIpv6Packet},
+        Packet,
+    },
+    util::MacAddr,
+};
+
+use super::{raw_sockets::RawSocket, tcp::ETH_HEADER_LEN};
+
+const IPV6_HEADER_LEN: usize = 40;
+/// The neighbor solicitation and the option with our MAC address.
+const SOLICIT_LEN: usize = 24 + 8;
+
+/// Find the MAC address of `target_ip` with a neighbor solicitation, which is
+/// IPv6's version of an ARP request. The solicitation is sent a few times in
+/// case one gets lost.
+pub fn resolve(
+    interface_name: &str,
+    interface_mac: MacAddr,
+    source_ip: Ipv6Addr,
+    target_ip: Ipv6Addr,
+) -> io::Result<Option<MacAddr>> {
+    let mut socket = RawSocket::new(interface_name)?;
+    let request = build_solicit(interface_mac, source_ip, target_ip);
+
+    let mut buffer = vec![0; 1 << 16];
+    for _ in 0..3 {
+        socket.send_blocking(&request);
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if !socket.poll(remaining)? {
+                break;
+            }
+            let len = match socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err),
+            };
+            if let Some(mac) = parse_advert(&buffer[..len], target_ip) {
+                return Ok(Some(mac));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn build_solicit(interface_mac: MacAddr, source_ip: Ipv6Addr, target_ip: Ipv6Addr) -> Vec<u8> {
+    // the solicited-node multicast address, which only the hosts whose
+    // addresses end in the same 24 bits listen on
+    let target = target_ip.octets();
+    let destination_ip = Ipv6Addr::from(
+        u128::from(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00, 0))
+            | u128::from_be_bytes(target) & 0xff_ffff,
+    );
+    let destination_mac = MacAddr::new(0x33, 0x33, 0xff, target[13], target[14], target[15]);
+
+    let mut packet = vec![0; ETH_HEADER_LEN + IPV6_HEADER_LEN + SOLICIT_LEN];
+
+    let mut ethernet = MutableEthernetPacket::new(&mut packet).unwrap();
+    ethernet.set_destination(destination_mac);
+    ethernet.set_source(interface_mac);
+    ethernet.set_ethertype(EtherTypes::Ipv6);
+
+    let mut ipv6 = MutableIpv6Packet::new(&mut packet[ETH_HEADER_LEN..]).unwrap();
+    ipv6.set_version(6);
+    ipv6.set_payload_length(SOLICIT_LEN as u16);
+    ipv6.set_next_header(IpNextHeaderProtocols::Icmpv6);
+    // anything else is rejected by the neighbor
+    ipv6.set_hop_limit(255);
+    ipv6.set_source(source_ip);
+    ipv6.set_destination(destination_ip);
+
+    let solicit_buffer = &mut packet[ETH_HEADER_LEN + IPV6_HEADER_LEN..];
+    let mut solicit = MutableNeighborSolicitPacket::new(solicit_buffer).unwrap();
+    solicit.set_icmpv6_type(Icmpv6Types::NeighborSolicit);
+    solicit.set_icmpv6_code(Icmpv6Code(0));
+    solicit.set_target_addr(target_ip);
+    solicit.set_options(&[NdpOption {
+        option_type: NdpOptionTypes::SourceLLAddr,
+        length: 1,
+        data: interface_mac.octets().to_vec(),
+    }]);
+
+    let solicit_buffer = &mut packet[ETH_HEADER_LEN + IPV6_HEADER_LEN..];
+    let checksum = icmpv6::checksum(
+        &Icmpv6Packet::new(solicit_buffer).unwrap(),
+        &source_ip,
+        &destination_ip,
+    );
+    MutableNeighborSolicitPacket::new(solicit_buffer)
+        .unwrap()
+        .set_checksum(checksum);
+
+    packet
+}
+
+/// Get the MAC address from the packet if it's a neighbor advertisement for
+/// `target_ip`.
+fn parse_advert(packet: &[u8], target_ip: Ipv6Addr) -> Option<MacAddr> {
+    let ethernet = EthernetPacket::new(packet)?;
+    if ethernet.get_ethertype() != EtherTypes::Ipv6 {
+        return None;
+    }
+    let ipv6 = Ipv6Packet::new(ethernet.payload())?;
+    if ipv6.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+        return None;
+    }
+    let advert = NeighborAdvertPacket::new(ipv6.payload())?;
+    if advert.get_icmpv6_type() != Icmpv6Types::NeighborAdvert
+        || advert.get_target_addr() != target_ip
+    {
+        return None;
+    }
+    // replies to multicast solicitations always have the target's address
+    advert.get_options().into_iter().find_map(|option| {
+        if option.option_type != NdpOptionTypes::TargetLLAddr || option.data.len() < 6 {
+            return None;
+        }
+        let mac = &option.data;
+        Some(MacAddr::new(mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_advert() {
+        let our_mac = MacAddr::new(2, 0, 0, 0, 0, 1);
+        let gateway_mac = MacAddr::new(2, 0, 0, 0, 0, 2);
+        let our_ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        let gateway_ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+
+        // the solicitation isn't an advertisement
+        let request = build_solicit(our_mac, our_ip, gateway_ip);
+        assert_eq!(parse_advert(&request, gateway_ip), None);
+        assert_eq!(
+            &request[..6],
+            &[0x33, 0x33, 0xff, 0, 0, 1],
+            "sent to the solicited-node multicast address"
+        );
+
+        // turn the solicitation into the advertisement that the gateway
+        // would send, which has the same layout
+        let mut reply = request.clone();
+        let icmp = ETH_HEADER_LEN + IPV6_HEADER_LEN;
+        reply[icmp] = Icmpv6Types::NeighborAdvert.0;
+        // the target link-layer address option
+        reply[icmp + 24] = NdpOptionTypes::TargetLLAddr.0;
+        reply[icmp + 26..icmp + 32].copy_from_slice(&gateway_mac.octets());
+        assert_eq!(parse_advert(&reply, gateway_ip), Some(gateway_mac));
+        assert_eq!(parse_advert(&reply, our_ip), None);
+    }
+}
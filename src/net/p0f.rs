@@ -0,0 +1,599 @@
+use std::{fmt, sync::LazyLock};
+
+use pnet::packet::{
+    tcp::{TcpFlags, TcpPacket},
+    Packet,
+};
+
+use super::tcp::IpHeader;
+
+/// The SYN+ACK signatures that servers are matched against. They're in p0f's
+/// format, and the file says where each of them comes from.
+static DATABASE: LazyLock<Vec<DatabaseSignature>> =
+    LazyLock::new(|| parse_database(include_str!("syn_ack.fp")));
+
+/// p0f doesn't believe that a packet went through more hops than this.
+const MAX_DISTANCE: u8 = 35;
+
+/// The quirks in the order that p0f writes them.
+const QUIRKS: &[&str] = &[
+    "df", "id+", "id-", "ecn", "0+", "flow", "seq-", "ack+", "ack-", "uptr+", "urgf+", "pushf+",
+    "ts1-", "ts2+", "opt+", "exws", "bad",
+];
+
+/// The bit for a quirk from [`QUIRKS`].
+const fn quirk(index: usize) -> u32 {
+    1 << index
+}
+const QUIRK_DF: u32 = quirk(0);
+const QUIRK_ID_PLUS: u32 = quirk(1);
+const QUIRK_ID_MINUS: u32 = quirk(2);
+const QUIRK_ECN: u32 = quirk(3);
+const QUIRK_ZERO_PLUS: u32 = quirk(4);
+const QUIRK_FLOW: u32 = quirk(5);
+const QUIRK_SEQ_MINUS: u32 = quirk(6);
+const QUIRK_ACK_MINUS: u32 = quirk(8);
+const QUIRK_UPTR_PLUS: u32 = quirk(9);
+const QUIRK_URGF_PLUS: u32 = quirk(10);
+const QUIRK_PUSHF_PLUS: u32 = quirk(11);
+const QUIRK_TS1_MINUS: u32 = quirk(12);
+const QUIRK_OPT_PLUS: u32 = quirk(14);
+const QUIRK_EXWS: u32 = quirk(15);
+const QUIRK_BAD: u32 = quirk(16);
+
+/// TCP options can't be longer than this.
+const MAX_OPTIONS_LEN: usize = 40;
+
+/// What a SYN+ACK tells us about the server's TCP/IP stack, the same things
+/// that p0f looks at. This is rendered as a p0f signature with
+/// [`fmt::Display`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpSignature {
+    pub ip_version: u8,
+    pub ttl: u8,
+    pub ip_options_len: u8,
+    pub mss: Option<u16>,
+    pub window: u16,
+    pub window_scale: Option<u8>,
+    /// The kinds of the TCP options in order, only the first `options_len`
+    /// are used.
+    options: [u8; MAX_OPTIONS_LEN],
+    options_len: u8,
+    /// The number of bytes after the end of option list option.
+    eol_padding: u8,
+    quirks: u32,
+    has_payload: bool,
+}
+
+impl TcpSignature {
+    pub fn new(ip: &IpHeader, tcp: &TcpPacket) -> Self {
+        let mut signature = TcpSignature {
+            ip_version: if ip.source.is_ipv4() { 4 } else { 6 },
+            ttl: ip.ttl,
+            ip_options_len: ip.options_len,
+            mss: None,
+            window: tcp.get_window(),
+            window_scale: None,
+            options: [0; MAX_OPTIONS_LEN],
+            options_len: 0,
+            eol_padding: 0,
+            quirks: 0,
+            has_payload: !tcp.payload().is_empty(),
+        };
+
+        if ip.dont_fragment {
+            signature.quirks |= QUIRK_DF;
+            if ip.id != 0 {
+                signature.quirks |= QUIRK_ID_PLUS;
+            }
+        } else if ip.id == 0 && ip.source.is_ipv4() {
+            signature.quirks |= QUIRK_ID_MINUS;
+        }
+        let flags = tcp.get_flags();
+        if ip.ecn || flags & (TcpFlags::ECE | TcpFlags::CWR) != 0 {
+            signature.quirks |= QUIRK_ECN;
+        }
+        if ip.reserved_bit {
+            signature.quirks |= QUIRK_ZERO_PLUS;
+        }
+        if ip.flow_label != 0 {
+            signature.quirks |= QUIRK_FLOW;
+        }
+        if tcp.get_sequence() == 0 {
+            signature.quirks |= QUIRK_SEQ_MINUS;
+        }
+        if tcp.get_acknowledgement() == 0 {
+            signature.quirks |= QUIRK_ACK_MINUS;
+        }
+        if flags & TcpFlags::URG != 0 {
+            signature.quirks |= QUIRK_URGF_PLUS;
+        } else if tcp.get_urgent_ptr() != 0 {
+            signature.quirks |= QUIRK_UPTR_PLUS;
+        }
+        if flags & TcpFlags::PSH != 0 {
+            signature.quirks |= QUIRK_PUSHF_PLUS;
+        }
+
+        let header_len = (tcp.get_data_offset() as usize * 4).clamp(20, tcp.packet().len());
+        signature.parse_options(&tcp.packet()[20..header_len]);
+
+        signature
+    }
+
+    fn parse_options(&mut self, mut options: &[u8]) {
+        while let Some(&kind) = options.first() {
+            if self.options_len as usize == MAX_OPTIONS_LEN {
+                break;
+            }
+            self.options[self.options_len as usize] = kind;
+            self.options_len += 1;
+
+            match kind {
+                // end of option list, anything after it should be zero
+                0 => {
+                    let padding = &options[1..];
+                    self.eol_padding = padding.len() as u8;
+                    if padding.iter().any(|&b| b != 0) {
+                        self.quirks |= QUIRK_OPT_PLUS;
+                    }
+                    return;
+                }
+                // nop
+                1 => options = &options[1..],
+                _ => {
+                    let len = options.get(1).copied().unwrap_or_default() as usize;
+                    if len < 2 || len > options.len() {
+                        self.quirks |= QUIRK_BAD;
+                        return;
+                    }
+                    let data = &options[2..len];
+                    match (kind, data.len()) {
+                        // mss
+                        (2, 2) => self.mss = Some(u16::from_be_bytes([data[0], data[1]])),
+                        // window scale
+                        (3, 1) => {
+                            self.window_scale = Some(data[0]);
+                            if data[0] > 14 {
+                                self.quirks |= QUIRK_EXWS;
+                            }
+                        }
+                        // timestamps, the first one is the server's own and
+                        // the second one echoes ours
+                        (8, 8) => {
+                            if data[..4] == [0; 4] {
+                                self.quirks |= QUIRK_TS1_MINUS;
+                            }
+                        }
+                        (2 | 3 | 8, _) => self.quirks |= QUIRK_BAD,
+                        _ => {}
+                    }
+                    options = &options[len..];
+                }
+            }
+        }
+    }
+
+    /// The TTL that the server probably started with, servers almost always
+    /// use one of these.
+    pub fn initial_ttl(&self) -> u8 {
+        match self.ttl {
+            0..=32 => 32,
+            33..=64 => 64,
+            65..=128 => 128,
+            _ => 255,
+        }
+    }
+
+    /// The number of hops between us and the server.
+    pub fn distance(&self) -> u8 {
+        self.initial_ttl() - self.ttl
+    }
+
+    /// The option layout, like `mss,sok,ts,nop,ws`.
+    fn layout(&self) -> String {
+        self.options[..self.options_len as usize]
+            .iter()
+            .map(|&kind| match kind {
+                0 => format!("eol+{}", self.eol_padding),
+                1 => "nop".to_string(),
+                2 => "mss".to_string(),
+                3 => "ws".to_string(),
+                4 => "sok".to_string(),
+                5 => "sack".to_string(),
+                8 => "ts".to_string(),
+                kind => format!("?{kind}"),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// The name of the OS from the first signature in the database that
+    /// matches, preferring specific signatures over generic ones.
+    pub fn guess_os(&self) -> Option<&'static str> {
+        let layout = self.layout();
+        let mut generic_match = None;
+        for signature in DATABASE.iter() {
+            if !signature.signature.matches(self, &layout) {
+                continue;
+            }
+            if !signature.generic {
+                return Some(&signature.label);
+            }
+            generic_match.get_or_insert(signature.label.as_str());
+        }
+        generic_match
+    }
+}
+
+impl fmt::Display for TcpSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}+{}:{}:",
+            self.ip_version,
+            self.ttl,
+            self.distance(),
+            self.ip_options_len
+        )?;
+        match self.mss {
+            Some(mss) => write!(f, "{mss}:")?,
+            None => write!(f, "*:")?,
+        }
+        match self.mss {
+            Some(mss) if mss != 0 && self.window.is_multiple_of(mss) => {
+                write!(f, "mss*{}", self.window / mss)?
+            }
+            _ => write!(f, "{}", self.window)?,
+        }
+        let quirks = QUIRKS
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.quirks & quirk(i) != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(
+            f,
+            ",{}:{}:{}:{}",
+            self.window_scale.unwrap_or_default(),
+            self.layout(),
+            quirks,
+            if self.has_payload { "+" } else { "0" }
+        )
+    }
+}
+
+pub(super) enum WindowMatch {
+    Any,
+    Value(u16),
+    /// A multiple of this.
+    Modulo(u16),
+    MssMultiple(u32),
+    MtuMultiple(u32),
+}
+
+/// A signature from the database with its label.
+struct DatabaseSignature {
+    /// The name and flavor from the label, like `Linux 4.19 or newer`.
+    label: String,
+    generic: bool,
+    signature: Signature,
+}
+
+/// A p0f signature, like a `sig` line in p0f.fp. `None` means `*`.
+///
+/// This is also how the fingerprint that we send SYNs with is configured.
+pub(super) struct Signature {
+    pub ip_version: Option<u8>,
+    pub ttl: u8,
+    /// p0f adds a `-` to TTLs that aren't a reliable guess, the distance
+    /// isn't checked for those.
+    bad_ttl: bool,
+    pub ip_options_len: u8,
+    pub mss: Option<u16>,
+    pub window: WindowMatch,
+    pub window_scale: Option<u8>,
+    pub layout: String,
+    quirks: u32,
+    has_payload: Option<bool>,
+}
+
+impl Signature {
+    pub fn parse(sig: &str) -> Option<Self> {
+        let parts = sig.split(':').collect::<Vec<_>>();
+        let [ip_version, ttl, ip_options_len, mss, window, layout, quirks, payload] = parts[..]
+        else {
+            return None;
+        };
+        let (window, window_scale) = window.split_once(',')?;
+
+        let ttl = ttl.split('+').next()?;
+        let (ttl, bad_ttl) = match ttl.strip_suffix('-') {
+            Some(ttl) => (ttl, true),
+            None => (ttl, false),
+        };
+
+        Some(Signature {
+            ip_version: parse_wildcard(ip_version)?,
+            ttl: ttl.parse().ok()?,
+            bad_ttl,
+            ip_options_len: ip_options_len.parse().ok()?,
+            mss: parse_wildcard(mss)?,
+            window: if window == "*" {
+                WindowMatch::Any
+            } else if let Some(n) = window.strip_prefix("mss*") {
+                WindowMatch::MssMultiple(n.parse().ok()?)
+            } else if let Some(n) = window.strip_prefix("mtu*") {
+                WindowMatch::MtuMultiple(n.parse().ok()?)
+            } else if let Some(n) = window.strip_prefix('%') {
+                WindowMatch::Modulo(n.parse().ok()?)
+            } else {
+                WindowMatch::Value(window.parse().ok()?)
+            },
+            window_scale: parse_wildcard(window_scale)?,
+            layout: layout.to_string(),
+            quirks: quirks
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(|name| QUIRKS.iter().position(|q| *q == name).map(quirk))
+                .sum::<Option<u32>>()?,
+            has_payload: match payload {
+                "*" => None,
+                "0" => Some(false),
+                "+" => Some(true),
+                _ => return None,
+            },
+        })
+    }
+
+    fn matches(&self, signature: &TcpSignature, layout: &str) -> bool {
+        if self.ip_version.is_some_and(|v| v != signature.ip_version)
+            || signature.ttl > self.ttl
+            || (!self.bad_ttl && self.ttl - signature.ttl > MAX_DISTANCE)
+            || self.ip_options_len != signature.ip_options_len
+            || self.mss.is_some_and(|mss| Some(mss) != signature.mss)
+            || self
+                .window_scale
+                .is_some_and(|scale| scale != signature.window_scale.unwrap_or_default())
+            || self.quirks != signature.quirks
+            || self.has_payload.is_some_and(|p| p != signature.has_payload)
+            || self.layout != layout
+        {
+            return false;
+        }
+
+        let window = signature.window as u32;
+        let mss = signature.mss.unwrap_or_default() as u32;
+        match self.window {
+            WindowMatch::Any => true,
+            WindowMatch::Value(value) => signature.window == value,
+            WindowMatch::Modulo(n) => n != 0 && signature.window.is_multiple_of(n),
+            WindowMatch::MssMultiple(n) => {
+                // like p0f, some stacks take the timestamp option out of the mss
+                let has_timestamp = layout.split(',').any(|option| option == "ts");
+                mss != 0
+                    && (mss * n == window
+                        || (has_timestamp && mss > 12 && (mss - 12) * n == window))
+            }
+            WindowMatch::MtuMultiple(n) => {
+                let header_len = if signature.ip_version == 4 { 40 } else { 60 };
+                mss != 0 && (mss + header_len) * n == window
+            }
+        }
+    }
+}
+
+fn parse_wildcard<T: std::str::FromStr>(value: &str) -> Option<Option<T>> {
+    if value == "*" {
+        Some(None)
+    } else {
+        value.parse().ok().map(Some)
+    }
+}
+
+/// Get the signatures in the `[tcp:response]` section of a p0f.fp file.
+fn parse_database(database: &str) -> Vec<DatabaseSignature> {
+    let mut signatures = Vec::new();
+    let mut in_section = false;
+    let mut label = None;
+    for line in database.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_section = line == "[tcp:response]";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "label" => {
+                // type:class:name:flavor
+                let parts = value.trim().splitn(4, ':').collect::<Vec<_>>();
+                label = match parts[..] {
+                    [kind, _class, name, flavor] => {
+                        Some((format!("{name} {flavor}").trim().to_string(), kind == "g"))
+                    }
+                    _ => None,
+                };
+            }
+            "sig" => {
+                if let Some((label, generic)) = &label {
+                    if let Some(signature) = Signature::parse(value.trim()) {
+                        signatures.push(DatabaseSignature {
+                            label: label.clone(),
+                            generic: *generic,
+                            signature,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    signatures
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use pnet::packet::tcp::{MutableTcpPacket, TcpOption, TcpOptionPacket};
+
+    use super::*;
+    use crate::net::fingerprint::TcpFingerprint;
+
+    fn syn_ack(window: u16, options: &[TcpOption]) -> Vec<u8> {
+        let options_len = options
+            .iter()
+            .map(TcpOptionPacket::packet_size)
+            .sum::<usize>();
+        let mut buffer = vec![0; 20 + options_len.div_ceil(4) * 4];
+        let mut tcp = MutableTcpPacket::new(&mut buffer).unwrap();
+        tcp.set_data_offset((tcp.packet().len() / 4) as u8);
+        tcp.set_flags(TcpFlags::SYN | TcpFlags::ACK);
+        tcp.set_sequence(1234);
+        tcp.set_acknowledgement(5678);
+        tcp.set_window(window);
+        tcp.set_options(options);
+        buffer
+    }
+
+    fn ip_header(ttl: u8) -> IpHeader {
+        IpHeader {
+            source: IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1)),
+            destination: IpAddr::V4(Ipv4Addr::new(203, 0, 113, 2)),
+            ttl,
+            options_len: 0,
+            dont_fragment: true,
+            id: 0,
+            ecn: false,
+            flow_label: 0,
+            reserved_bit: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        let signature = Signature::parse("*:64:0:*:mss*20,10:mss,sok,ts,nop,ws:df,id+:0").unwrap();
+        assert_eq!(signature.ttl, 64);
+        assert!(matches!(signature.window, WindowMatch::MssMultiple(20)));
+        assert_eq!(signature.quirks, QUIRK_DF | QUIRK_ID_PLUS);
+
+        // the fingerprint that we send syns with is parsed the same way
+        let fingerprint = TcpFingerprint::parse_signature(
+            "*:64:0:*:mss*20,10:mss,sok,ts,nop,ws:df,id+:0",
+            Some(1460),
+        );
+        assert_eq!(fingerprint.initial_ttl, 64);
+        assert_eq!(fingerprint.window_size, 29200);
+        assert_eq!(fingerprint.options.len(), 5);
+        // a wildcard window doesn't keep the scale
+        let fingerprint =
+            TcpFingerprint::parse_signature("*:64:0:*:*,7:mss,sok,ts,nop,ws:df,id+:0", Some(1460));
+        assert_eq!(fingerprint.window_size, 0);
+        assert_eq!(
+            format!("{:?}", fingerprint.options[4]),
+            format!("{:?}", TcpOption::wscale(0))
+        );
+
+        assert!(Signature::parse("*:64:0:*:mss*20,10:mss:nonsense:0").is_none());
+        assert!(Signature::parse("*:64:0:*:mss*20,10").is_none());
+    }
+
+    #[test]
+    fn test_linux_syn_ack() {
+        let packet = syn_ack(
+            65160,
+            &[
+                TcpOption::mss(1460),
+                TcpOption::sack_perm(),
+                TcpOption::timestamp(1, 2),
+                TcpOption::nop(),
+                TcpOption::wscale(7),
+            ],
+        );
+        let signature = TcpSignature::new(&ip_header(52), &TcpPacket::new(&packet).unwrap());
+        assert_eq!(
+            signature.to_string(),
+            "4:52+12:0:1460:65160,7:mss,sok,ts,nop,ws:df:0"
+        );
+        assert_eq!(signature.distance(), 12);
+        assert_eq!(signature.guess_os(), Some("Linux 4.19 or newer"));
+
+        // a smaller mtu, the window is still a multiple of the mss minus the
+        // timestamp
+        let packet = syn_ack(
+            47 * 1388,
+            &[
+                TcpOption::mss(1400),
+                TcpOption::sack_perm(),
+                TcpOption::timestamp(1, 2),
+                TcpOption::nop(),
+                TcpOption::wscale(7),
+            ],
+        );
+        let signature = TcpSignature::new(&ip_header(52), &TcpPacket::new(&packet).unwrap());
+        assert_eq!(signature.guess_os(), Some("Linux 4.19 or newer"));
+
+        // without timestamps the mss is used as is
+        let packet = syn_ack(
+            44 * 1460,
+            &[
+                TcpOption::mss(1460),
+                TcpOption::nop(),
+                TcpOption::nop(),
+                TcpOption::sack_perm(),
+                TcpOption::nop(),
+                TcpOption::wscale(7),
+            ],
+        );
+        let signature = TcpSignature::new(&ip_header(52), &TcpPacket::new(&packet).unwrap());
+        assert_eq!(signature.guess_os(), Some("Linux 4.19 or newer"));
+        let packet = syn_ack(
+            44 * 1448,
+            &[
+                TcpOption::mss(1460),
+                TcpOption::nop(),
+                TcpOption::nop(),
+                TcpOption::sack_perm(),
+                TcpOption::nop(),
+                TcpOption::wscale(7),
+            ],
+        );
+        let signature = TcpSignature::new(&ip_header(52), &TcpPacket::new(&packet).unwrap());
+        assert_eq!(signature.guess_os(), Some("Linux"));
+    }
+
+    #[test]
+    fn test_windows_syn_ack() {
+        let packet = syn_ack(
+            8192,
+            &[
+                TcpOption::mss(1440),
+                TcpOption::nop(),
+                TcpOption::wscale(8),
+                TcpOption::nop(),
+                TcpOption::nop(),
+                TcpOption::sack_perm(),
+            ],
+        );
+        let mut ip = ip_header(117);
+        ip.id = 4321;
+        let signature = TcpSignature::new(&ip, &TcpPacket::new(&packet).unwrap());
+        assert_eq!(
+            signature.to_string(),
+            "4:117+11:0:1440:8192,8:mss,nop,ws,nop,nop,sok:df,id+:0"
+        );
+        assert_eq!(signature.guess_os(), Some("Windows 7 or 8"));
+
+        // nothing has a ttl of 255 and these options
+        let signature = TcpSignature::new(&ip_header(250), &TcpPacket::new(&packet).unwrap());
+        assert_eq!(signature.guess_os(), None);
+    }
+}
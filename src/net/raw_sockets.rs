@@ -1,7 +1,13 @@
 //! borrowed from smoltcp
 
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::{io, mem};
+use std::sync::atomic::{fence, Ordering};
+use std::time::Duration;
+use std::{io, mem, ptr, slice};
+
+use tracing::warn;
+
+use crate::config::SendMode;
 
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -41,11 +47,98 @@ pub const SIOCGIFINDEX: libc::c_ulong = 0x8933;
 pub const ETH_P_ALL: libc::c_short = 0x0003;
 pub const ETH_P_IEEE802154: libc::c_short = 0x00F6;
 
+// from linux/if_packet.h, older versions of libc don't have these
+const SOL_PACKET: libc::c_int = 263;
+const PACKET_RX_RING: libc::c_int = 5;
+const PACKET_STATISTICS: libc::c_int = 6;
+const PACKET_VERSION: libc::c_int = 10;
+const PACKET_TX_RING: libc::c_int = 13;
+const PACKET_FANOUT: libc::c_int = 18;
+const PACKET_FANOUT_HASH: libc::c_int = 0;
+const PACKET_FANOUT_FLAG_DEFRAG: libc::c_int = 0x8000;
+/// The kernel picks an unused id for the group, since 4.16.
+const PACKET_FANOUT_FLAG_UNIQUEID: libc::c_int = 0x2000;
+const PACKET_OUTGOING: u8 = 4;
+const TPACKET_V2: libc::c_int = 1;
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1 << 0;
+const TP_STATUS_AVAILABLE: u32 = 0;
+const TP_STATUS_SEND_REQUEST: u32 = 1 << 0;
+const TP_STATUS_SENDING: u32 = 1 << 1;
+const TP_STATUS_WRONG_FORMAT: u32 = 1 << 2;
+
+#[repr(C)]
+struct tpacket_req {
+    tp_block_size: libc::c_uint,
+    tp_block_nr: libc::c_uint,
+    tp_frame_size: libc::c_uint,
+    tp_frame_nr: libc::c_uint,
+}
+
+#[repr(C)]
+struct tpacket_stats {
+    tp_packets: libc::c_uint,
+    tp_drops: libc::c_uint,
+}
+
+#[repr(C)]
+struct tpacket2_hdr {
+    tp_status: u32,
+    tp_len: u32,
+    tp_snaplen: u32,
+    tp_mac: u16,
+    tp_net: u16,
+    tp_sec: u32,
+    tp_nsec: u32,
+    tp_vlan_tci: u16,
+    tp_vlan_tpid: u16,
+    tp_padding: [u8; 4],
+}
+
+/// Where the packet starts in a TX ring frame. This is TPACKET2_HDRLEN minus
+/// the sockaddr_ll, which is only filled in for RX.
+const TX_RING_DATA_OFFSET: usize = mem::size_of::<tpacket2_hdr>();
+/// The sockaddr_ll comes right after the frame header, which is already
+/// aligned.
+const SOCKADDR_LL_OFFSET: usize = mem::size_of::<tpacket2_hdr>();
+/// Room before the packet in an RX ring frame for the frame header, the
+/// sockaddr_ll, and the padding that the kernel adds to align the packet.
+const RX_RING_HEADROOM: usize = 128;
+
+/// Counters from PACKET_STATISTICS. The kernel resets them every time they're
+/// read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PacketStatistics {
+    /// The number of packets that reached the socket, including the dropped
+    /// ones.
+    pub packets: u32,
+    /// The number of packets that were dropped because the ring was full.
+    pub drops: u32,
+    /// The number of packets that didn't fit in an RX ring frame and were
+    /// dropped by us. This happens when GRO merges packets into ones that are
+    /// bigger than the MTU.
+    pub truncated: u32,
+}
+
 #[derive(Debug)]
 pub struct RawSocket {
     protocol: libc::c_short,
     lower: libc::c_int,
     ifreq: ifreq,
+
+    send_mode: SendMode,
+    batch_size: usize,
+    /// Packets waiting for the next sendmmsg, only used with
+    /// [`SendMode::Sendmmsg`].
+    batch: PacketBatch,
+    /// Only set with [`SendMode::TxRing`].
+    tx_ring: Option<Ring>,
+    /// Set with [`Self::set_rx_ring`], packets are only received through
+    /// [`Self::recv_with`] then.
+    rx_ring: Option<Ring>,
+    /// Packets that were cut off by the RX ring since the last
+    /// [`Self::statistics`].
+    truncated: u32,
 }
 
 impl AsRawFd for RawSocket {
@@ -74,12 +167,48 @@ impl RawSocket {
             protocol,
             lower,
             ifreq: ifreq_for(name),
+
+            send_mode: SendMode::Send,
+            batch_size: 1,
+            batch: PacketBatch::new(0, 0),
+            tx_ring: None,
+            rx_ring: None,
+            truncated: 0,
         };
         socket.bind_interface()?;
 
         Ok(socket)
     }
 
+    /// Change how [queued](Self::queue) packets are sent. `batch_size` is the
+    /// number of packets per sendmmsg or the number of frames in the TX ring.
+    pub fn set_send_mode(&mut self, send_mode: SendMode, batch_size: usize) -> io::Result<()> {
+        self.flush();
+
+        let batch_size = batch_size.max(1);
+        // the mtu doesn't include the ethernet header
+        let max_packet_size = self.interface_mtu()? + 14;
+
+        self.batch = PacketBatch::new(0, 0);
+        self.tx_ring = None;
+        match send_mode {
+            SendMode::Send => {}
+            SendMode::Sendmmsg => self.batch = PacketBatch::new(batch_size, max_packet_size),
+            SendMode::TxRing => {
+                self.tx_ring = Some(Ring::new(
+                    self.lower,
+                    PACKET_TX_RING,
+                    batch_size,
+                    TX_RING_DATA_OFFSET + max_packet_size,
+                )?)
+            }
+        }
+        self.send_mode = send_mode;
+        self.batch_size = batch_size;
+
+        Ok(())
+    }
+
     pub fn interface_mtu(&mut self) -> io::Result<usize> {
         ifreq_ioctl(self.lower, &mut self.ifreq, SIOCGIFMTU).map(|mtu| mtu as usize)
     }
@@ -139,16 +268,491 @@ impl RawSocket {
         }
     }
 
+    /// Send the packet right away, even if the send mode batches packets.
     pub fn send_blocking(&mut self, buffer: &[u8]) {
+        if self.send_mode != SendMode::Send {
+            self.queue(buffer);
+            self.flush();
+            return;
+        }
+
         loop {
             match self.send(buffer) {
                 Ok(_) => break,
-                Err(err) => match err.kind() {
-                    io::ErrorKind::WouldBlock => {}
-                    e => panic!("error sending packet: {:?}", e),
-                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => {
+                    warn!("Failed to send a packet: {err}");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Receive packets into a PACKET_RX_RING with room for `frames` packets.
+    /// Packets have to be read with [`Self::recv_with`] after this.
+    pub fn set_rx_ring(&mut self, frames: usize) -> io::Result<()> {
+        // the mtu doesn't include the ethernet header
+        let max_packet_size = self.interface_mtu()? + 14;
+        self.rx_ring = Some(Ring::new(
+            self.lower,
+            PACKET_RX_RING,
+            frames,
+            RX_RING_HEADROOM + max_packet_size,
+        )?);
+        Ok(())
+    }
+
+    /// Wait until there's a packet to read or the timeout passes. Returns
+    /// whether there's a packet.
+    pub fn poll(&self, timeout: Duration) -> io::Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.lower,
+            events: libc::POLLIN | libc::POLLERR,
+            revents: 0,
+        };
+        let res = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+        if res == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        Ok(res > 0)
+    }
+
+    /// Call `f` with every packet that's waiting in the RX ring. The packets
+    /// are read in place, so they're never copied. Packets that we sent are
+    /// skipped, and so are packets that were cut off because they're bigger
+    /// than a frame, which are counted in [`PacketStatistics::truncated`].
+    ///
+    /// Returns the number of packets that `f` was called with.
+    pub fn recv_with(&mut self, mut f: impl FnMut(&[u8])) -> usize {
+        let rx_ring = self.rx_ring.as_mut().expect("rx ring is set up");
+
+        let mut received = 0;
+        loop {
+            let header = rx_ring.next_header();
+            unsafe {
+                let status = ptr::read_volatile(ptr::addr_of!((*header).tp_status));
+                if status & TP_STATUS_USER == 0 {
+                    break;
+                }
+                // don't read the packet before the kernel is done writing it
+                fence(Ordering::Acquire);
+
+                let frame = header as *const u8;
+                let sockaddr = frame.add(SOCKADDR_LL_OFFSET) as *const libc::sockaddr_ll;
+                if (*header).tp_len > (*header).tp_snaplen {
+                    // the rest of the packet is gone, so parsing it would
+                    // only give us garbage
+                    self.truncated += 1;
+                } else if (*sockaddr).sll_pkttype != PACKET_OUTGOING {
+                    let packet = slice::from_raw_parts(
+                        frame.add((*header).tp_mac as usize),
+                        (*header).tp_snaplen as usize,
+                    );
+                    f(packet);
+                    received += 1;
+                }
+
+                // give the frame back to the kernel
+                fence(Ordering::Release);
+                ptr::write_volatile(ptr::addr_of_mut!((*header).tp_status), TP_STATUS_KERNEL);
+            }
+            rx_ring.advance();
+        }
+        received
+    }
+
+    /// Get the number of packets that were received and dropped since the
+    /// last time this was called.
+    pub fn statistics(&mut self) -> io::Result<PacketStatistics> {
+        let mut stats = tpacket_stats {
+            tp_packets: 0,
+            tp_drops: 0,
+        };
+        let mut len = mem::size_of::<tpacket_stats>() as libc::socklen_t;
+        unsafe {
+            if libc::getsockopt(
+                self.lower,
+                SOL_PACKET,
+                PACKET_STATISTICS,
+                &mut stats as *mut tpacket_stats as *mut libc::c_void,
+                &mut len,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(PacketStatistics {
+            packets: stats.tp_packets,
+            drops: stats.tp_drops,
+            truncated: mem::take(&mut self.truncated),
+        })
+    }
+
+    /// Create a new PACKET_FANOUT group with an id that the kernel picks, so
+    /// it can't collide with another process's group. The id is returned so
+    /// other sockets can [`Self::join_fanout`] it.
+    pub fn create_fanout(&self) -> io::Result<u16> {
+        self.set_fanout(0, PACKET_FANOUT_FLAG_UNIQUEID)?;
+
+        let mut arg: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        unsafe {
+            if libc::getsockopt(
+                self.lower,
+                SOL_PACKET,
+                PACKET_FANOUT,
+                &mut arg as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        // the id is in the low 16 bits, the type and flags are above it
+        Ok(arg as u16)
+    }
+
+    /// Join the PACKET_FANOUT group with the given id, so packets are split
+    /// between every socket in the group by a hash of their flow. Fragments
+    /// are reassembled first so they end up in the same socket.
+    pub fn join_fanout(&self, group_id: u16) -> io::Result<()> {
+        self.set_fanout(group_id, 0)
+    }
+
+    fn set_fanout(&self, group_id: u16, extra_flags: libc::c_int) -> io::Result<()> {
+        let arg = group_id as libc::c_int
+            | ((PACKET_FANOUT_HASH | PACKET_FANOUT_FLAG_DEFRAG | extra_flags) << 16);
+        unsafe {
+            if libc::setsockopt(
+                self.lower,
+                SOL_PACKET,
+                PACKET_FANOUT,
+                &arg as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the packet to the current batch. It's sent when the batch is full
+    /// or on the next [`Self::flush`].
+    pub fn queue(&mut self, buffer: &[u8]) {
+        match self.send_mode {
+            SendMode::Send => self.send_blocking(buffer),
+            SendMode::Sendmmsg => {
+                if self.batch.push(buffer) {
+                    self.flush();
+                }
+            }
+            SendMode::TxRing => {
+                let tx_ring = self.tx_ring.as_mut().expect("tx ring is set up");
+                // the frame is free again once the kernel sent what was in it
+                while !tx_ring.push(buffer) {
+                    if !tx_ring.flush(self.lower) {
+                        // the kernel won't send what's waiting, so the frame
+                        // is reused and that packet is lost
+                        tx_ring.drop_next_frame();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send every packet that was queued.
+    pub fn flush(&mut self) {
+        match self.send_mode {
+            SendMode::Send => {}
+            SendMode::Sendmmsg => {
+                send_batch_blocking(self.lower, &self.batch);
+                self.batch.clear();
+            }
+            SendMode::TxRing => {
+                if let Some(tx_ring) = &mut self.tx_ring {
+                    tx_ring.flush(self.lower);
+                }
+            }
+        }
+    }
+}
+
+/// Send every packet in the batch, with as few sendmmsg calls as possible.
+fn send_batch_blocking(lower: libc::c_int, batch: &PacketBatch) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let mut iovecs = batch
+        .packets()
+        .map(|packet| libc::iovec {
+            iov_base: packet.as_ptr() as *mut libc::c_void,
+            iov_len: packet.len(),
+        })
+        .collect::<Vec<_>>();
+    let mut headers = iovecs
+        .iter_mut()
+        .map(|iovec| {
+            let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+            header.msg_hdr.msg_iov = iovec;
+            header.msg_hdr.msg_iovlen = 1;
+            header
+        })
+        .collect::<Vec<_>>();
+
+    let mut sent = 0;
+    while sent < headers.len() {
+        let res = unsafe {
+            libc::sendmmsg(
+                lower,
+                headers[sent..].as_mut_ptr(),
+                (headers.len() - sent) as libc::c_uint,
+                0,
+            )
+        };
+        if res == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                // sendmmsg only fails if the first packet couldn't be sent,
+                // so skip it and send the rest
+                warn!("Failed to send a packet: {err}");
+                sent += 1;
+            }
+        } else {
+            sent += res as usize;
+        }
+    }
+}
+
+/// Packets that are copied next to each other so they can be sent with one
+/// syscall.
+#[derive(Debug, Clone)]
+pub struct PacketBatch {
+    buffer: Vec<u8>,
+    max_packet_size: usize,
+    lens: Vec<usize>,
+    capacity: usize,
+}
+
+impl PacketBatch {
+    pub fn new(capacity: usize, max_packet_size: usize) -> Self {
+        Self {
+            buffer: vec![0; capacity * max_packet_size],
+            max_packet_size,
+            lens: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Copy the packet into the batch. Returns whether the batch is full now.
+    ///
+    /// Panics if the batch was already full or the packet is bigger than the
+    /// max packet size.
+    pub fn push(&mut self, packet: &[u8]) -> bool {
+        assert!(self.lens.len() < self.capacity, "packet batch is full");
+        assert!(
+            packet.len() <= self.max_packet_size,
+            "packet is bigger than the mtu"
+        );
+
+        let start = self.lens.len() * self.max_packet_size;
+        self.buffer[start..start + packet.len()].copy_from_slice(packet);
+        self.lens.push(packet.len());
+
+        self.lens.len() >= self.capacity
+    }
+
+    pub fn packets(&self) -> impl Iterator<Item = &[u8]> {
+        self.lens.iter().enumerate().map(|(i, &len)| {
+            let start = i * self.max_packet_size;
+            &self.buffer[start..start + len]
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lens.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lens.clear();
+    }
+}
+
+/// A PACKET_TX_RING or PACKET_RX_RING that's mmapped from the socket.
+///
+/// For TX, packets are written straight into the ring and the kernel sends all
+/// of them when we call send without any data. For RX, the kernel writes the
+/// packets into the ring and we read them from there without copying them.
+#[derive(Debug)]
+struct Ring {
+    ring: *mut u8,
+    ring_len: usize,
+    frame_size: usize,
+    frame_count: usize,
+    /// The frame that the next packet is written to or read from.
+    next_frame: usize,
+}
+
+// the ring is only ever used through the socket that owns it
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn new(
+        lower: libc::c_int,
+        ring_type: libc::c_int,
+        frames: usize,
+        frame_size: usize,
+    ) -> io::Result<Self> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let frame_size = frame_size.next_power_of_two();
+        // frames can't cross blocks and blocks have to be a multiple of the
+        // page size
+        let block_size = frame_size.max(page_size);
+        let frames_per_block = block_size / frame_size;
+        let block_count = frames.div_ceil(frames_per_block);
+        let frame_count = block_count * frames_per_block;
+
+        let version = TPACKET_V2;
+        let req = tpacket_req {
+            tp_block_size: block_size as libc::c_uint,
+            tp_block_nr: block_count as libc::c_uint,
+            tp_frame_size: frame_size as libc::c_uint,
+            tp_frame_nr: frame_count as libc::c_uint,
+        };
+        unsafe {
+            if libc::setsockopt(
+                lower,
+                SOL_PACKET,
+                PACKET_VERSION,
+                &version as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setsockopt(
+                lower,
+                SOL_PACKET,
+                ring_type,
+                &req as *const tpacket_req as *const libc::c_void,
+                mem::size_of::<tpacket_req>() as libc::socklen_t,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error());
             }
         }
+
+        let ring_len = block_size * block_count;
+        let ring = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                ring_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                lower,
+                0,
+            )
+        };
+        if ring == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ring: ring as *mut u8,
+            ring_len,
+            frame_size,
+            frame_count,
+            next_frame: 0,
+        })
+    }
+
+    fn next_header(&self) -> *mut tpacket2_hdr {
+        // a block is always a whole number of frames, so the frames are
+        // contiguous
+        unsafe { self.ring.add(self.next_frame * self.frame_size) as *mut tpacket2_hdr }
+    }
+
+    /// Write the packet to the next frame of a TX ring. Returns false if the
+    /// kernel hasn't sent what was in that frame yet.
+    fn push(&mut self, packet: &[u8]) -> bool {
+        assert!(
+            TX_RING_DATA_OFFSET + packet.len() <= self.frame_size,
+            "packet is bigger than the mtu"
+        );
+
+        let header = self.next_header();
+        unsafe {
+            let status = ptr::read_volatile(ptr::addr_of!((*header).tp_status));
+            if status & (TP_STATUS_SEND_REQUEST | TP_STATUS_SENDING) != 0 {
+                return false;
+            }
+            if status & TP_STATUS_WRONG_FORMAT != 0 {
+                warn!("The kernel rejected a packet in the TX ring");
+            }
+
+            let data = (header as *mut u8).add(TX_RING_DATA_OFFSET);
+            ptr::copy_nonoverlapping(packet.as_ptr(), data, packet.len());
+            (*header).tp_len = packet.len() as u32;
+            // the kernel mustn't see the new status before the packet
+            fence(Ordering::Release);
+            ptr::write_volatile(
+                ptr::addr_of_mut!((*header).tp_status),
+                TP_STATUS_SEND_REQUEST,
+            );
+        }
+
+        self.advance();
+        true
+    }
+
+    fn advance(&mut self) {
+        self.next_frame = (self.next_frame + 1) % self.frame_count;
+    }
+
+    /// Tell the kernel to send every frame of a TX ring that's waiting.
+    /// Returns false if it failed, in which case the frames are still
+    /// waiting.
+    fn flush(&mut self, lower: libc::c_int) -> bool {
+        loop {
+            let res = unsafe { libc::send(lower, ptr::null(), 0, 0) };
+            if res != -1 {
+                return true;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                warn!("Failed to send the packets in the TX ring: {err}");
+                return false;
+            }
+        }
+    }
+
+    /// Free the next frame if it's waiting to be sent, so its packet is
+    /// dropped. Frames that the kernel is already sending are left alone.
+    fn drop_next_frame(&mut self) {
+        let header = self.next_header();
+        unsafe {
+            let status = ptr::read_volatile(ptr::addr_of!((*header).tp_status));
+            if status & TP_STATUS_SEND_REQUEST != 0 && status & TP_STATUS_SENDING == 0 {
+                ptr::write_volatile(ptr::addr_of_mut!((*header).tp_status), TP_STATUS_AVAILABLE);
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ring as *mut libc::c_void, self.ring_len);
+        }
     }
 }
 
@@ -173,8 +777,19 @@ impl Clone for RawSocket {
             protocol,
             lower,
             ifreq: self.ifreq.clone(),
+
+            send_mode: SendMode::Send,
+            batch_size: 1,
+            batch: PacketBatch::new(0, 0),
+            tx_ring: None,
+            rx_ring: None,
+            truncated: 0,
         };
         socket.bind_interface().unwrap();
+        // every socket needs its own ring
+        socket
+            .set_send_mode(self.send_mode, self.batch_size)
+            .unwrap();
 
         socket
     }
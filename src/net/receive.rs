@@ -0,0 +1,166 @@
+use std::{io, net::IpAddr, time::Duration};
+
+use pnet::{
+    packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    util::MacAddr,
+};
+use tracing::warn;
+
+use super::{
+    interface::Network,
+    raw_sockets::{PacketStatistics, RawSocket},
+    tcp::{ip_payload, IpHeader, ETH_HEADER_LEN},
+};
+use crate::scanner::SourcePort;
+
+/// The number of packets that fit in the RX ring, each frame is 2 KB with a
+/// normal MTU.
+const RX_RING_FRAMES: usize = 16384;
+
+/// Receives the TCP or UDP packets that were sent to our source addresses and
+/// ports. The kernel writes them straight to an RX ring and they're parsed in
+/// place, so nothing is copied.
+pub struct PacketReceiver {
+    interface_mac: Option<MacAddr>,
+    source_port: SourcePort,
+    /// Packets to other addresses are ignored.
+    source_ips: Vec<IpAddr>,
+
+    #[cfg(not(feature = "benchmark"))]
+    socket: RawSocket,
+}
+
+impl PacketReceiver {
+    pub fn new(network: &Network, source_port: SourcePort) -> io::Result<Self> {
+        #[cfg(not(feature = "benchmark"))]
+        let socket = {
+            let mut socket = RawSocket::new(&network.interface.name)?;
+            socket.set_rx_ring(RX_RING_FRAMES)?;
+            socket
+        };
+
+        Ok(Self {
+            interface_mac: network.interface.mac,
+            source_port,
+            source_ips: network.source_ips(),
+            #[cfg(not(feature = "benchmark"))]
+            socket,
+        })
+    }
+
+    /// Wait up to `timeout` for packets, then call `f` with the IP header and
+    /// the transport packet of every `protocol` packet that was sent to us.
+    pub fn recv_with(
+        &mut self,
+        timeout: Duration,
+        protocol: IpNextHeaderProtocol,
+        mut f: impl FnMut(&IpHeader, &[u8]),
+    ) {
+        #[cfg(not(feature = "benchmark"))]
+        {
+            match self.socket.poll(timeout) {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(err) => {
+                    warn!("Failed to poll the socket: {err}");
+                    return;
+                }
+            }
+
+            let interface_mac = self.interface_mac;
+            let source_port = self.source_port;
+            let source_ips = &self.source_ips;
+            self.socket.recv_with(|packet| {
+                let ip_packet = if interface_mac.is_some() {
+                    let Some(ip_packet) = packet.get(ETH_HEADER_LEN..) else {
+                        return;
+                    };
+                    ip_packet
+                } else {
+                    // no interface mac = no ethernet header
+                    packet
+                };
+
+                let Some((header, packet_protocol, payload)) = transport_packet(ip_packet) else {
+                    return;
+                };
+                if packet_protocol != protocol || !source_ips.contains(&header.destination) {
+                    return;
+                }
+                // the destination port is in the same place for tcp and udp
+                let Some(destination_port) = payload.get(2..4) else {
+                    return;
+                };
+                if source_port.contains(u16::from_be_bytes([
+                    destination_port[0],
+                    destination_port[1],
+                ])) {
+                    f(&header, payload);
+                }
+            });
+        }
+        #[cfg(feature = "benchmark")]
+        let _ = (timeout, protocol, &mut f);
+    }
+
+    /// Split this into `count` receivers with PACKET_FANOUT, so each one gets
+    /// a share of the packets. Packets from the same flow always go to the
+    /// same receiver.
+    pub fn fanout(self, count: usize) -> io::Result<Vec<PacketReceiver>> {
+        if count <= 1 {
+            return Ok(vec![self]);
+        }
+
+        #[cfg(not(feature = "benchmark"))]
+        let group_id = self.socket.create_fanout()?;
+
+        let mut receivers = Vec::with_capacity(count);
+        for _ in 1..count {
+            #[cfg(not(feature = "benchmark"))]
+            let socket = {
+                // cloning opens a new socket on the same interface
+                let mut socket = self.socket.clone();
+                socket.set_rx_ring(RX_RING_FRAMES)?;
+                socket.join_fanout(group_id)?;
+                socket
+            };
+            receivers.push(PacketReceiver {
+                interface_mac: self.interface_mac,
+                source_port: self.source_port,
+                source_ips: self.source_ips.clone(),
+                #[cfg(not(feature = "benchmark"))]
+                socket,
+            });
+        }
+        receivers.insert(0, self);
+
+        Ok(receivers)
+    }
+
+    /// The number of packets that the kernel received and dropped since the
+    /// last time this was called.
+    pub fn statistics(&mut self) -> Option<PacketStatistics> {
+        #[cfg(not(feature = "benchmark"))]
+        return self.socket.statistics().ok();
+        #[cfg(feature = "benchmark")]
+        None
+    }
+}
+
+/// Get the IP header, the protocol, and the TCP or UDP packet from an IPv4 or
+/// IPv6 packet, without copying anything. IPv4 packets that are tunneled in
+/// another one are unwrapped.
+fn transport_packet(packet: &[u8]) -> Option<(IpHeader, IpNextHeaderProtocol, &[u8])> {
+    let (header, protocol, payload) = ip_payload(packet)?;
+    match protocol {
+        // the outer header is the one that was sent to us
+        IpNextHeaderProtocols::Ipv4 => {
+            transport_packet(payload).map(|(_, protocol, payload)| (header, protocol, payload))
+        }
+        IpNextHeaderProtocols::IpComp => {
+            warn!("Recieved an IpComp packet, but it's not supported.");
+            None
+        }
+        _ => Some((header, protocol, payload)),
+    }
+}
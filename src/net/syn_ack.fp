@@ -0,0 +1,63 @@
+; SYN+ACK signatures that the servers we connect to are matched against. They
+; use the format of p0f v3's p0f.fp, and only the [tcp:response] section is
+; read. Every label says where its signatures come from.
+;
+; label = type:class:name:flavor, where type is s (specific) or g (generic)
+; sig   = ver:ittl:olen:mss:wsize,scale:olayout:quirks:pclass
+;
+; Like in p0f, mss*N matches a window of N times the MSS in the SYN+ACK, or N
+; times the MSS minus 12 if there's a timestamp option.
+;
+; Specific labels are preferred over generic ones when both match.
+
+[tcp:response]
+
+; source: the kernel commit "tcp: up initial rmem to 128KB and SYN rwin to
+; around 64KB" (Linux 4.19). tcp_openreq_init_rwin() in tcp_minisocks.c takes
+; 12 from the MSS for timestamps, and tcp_select_initial_window() in
+; tcp_output.c rounds 64K down to a multiple of it. N is 65535 / MSS rounded
+; down, so these cover an MSS of 1394 to 1460. The option order is from
+; tcp_options_write() in tcp_output.c.
+label = s:unix:Linux:4.19 or newer
+sig   = *:64:0:*:mss*45,*:mss,sok,ts,nop,ws:df:0
+sig   = *:64:0:*:mss*45,*:mss,sok,ts,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*46,*:mss,sok,ts,nop,ws:df:0
+sig   = *:64:0:*:mss*46,*:mss,sok,ts,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*47,*:mss,sok,ts,nop,ws:df:0
+sig   = *:64:0:*:mss*47,*:mss,sok,ts,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*44,*:mss,nop,nop,sok,nop,ws:df:0
+sig   = *:64:0:*:mss*44,*:mss,nop,nop,sok,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*45,*:mss,nop,nop,sok,nop,ws:df:0
+sig   = *:64:0:*:mss*45,*:mss,nop,nop,sok,nop,ws:df,id+:0
+sig   = *:64:0:*:mss*46,*:mss,nop,nop,sok,nop,ws:df:0
+sig   = *:64:0:*:mss*46,*:mss,nop,nop,sok,nop,ws:df,id+:0
+
+; source: syncache_add() in sys/netinet/tcp_syncache.c clamps the window to
+; TCP_MAXWIN, which the default 64K receive buffer always hits. The option
+; order is from tcp_addoptions() in sys/netinet/tcp_output.c.
+label = s:unix:FreeBSD:
+sig   = *:64:0:*:65535,*:mss,nop,ws,sok,ts:df,id+:0
+sig   = *:64:0:*:65535,*:mss,nop,ws,sok:df,id+:0
+sig   = *:64:0:*:65535,0:mss,sok,ts:df,id+:0
+
+; source: the s:win:Windows:7 or 8 signatures in the [tcp:response] section of
+; p0f 3.09b's p0f.fp, by Michal Zalewski (LGPL 2.1).
+label = s:win:Windows:7 or 8
+sig   = *:128:0:*:8192,0:mss:df,id+:0
+sig   = *:128:0:*:8192,0:mss,sok,ts:df,id+:0
+sig   = *:128:0:*:8192,8:mss,nop,ws:df,id+:0
+sig   = *:128:0:*:8192,8:mss,nop,ws,sok,ts:df,id+:0
+sig   = *:128:0:*:8192,8:mss,nop,ws,nop,nop,sok:df,id+:0
+
+; source: the option orders from the Linux signatures above, with any window.
+label = g:unix:Linux:
+sig   = *:64:0:*:*,*:mss,sok,ts,nop,ws:df:0
+sig   = *:64:0:*:*,*:mss,sok,ts,nop,ws:df,id+:0
+sig   = *:64:0:*:*,*:mss,nop,nop,sok,nop,ws:df:0
+sig   = *:64:0:*:*,*:mss,nop,nop,sok,nop,ws:df,id+:0
+
+; source: the option orders from the Windows signatures above, with any window.
+label = g:win:Windows:
+sig   = *:128:0:*:*,*:mss,nop,ws,sok,ts:df,id+:0
+sig   = *:128:0:*:*,*:mss,nop,ws,nop,nop,sok:df,id+:0
+sig   = *:128:0:*:*,0:mss:df,id+:0
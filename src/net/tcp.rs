@@ -1,41 +1,139 @@
 use std::{
-    io::Write,
-    net::{IpAddr, Ipv4Addr, SocketAddrV4},
-    time::Duration,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::LazyLock,
+    time::{Duration, Instant},
 };
 
 use pnet::{
-    datalink::{self, Channel, Config, DataLinkReceiver, NetworkInterface},
     packet::{
-        ethernet::EthernetPacket,
-        ip::IpNextHeaderProtocols::{self},
-        ipv4::{Ipv4, Ipv4Packet},
-        tcp::{Tcp, TcpFlags, TcpOption, TcpPacket},
-        FromPacket, Packet,
+        ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+        ipv4::{Ipv4Flags, Ipv4Packet},
+        ipv6::Ipv6Packet,
+        tcp::{TcpFlags, TcpOption, TcpOptionNumbers, TcpPacket},
+        Packet,
     },
     util::MacAddr,
 };
-use tracing::warn;
 
-use crate::{net::tcp_template::TemplatePacketRepr, scanner::SourcePort};
+use crate::{config::SendMode, net::tcp_template::TemplatePacketRepr, scanner::SourcePort};
 use crate::net::fingerprint::TcpFingerprint;
 use super::{
-    raw_sockets::RawSocket,
+    interface::{source_index, Network},
+    raw_sockets::{PacketStatistics, RawSocket},
+    receive::PacketReceiver,
     tcp_template::{self, TemplatePacket},
 };
 
 pub const ETH_HEADER_LEN: usize = 14;
+const IPV6_HEADER_LEN: usize = 40;
 
-fn get_interface() -> NetworkInterface {
-    let interface_name = default_net::get_default_interface().unwrap().name;
-    println!("Network interface: {interface_name}");
+static TIMESTAMP_EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
 
-    // Find the network interface with the provided name
-    let interfaces = datalink::interfaces();
-    interfaces
-        .into_iter()
-        .find(|i| i.name == interface_name)
-        .unwrap()
+/// The value we put in the TSval of the TCP timestamp option, which is the
+/// number of milliseconds since the program started. Servers echo it back in
+/// the SYN+ACK, so we can get the RTT without keeping any state.
+pub fn tcp_timestamp() -> u32 {
+    TIMESTAMP_EPOCH.elapsed().as_millis() as u32
+}
+
+/// Get the time since we sent the packet that this one is replying to, from
+/// the TSecr in its timestamp option. None if there's no timestamp option,
+/// which servers leave out when they don't support timestamps.
+pub fn rtt_from_timestamp(tcp: &TcpPacket) -> Option<Duration> {
+    let echoed = timestamp_echo(tcp)?;
+    let rtt = Duration::from_millis(tcp_timestamp().wrapping_sub(echoed) as u64);
+    // anything this big is from a broken server, not a real rtt
+    if rtt > Duration::from_secs(60) {
+        return None;
+    }
+    Some(rtt)
+}
+
+/// The TSecr of the packet's timestamp option, if it has one. 0 is a valid
+/// echo since our TSval starts at 0.
+fn timestamp_echo(tcp: &TcpPacket) -> Option<u32> {
+    let option = tcp
+        .get_options_iter()
+        .find(|option| option.get_number() == TcpOptionNumbers::TIMESTAMPS)?;
+    let echoed = option.payload().get(4..8)?.try_into().ok()?;
+    Some(u32::from_be_bytes(echoed))
+}
+
+/// The parts of an IPv4 or IPv6 header that we care about, which are copied
+/// out so the header doesn't have to be parsed again.
+#[derive(Clone, Copy, Debug)]
+pub struct IpHeader {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    /// The hop limit for IPv6.
+    pub ttl: u8,
+    /// Always 0 for IPv6.
+    pub options_len: u8,
+    /// IPv6 packets are never fragmented by routers, so this is always false
+    /// for them.
+    pub dont_fragment: bool,
+    /// Always 0 for IPv6.
+    pub id: u16,
+    pub ecn: bool,
+    /// Always 0 for IPv4.
+    pub flow_label: u32,
+    /// The IPv4 flag that must be zero.
+    pub reserved_bit: bool,
+}
+
+/// Get the header, the protocol, and the payload of an IPv4 or IPv6 packet
+/// (without the ethernet header). The payload is borrowed from the packet,
+/// unlike with [`Packet::payload`].
+pub(super) fn ip_payload(packet: &[u8]) -> Option<(IpHeader, IpNextHeaderProtocol, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let ipv4 = Ipv4Packet::new(packet)?;
+            let header_len = ipv4.get_header_length() as usize * 4;
+            // there can be padding after the ip packet
+            let total_len = (ipv4.get_total_length() as usize).min(packet.len());
+            let header = IpHeader {
+                source: IpAddr::V4(ipv4.get_source()),
+                destination: IpAddr::V4(ipv4.get_destination()),
+                ttl: ipv4.get_ttl(),
+                options_len: header_len.saturating_sub(20) as u8,
+                dont_fragment: ipv4.get_flags() & Ipv4Flags::DontFragment != 0,
+                id: ipv4.get_identification(),
+                ecn: ipv4.get_ecn() != 0,
+                flow_label: 0,
+                reserved_bit: ipv4.get_flags() & 0b100 != 0,
+            };
+            Some((
+                header,
+                ipv4.get_next_level_protocol(),
+                packet.get(header_len..total_len)?,
+            ))
+        }
+        6 => {
+            let ipv6 = Ipv6Packet::new(packet)?;
+            let total_len =
+                (IPV6_HEADER_LEN + ipv6.get_payload_length() as usize).min(packet.len());
+            let header = IpHeader {
+                source: IpAddr::V6(ipv6.get_source()),
+                destination: IpAddr::V6(ipv6.get_destination()),
+                ttl: ipv6.get_hop_limit(),
+                options_len: 0,
+                dont_fragment: false,
+                id: 0,
+                ecn: ipv6.get_traffic_class() & 0b11 != 0,
+                flow_label: ipv6.get_flow_label(),
+                reserved_bit: false,
+            };
+            // we don't bother with extension headers since servers don't send
+            // them in replies
+            Some((
+                header,
+                ipv6.get_next_header(),
+                packet.get(IPV6_HEADER_LEN..total_len)?,
+            ))
+        }
+        _ => None,
+    }
 }
 
 pub struct StatelessTcp {
@@ -45,10 +143,15 @@ pub struct StatelessTcp {
 
 #[derive(Clone)]
 pub struct StatelessTcpWriteHalf {
-    source_ip: Ipv4Addr,
-    source_port: SourcePort,
+    /// Each target is sent to from one of these, picked with
+    /// [`source_index`].
+    source_ips: Vec<Ipv4Addr>,
+    /// Empty if we don't have a public IPv6 address, IPv6 targets are skipped
+    /// then.
+    source_ipv6s: Vec<Ipv6Addr>,
 
     gateway_mac: Option<MacAddr>,
+    gateway_mac_v6: Option<MacAddr>,
     interface_mac: Option<MacAddr>,
 
     mtu: usize,
@@ -58,16 +161,21 @@ pub struct StatelessTcpWriteHalf {
 
     pub fingerprint: TcpFingerprint,
 
-    template_syn_packet: TemplatePacket,
+    syn_templates: SynTemplates,
 }
 
-pub struct StatelessTcpReadHalf {
-    interface_mac: Option<MacAddr>,
+/// The SYNs that we send, built from a template for every source address.
+#[derive(Clone)]
+pub struct SynTemplates {
     source_port: SourcePort,
+    /// One for every source address, in the same order.
+    v4: Vec<TemplatePacket>,
+    v6: Vec<TemplatePacket>,
+}
 
-    // tx: Box<dyn DataLinkSender>,
-    #[cfg(not(feature = "benchmark"))]
-    rx: Box<dyn DataLinkReceiver>,
+pub struct StatelessTcpReadHalf {
+    /// A separate socket from the write half, with an RX ring.
+    receiver: PacketReceiver,
 }
 
 impl StatelessTcp {
@@ -75,34 +183,9 @@ impl StatelessTcp {
     ///
     /// For the source port I usually do 61000 and then firewall it with
     /// `iptables -A INPUT -p tcp --dport 61000 -j DROP`
-    pub fn new(source_port: SourcePort, fingerprint: TcpFingerprint) -> Self {
-        let interface = get_interface();
-        let gateway_mac = if let Ok(default_gateway) = default_net::get_default_gateway() {
-            Some(MacAddr::from(default_gateway.mac_addr.octets()))
-        } else {
-            None
-        };
-
-        // Create a channel to receive on
-        #[cfg(not(feature = "benchmark"))]
-        let (_tx, rx) = match datalink::channel(
-            &interface,
-            Config {
-                read_timeout: Some(Duration::ZERO),
-                // read_timeout: Some(Duration::from_secs(1)),
-                ..Default::default()
-            },
-            // Config::default(),
-        ) {
-            Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
-            Ok(_) => panic!("unhandled channel type"),
-            Err(e) => panic!("unable to create channel: {}", e),
-        };
-
-        let interface_ipv4 = match interface.ips.iter().find(|ip| ip.is_ipv4()).unwrap().ip() {
-            IpAddr::V4(ip) => ip,
-            IpAddr::V6(_) => panic!("ipv6 not supported"),
-        };
+    pub fn new(network: &Network, source_port: SourcePort, fingerprint: TcpFingerprint) -> Self {
+        let interface = &network.interface;
+        let gateway_mac = network.gateway_mac;
 
         #[cfg(not(feature = "benchmark"))]
         let mut socket = RawSocket::new(&interface.name).unwrap();
@@ -115,73 +198,141 @@ impl StatelessTcp {
         }
 
         let write_half = StatelessTcpWriteHalf {
-            source_ip: interface_ipv4,
-            source_port,
+            source_ips: network.source_ipv4s.clone(),
+            source_ipv6s: network.source_ipv6s.clone(),
 
             gateway_mac,
+            gateway_mac_v6: network.gateway_mac_v6,
             interface_mac,
             mtu,
 
             #[cfg(not(feature = "benchmark"))]
             socket,
 
-            template_syn_packet: TemplatePacket::new(TemplatePacketRepr {
-                flags: TcpFlags::SYN,
-                window: fingerprint.window_size,
-                urgent_ptr: 0,
-                initial_ttl: fingerprint.initial_ttl,
-                options: fingerprint.options.clone(),
-                gateway_mac,
-                interface_mac,
-                source_addr: interface_ipv4,
-            }),
-
+            syn_templates: SynTemplates::new(network, source_port, &fingerprint),
             fingerprint,
         };
 
         StatelessTcp {
             read: StatelessTcpReadHalf {
-                source_port,
-                interface_mac,
-                #[cfg(not(feature = "benchmark"))]
-                rx,
+                receiver: PacketReceiver::new(network, source_port)
+                    .expect("failed to set up the rx ring"),
             },
             write: write_half,
         }
     }
 }
 
-impl StatelessTcpWriteHalf {
-    pub fn mtu(&self) -> u16 {
-        self.mtu as u16
-    }
-    pub fn has_ethernet_header(&self) -> bool {
-        self.gateway_mac.is_some() && self.interface_mac.is_some()
+impl SynTemplates {
+    pub fn new(network: &Network, source_port: SourcePort, fingerprint: &TcpFingerprint) -> Self {
+        let template = |source_addr: IpAddr| {
+            TemplatePacket::new(TemplatePacketRepr {
+                flags: TcpFlags::SYN,
+                window: fingerprint.window_size,
+                urgent_ptr: 0,
+                initial_ttl: fingerprint.initial_ttl,
+                options: fingerprint.options.clone(),
+                gateway_mac: network.gateway_mac_for(source_addr),
+                interface_mac: network.interface.mac,
+                source_addr,
+            })
+        };
+        Self {
+            source_port,
+            v4: network
+                .source_ipv4s
+                .iter()
+                .map(|&ip| template(ip.into()))
+                .collect(),
+            v6: network
+                .source_ipv6s
+                .iter()
+                .map(|&ip| template(ip.into()))
+                .collect(),
+        }
     }
 
-    pub fn send_syn(&mut self, addr: SocketAddrV4, sequence: u32) {
-        let packet = self.template_syn_packet.build(tcp_template::PacketRepr {
-            dest_addr: *addr.ip(),
+    /// Build the SYN to the address, from the source address that every
+    /// packet to it is sent from. None if we don't have an address in the
+    /// same family.
+    pub fn build(&mut self, addr: SocketAddr, sequence: u32) -> Option<&[u8]> {
+        let templates = match addr {
+            SocketAddr::V4(_) => &mut self.v4,
+            SocketAddr::V6(_) => &mut self.v6,
+        };
+        let i = source_index(addr, templates.len());
+        let packet = templates.get_mut(i)?.build(tcp_template::PacketRepr {
+            dest_addr: addr.ip(),
             dest_port: addr.port(),
             sequence,
             acknowledgement: 0,
+            timestamp: tcp_timestamp(),
             payload: &[],
             source_port: self.source_port.pick(sequence),
         });
+        Some(packet)
+    }
+}
 
+impl StatelessTcpWriteHalf {
+    pub fn mtu(&self) -> u16 {
+        self.mtu as u16
+    }
+    /// The first IPv6 address that we send from, if we have one.
+    pub fn source_ipv6(&self) -> Option<Ipv6Addr> {
+        self.source_ipv6s.first().copied()
+    }
+    /// The address we send from to the given address, in the same family.
+    fn source_ip_for(&self, dest_addr: SocketAddr) -> Option<IpAddr> {
+        match dest_addr {
+            SocketAddr::V4(_) => {
+                let i = source_index(dest_addr, self.source_ips.len());
+                Some(self.source_ips[i].into())
+            }
+            SocketAddr::V6(_) => {
+                let i = source_index(dest_addr, self.source_ipv6s.len());
+                self.source_ipv6s.get(i).copied().map(IpAddr::V6)
+            }
+        }
+    }
+    pub fn has_ethernet_header(&self) -> bool {
+        self.gateway_mac.is_some() && self.interface_mac.is_some()
+    }
+
+    /// Change how SYNs are sent, see [`SendMode`]. Other packets are always
+    /// sent right away.
+    pub fn set_send_mode(&mut self, send_mode: SendMode, batch_size: usize) -> io::Result<()> {
         #[cfg(not(feature = "benchmark"))]
-        self.socket.send_blocking(packet);
+        self.socket.set_send_mode(send_mode, batch_size)?;
+        Ok(())
+    }
+
+    /// Queue a SYN to the address. Depending on the send mode it might not be
+    /// sent until [`Self::flush`] is called.
+    pub fn send_syn(&mut self, addr: SocketAddr, sequence: u32) {
+        let Some(packet) = self.syn_templates.build(addr, sequence) else {
+            return;
+        };
+
+        #[cfg(not(feature = "benchmark"))]
+        self.socket.queue(packet);
+    }
+
+    /// Send the SYNs that were queued.
+    pub fn flush(&mut self) {
+        #[cfg(not(feature = "benchmark"))]
+        self.socket.flush();
     }
 
     pub fn send_ack(
         &mut self,
-        addr: SocketAddrV4,
+        addr: SocketAddr,
         source_port: u16,
         sequence: u32,
         acknowledgement: u32,
     ) {
         self.send_tcp(PacketRepr {
-            dest_addr: *addr.ip(),
+            dest_addr: addr.ip(),
             dest_port: addr.port(),
             sequence,
             acknowledgement,
@@ -196,13 +347,13 @@ impl StatelessTcpWriteHalf {
 
     pub fn send_rst(
         &mut self,
-        addr: SocketAddrV4,
+        addr: SocketAddr,
         source_port: u16,
         sequence: u32,
         acknowledgement: u32,
     ) {
         self.send_tcp(PacketRepr {
-            dest_addr: *addr.ip(),
+            dest_addr: addr.ip(),
             dest_port: addr.port(),
             source_port,
             sequence,
@@ -217,13 +368,13 @@ impl StatelessTcpWriteHalf {
 
     pub fn send_fin(
         &mut self,
-        addr: SocketAddrV4,
+        addr: SocketAddr,
         source_port: u16,
         sequence: u32,
         acknowledgement: u32,
     ) {
         self.send_tcp(PacketRepr {
-            dest_addr: *addr.ip(),
+            dest_addr: addr.ip(),
             dest_port: addr.port(),
             source_port,
             sequence,
@@ -238,14 +389,14 @@ impl StatelessTcpWriteHalf {
 
     pub fn send_data(
         &mut self,
-        addr: SocketAddrV4,
+        addr: SocketAddr,
         source_port: u16,
         sequence: u32,
         acknowledgement: u32,
         payload: &[u8],
     ) {
         self.send_tcp(PacketRepr {
-            dest_addr: *addr.ip(),
+            dest_addr: addr.ip(),
             dest_port: addr.port(),
             source_port,
             sequence,
@@ -259,8 +410,16 @@ impl StatelessTcpWriteHalf {
     }
 
     pub fn send_tcp(&mut self, repr: PacketRepr) {
-        let source_addr = SocketAddrV4::new(self.source_ip, repr.source_port);
-        let packet = build_tcp_packet(repr, self.gateway_mac, self.interface_mac, source_addr, &self.fingerprint);
+        let dest_addr = SocketAddr::new(repr.dest_addr, repr.dest_port);
+        let Some(source_ip) = self.source_ip_for(dest_addr) else {
+            return;
+        };
+        let source_addr = SocketAddr::new(source_ip, repr.source_port);
+        let gateway_mac = match source_ip {
+            IpAddr::V4(_) => self.gateway_mac,
+            IpAddr::V6(_) => self.gateway_mac_v6,
+        };
+        let packet = build_tcp_packet(repr, gateway_mac, self.interface_mac, source_addr, &self.fingerprint);
         #[cfg(not(feature = "benchmark"))]
         self.socket.send_blocking(&packet);
     }
@@ -270,7 +429,7 @@ fn build_tcp_packet(
     repr: PacketRepr,
     gateway_mac: Option<MacAddr>,
     interface_mac: Option<MacAddr>,
-    source_addr: SocketAddrV4,
+    source_addr: SocketAddr,
     fingerprint: &TcpFingerprint,
 ) -> Vec<u8> {
     let mut template = TemplatePacket::new(TemplatePacketRepr {
@@ -280,7 +439,7 @@ fn build_tcp_packet(
         options: repr.options.to_vec(),
         gateway_mac,
         interface_mac,
-        source_addr: *source_addr.ip(),
+        source_addr: source_addr.ip(),
         initial_ttl: fingerprint.initial_ttl
     });
     template
@@ -290,44 +449,47 @@ fn build_tcp_packet(
             source_port: repr.source_port,
             sequence: repr.sequence,
             acknowledgement: repr.acknowledgement,
+            timestamp: tcp_timestamp(),
             payload: repr.payload,
         })
         .to_vec()
 }
 
 impl StatelessTcpReadHalf {
-    pub fn recv(&mut self) -> Option<(Ipv4, Tcp)> {
-        #[cfg(not(feature = "benchmark"))]
-        loop {
-            match self.rx.next() {
-                Ok(packet) => {
-                    let payload_for_ipv4 = if self.interface_mac.is_some() {
-                        let ethernet = EthernetPacket::new(packet).unwrap();
-                        ethernet.payload().to_vec()
-                    } else {
-                        // no interface mac = no ethernet header
-                        packet.to_vec()
-                    };
-
-                    if let Some(ipv4) = Ipv4Packet::new(&payload_for_ipv4) {
-                        if let Some(tcp) = process_ipv4(&ipv4) {
-                            if self.source_port.contains(tcp.destination) {
-                                return Some((ipv4.from_packet(), tcp));
-                            }
-                        }
-                    }
+    /// Wait up to `timeout` for packets, then call `f` with every TCP packet
+    /// that was sent to us and its IP header. The packets are parsed in
+    /// place, so nothing is copied.
+    pub fn recv_with(&mut self, timeout: Duration, mut f: impl FnMut(&IpHeader, &TcpPacket)) {
+        self.receiver
+            .recv_with(timeout, IpNextHeaderProtocols::Tcp, |header, packet| {
+                if let Some(tcp) = TcpPacket::new(packet) {
+                    f(header, &tcp);
                 }
-                Err(_) => return None,
-            }
-        }
-        #[cfg(feature = "benchmark")]
-        None
+            });
+    }
+
+    /// Split this into `count` read halves with PACKET_FANOUT, so each one
+    /// gets a share of the packets. Packets from the same connection always
+    /// go to the same read half.
+    pub fn fanout(self, count: usize) -> io::Result<Vec<StatelessTcpReadHalf>> {
+        Ok(self
+            .receiver
+            .fanout(count)?
+            .into_iter()
+            .map(|receiver| StatelessTcpReadHalf { receiver })
+            .collect())
+    }
+
+    /// The number of packets that the kernel received and dropped since the
+    /// last time this was called.
+    pub fn statistics(&mut self) -> Option<PacketStatistics> {
+        self.receiver.statistics()
     }
 }
 
 #[derive(Debug)]
 pub struct PacketRepr<'a> {
-    pub dest_addr: Ipv4Addr,
+    pub dest_addr: IpAddr,
     pub dest_port: u16,
 
     pub source_port: u16,
@@ -340,28 +502,3 @@ pub struct PacketRepr<'a> {
     pub options: &'a [TcpOption],
     pub payload: &'a [u8],
 }
-
-fn process_ipv4(ipv4: &Ipv4Packet) -> Option<Tcp> {
-    match ipv4.get_next_level_protocol() {
-        IpNextHeaderProtocols::Tcp => {
-            if let Some(tcp) = TcpPacket::new(ipv4.payload()) {
-                std::io::stdout().flush().unwrap();
-                Some(tcp.from_packet())
-            } else {
-                None
-            }
-        }
-        IpNextHeaderProtocols::Ipv4 => {
-            if let Some(ipv4) = Ipv4Packet::new(ipv4.payload()) {
-                process_ipv4(&ipv4)
-            } else {
-                None
-            }
-        }
-        IpNextHeaderProtocols::IpComp => {
-            warn!("Recieved an IpComp packet, but it's not supported.");
-            None
-        }
-        _ => None,
-    }
-}
@@ -1,11 +1,12 @@
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use pnet::{
     packet::{
         ethernet::{EtherTypes, Ethernet, MutableEthernetPacket},
         ip::IpNextHeaderProtocols,
         ipv4::{self, MutableIpv4Packet},
-        tcp::{MutableTcpPacket, TcpOption, TcpOptionPacket},
+        ipv6::MutableIpv6Packet,
+        tcp::{MutableTcpPacket, TcpOption, TcpOptionNumbers, TcpOptionPacket},
     },
     util::MacAddr,
 };
@@ -18,15 +19,21 @@ pub struct TemplatePacket {
     packet: Vec<u8>,
 
     // source addr needs to be stored for the checksum
-    source_addr: Ipv4Addr,
+    source_addr: IpAddr,
 
     eth_header_len: usize,
-    // ipv4 header length is constant (20 bytes) so it doesn't need to be here
-    // ipv4_header_len: usize,
+    /// 20 bytes for ipv4 and 40 for ipv6, we never send ip options or
+    /// extension headers.
+    ip_header_len: usize,
     tcp_header_len: usize,
+
+    /// Where the TSval of the timestamp option is in the TCP header, if we're
+    /// sending one.
+    timestamp_offset: Option<usize>,
 }
 
 const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
 
 /// Parts of a packet that will be the same for every packet
 pub struct TemplatePacketRepr {
@@ -38,16 +45,22 @@ pub struct TemplatePacketRepr {
 
     pub gateway_mac: Option<MacAddr>,
     pub interface_mac: Option<MacAddr>,
-    pub source_addr: Ipv4Addr,
+    /// Whether this is an IPv4 or IPv6 template depends on the family of the
+    /// source address.
+    pub source_addr: IpAddr,
 }
 
 /// Parts of a packet that will be different for every packet
 pub struct PacketRepr<'a> {
-    pub dest_addr: Ipv4Addr,
+    /// Must be the same family as the template's source address.
+    pub dest_addr: IpAddr,
     pub dest_port: u16,
     pub source_port: u16,
     pub sequence: u32,
     pub acknowledgement: u32,
+    /// The TSval that's written in the timestamp option, this is ignored if
+    /// the template doesn't have one.
+    pub timestamp: u32,
     pub payload: &'a [u8],
 }
 
@@ -60,16 +73,31 @@ impl TemplatePacket {
         // divide by 4 and round up
         let tcp_header_len: usize = 20 + tcp_options_length_as_words * 4;
 
+        let mut timestamp_offset = None;
+        let mut option_offset = 20;
+        for option in &repr.options {
+            if option.number == TcpOptionNumbers::TIMESTAMPS {
+                // skip the kind and length
+                timestamp_offset = Some(option_offset + 2);
+            }
+            option_offset += TcpOptionPacket::packet_size(option);
+        }
+
         let eth_header_len = if repr.gateway_mac.is_some() {
             ETH_HEADER_LEN
         } else {
             0
         };
 
-        let mut packet = vec![0u8; eth_header_len + IPV4_HEADER_LEN + tcp_header_len];
+        let ip_header_len = match repr.source_addr {
+            IpAddr::V4(_) => IPV4_HEADER_LEN,
+            IpAddr::V6(_) => IPV6_HEADER_LEN,
+        };
+
+        let mut packet = vec![0u8; eth_header_len + ip_header_len + tcp_header_len];
 
         let mut mutable_tcp_packet =
-            MutableTcpPacket::new(&mut packet[eth_header_len + IPV4_HEADER_LEN..]).unwrap();
+            MutableTcpPacket::new(&mut packet[eth_header_len + ip_header_len..]).unwrap();
         mutable_tcp_packet.set_data_offset(5 + tcp_options_length_as_words as u8);
         mutable_tcp_packet.set_reserved(0);
         mutable_tcp_packet.set_flags(repr.flags);
@@ -77,36 +105,57 @@ impl TemplatePacket {
         mutable_tcp_packet.set_urgent_ptr(repr.urgent_ptr);
         mutable_tcp_packet.set_options(&repr.options);
 
-        // IPv4
         assert_eq!(
             packet[..packet.len() - tcp_header_len],
-            vec![0u8; eth_header_len + IPV4_HEADER_LEN]
+            vec![0u8; eth_header_len + ip_header_len]
         );
-        let mut mutable_ipv4_packet: MutableIpv4Packet =
-            MutableIpv4Packet::new(&mut packet[eth_header_len..]).unwrap();
-
-        mutable_ipv4_packet.set_version(4);
-        mutable_ipv4_packet.set_header_length(5);
-        mutable_ipv4_packet.set_dscp(0);
-        mutable_ipv4_packet.set_ecn(0);
-        mutable_ipv4_packet.set_identification(1);
-        mutable_ipv4_packet.set_flags(0b010);
-        mutable_ipv4_packet.set_fragment_offset(0);
-        mutable_ipv4_packet.set_ttl(repr.initial_ttl);
-        mutable_ipv4_packet.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
-        mutable_ipv4_packet.set_source(repr.source_addr);
-        mutable_ipv4_packet.set_options(&[]);
+        match repr.source_addr {
+            IpAddr::V4(source_addr) => {
+                // IPv4
+                let mut mutable_ipv4_packet: MutableIpv4Packet =
+                    MutableIpv4Packet::new(&mut packet[eth_header_len..]).unwrap();
+
+                mutable_ipv4_packet.set_version(4);
+                mutable_ipv4_packet.set_header_length(5);
+                mutable_ipv4_packet.set_dscp(0);
+                mutable_ipv4_packet.set_ecn(0);
+                mutable_ipv4_packet.set_identification(1);
+                mutable_ipv4_packet.set_flags(0b010);
+                mutable_ipv4_packet.set_fragment_offset(0);
+                mutable_ipv4_packet.set_ttl(repr.initial_ttl);
+                mutable_ipv4_packet.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+                mutable_ipv4_packet.set_source(source_addr);
+                mutable_ipv4_packet.set_options(&[]);
+            }
+            IpAddr::V6(source_addr) => {
+                // IPv6
+                let mut mutable_ipv6_packet: MutableIpv6Packet =
+                    MutableIpv6Packet::new(&mut packet[eth_header_len..]).unwrap();
+
+                mutable_ipv6_packet.set_version(6);
+                mutable_ipv6_packet.set_traffic_class(0);
+                mutable_ipv6_packet.set_flow_label(0);
+                mutable_ipv6_packet.set_next_header(IpNextHeaderProtocols::Tcp);
+                // the hop limit is the ipv6 equivalent of the ttl
+                mutable_ipv6_packet.set_hop_limit(repr.initial_ttl);
+                mutable_ipv6_packet.set_source(source_addr);
+            }
+        }
 
         if eth_header_len > 0 {
             // Ethernet
             let ethernet_packet = Ethernet {
                 destination: repr.gateway_mac.unwrap(),
                 source: repr.interface_mac.unwrap(),
-                ethertype: EtherTypes::Ipv4,
+                ethertype: if repr.source_addr.is_ipv4() {
+                    EtherTypes::Ipv4
+                } else {
+                    EtherTypes::Ipv6
+                },
                 payload: vec![],
             };
             assert_eq!(
-                packet[..packet.len() - tcp_header_len - IPV4_HEADER_LEN],
+                packet[..packet.len() - tcp_header_len - ip_header_len],
                 vec![0u8; eth_header_len]
             );
             let mut mutable_ethernet_packet = MutableEthernetPacket::new(&mut packet).unwrap();
@@ -119,20 +168,27 @@ impl TemplatePacket {
             packet,
             source_addr: repr.source_addr,
             eth_header_len,
+            ip_header_len,
             tcp_header_len,
+            timestamp_offset,
         }
     }
 
     /// Build the packet with the given options
     pub fn build(&mut self, repr: PacketRepr) -> &[u8] {
         self.packet.resize(
-            self.eth_header_len + IPV4_HEADER_LEN + self.tcp_header_len + repr.payload.len(),
+            self.eth_header_len + self.ip_header_len + self.tcp_header_len + repr.payload.len(),
             0,
         );
 
+        if let Some(timestamp_offset) = self.timestamp_offset {
+            let start = self.eth_header_len + self.ip_header_len + timestamp_offset;
+            self.packet[start..start + 4].copy_from_slice(&repr.timestamp.to_be_bytes());
+        }
+
         // TCP
         let mut mutable_tcp_packet =
-            MutableTcpPacket::new(&mut self.packet[self.eth_header_len + IPV4_HEADER_LEN..])
+            MutableTcpPacket::new(&mut self.packet[self.eth_header_len + self.ip_header_len..])
                 .unwrap();
         mutable_tcp_packet.set_source(repr.source_port);
         mutable_tcp_packet.set_destination(repr.dest_port);
@@ -141,21 +197,44 @@ impl TemplatePacket {
         if !repr.payload.is_empty() {
             mutable_tcp_packet.payload_mut()[..repr.payload.len()].copy_from_slice(repr.payload);
         }
-        let checksum = pnet::packet::tcp::ipv4_checksum(
-            &mutable_tcp_packet.to_immutable(),
-            &self.source_addr,
-            &repr.dest_addr,
-        );
-        mutable_tcp_packet.set_checksum(checksum);
-
-        // IPv4
-        let mut mutable_ipv4_packet: MutableIpv4Packet =
-            MutableIpv4Packet::new(&mut self.packet[self.eth_header_len..]).unwrap();
-        mutable_ipv4_packet.set_destination(repr.dest_addr);
-        mutable_ipv4_packet
-            .set_total_length((IPV4_HEADER_LEN + self.tcp_header_len + repr.payload.len()) as u16);
-
-        mutable_ipv4_packet.set_checksum(ipv4::checksum(&mutable_ipv4_packet.to_immutable()));
+        match (self.source_addr, repr.dest_addr) {
+            (IpAddr::V4(source_addr), IpAddr::V4(dest_addr)) => {
+                let checksum = pnet::packet::tcp::ipv4_checksum(
+                    &mutable_tcp_packet.to_immutable(),
+                    &source_addr,
+                    &dest_addr,
+                );
+                mutable_tcp_packet.set_checksum(checksum);
+
+                // IPv4
+                let mut mutable_ipv4_packet: MutableIpv4Packet =
+                    MutableIpv4Packet::new(&mut self.packet[self.eth_header_len..]).unwrap();
+                mutable_ipv4_packet.set_destination(dest_addr);
+                mutable_ipv4_packet.set_total_length(
+                    (IPV4_HEADER_LEN + self.tcp_header_len + repr.payload.len()) as u16,
+                );
+
+                mutable_ipv4_packet
+                    .set_checksum(ipv4::checksum(&mutable_ipv4_packet.to_immutable()));
+            }
+            (IpAddr::V6(source_addr), IpAddr::V6(dest_addr)) => {
+                let checksum = pnet::packet::tcp::ipv6_checksum(
+                    &mutable_tcp_packet.to_immutable(),
+                    &source_addr,
+                    &dest_addr,
+                );
+                mutable_tcp_packet.set_checksum(checksum);
+
+                // IPv6, there's no header checksum
+                let mut mutable_ipv6_packet: MutableIpv6Packet =
+                    MutableIpv6Packet::new(&mut self.packet[self.eth_header_len..]).unwrap();
+                mutable_ipv6_packet.set_destination(dest_addr);
+                // unlike ipv4's total length, this doesn't include the ip header
+                mutable_ipv6_packet
+                    .set_payload_length((self.tcp_header_len + repr.payload.len()) as u16);
+            }
+            _ => panic!("the destination address must be the same family as the template"),
+        }
 
         // the ethernet fields are already good
         &self.packet
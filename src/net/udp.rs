@@ -0,0 +1,142 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use pnet::packet::{ip::IpNextHeaderProtocols, udp::UdpPacket};
+
+use super::{
+    fingerprint::TcpFingerprint,
+    interface::{source_index, Network},
+    raw_sockets::{PacketStatistics, RawSocket},
+    receive::PacketReceiver,
+    tcp::IpHeader,
+    udp_template::{self, TemplatePacket, TemplatePacketRepr},
+};
+use crate::{config::FingerprintConfig, scanner::SourcePort};
+
+/// The UDP equivalent of [`StatelessTcp`](super::tcp::StatelessTcp). There's
+/// no handshake, so it's up to the protocol to put a cookie in the payload
+/// that the server will echo back.
+pub struct StatelessUdp {
+    pub read: StatelessUdpReadHalf,
+    pub write: StatelessUdpWriteHalf,
+}
+
+#[derive(Clone)]
+pub struct StatelessUdpWriteHalf {
+    source_port: SourcePort,
+
+    #[cfg(not(feature = "benchmark"))]
+    socket: RawSocket,
+
+    /// One for every source address, picked with [`source_index`].
+    template_packets: Vec<TemplatePacket>,
+    /// Empty if we don't have a public IPv6 address.
+    template_packets_v6: Vec<TemplatePacket>,
+}
+
+pub struct StatelessUdpReadHalf {
+    /// A separate socket from the write half, with an RX ring.
+    receiver: PacketReceiver,
+}
+
+impl StatelessUdp {
+    /// Create a new stateless UDP instance.
+    ///
+    /// Like with TCP, the source port should be firewalled with something like
+    /// `iptables -A INPUT -p udp --dport 61000 -j DROP` so the OS doesn't
+    /// reply with ICMP port unreachable. The fingerprint is only used for the
+    /// TTL, so our UDP packets look like they're from the same OS as the SYNs.
+    pub fn new(
+        network: &Network,
+        source_port: SourcePort,
+        fingerprint_config: Option<FingerprintConfig>,
+    ) -> Self {
+        let initial_ttl = match fingerprint_config {
+            Some(cfg) => TcpFingerprint::parse_signature(&cfg.signature, cfg.mss).initial_ttl,
+            None => TcpFingerprint::default().initial_ttl,
+        };
+
+        let interface = &network.interface;
+
+        #[cfg(not(feature = "benchmark"))]
+        let socket = RawSocket::new(&interface.name).unwrap();
+
+        let interface_mac = interface.mac;
+        let template_packet = |source_addr: IpAddr| {
+            TemplatePacket::new(TemplatePacketRepr {
+                initial_ttl,
+                gateway_mac: network.gateway_mac_for(source_addr),
+                interface_mac,
+                source_addr,
+            })
+        };
+
+        StatelessUdp {
+            read: StatelessUdpReadHalf {
+                receiver: PacketReceiver::new(network, source_port)
+                    .expect("failed to set up the rx ring"),
+            },
+            write: StatelessUdpWriteHalf {
+                source_port,
+                #[cfg(not(feature = "benchmark"))]
+                socket,
+                template_packets: network
+                    .source_ipv4s
+                    .iter()
+                    .map(|&ip| template_packet(ip.into()))
+                    .collect(),
+                template_packets_v6: network
+                    .source_ipv6s
+                    .iter()
+                    .map(|&ip| template_packet(ip.into()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl StatelessUdpWriteHalf {
+    /// Send a datagram to the given address. The source port is picked with
+    /// the cookie, the same way as for SYNs.
+    pub fn send_udp(&mut self, addr: SocketAddr, cookie: u32, payload: &[u8]) {
+        let templates = match addr {
+            SocketAddr::V4(_) => &mut self.template_packets,
+            SocketAddr::V6(_) => &mut self.template_packets_v6,
+        };
+        let i = source_index(addr, templates.len());
+        let Some(template) = templates.get_mut(i) else {
+            return;
+        };
+        let packet = template.build(udp_template::PacketRepr {
+            dest_addr: addr.ip(),
+            dest_port: addr.port(),
+            source_port: self.source_port.pick(cookie),
+            payload,
+        });
+
+        #[cfg(not(feature = "benchmark"))]
+        self.socket.send_blocking(packet);
+    }
+}
+
+impl StatelessUdpReadHalf {
+    /// Wait up to `timeout` for datagrams, then call `f` with every one that
+    /// was sent to us and its IP header. They're parsed in place, so nothing
+    /// is copied.
+    pub fn recv_with(&mut self, timeout: Duration, mut f: impl FnMut(&IpHeader, &UdpPacket)) {
+        self.receiver
+            .recv_with(timeout, IpNextHeaderProtocols::Udp, |header, packet| {
+                if let Some(udp) = UdpPacket::new(packet) {
+                    f(header, &udp);
+                }
+            });
+    }
+
+    /// The number of packets that the kernel received and dropped since the
+    /// last time this was called.
+    pub fn statistics(&mut self) -> Option<PacketStatistics> {
+        self.receiver.statistics()
+    }
+}
@@ -0,0 +1,170 @@
+use std::net::IpAddr;
+
+use pnet::{
+    packet::{
+        ethernet::{EtherTypes, MutableEthernetPacket},
+        ip::IpNextHeaderProtocols,
+        ipv4::{self, MutableIpv4Packet},
+        ipv6::MutableIpv6Packet,
+        udp::MutableUdpPacket,
+    },
+    util::MacAddr,
+};
+use pnet_macros_support::packet::MutablePacket;
+
+use crate::net::tcp::ETH_HEADER_LEN;
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+
+#[derive(Clone)]
+pub struct TemplatePacket {
+    packet: Vec<u8>,
+
+    // source addr needs to be stored for the checksum
+    source_addr: IpAddr,
+
+    eth_header_len: usize,
+    ip_header_len: usize,
+}
+
+/// Parts of a packet that will be the same for every packet
+pub struct TemplatePacketRepr {
+    pub initial_ttl: u8,
+
+    pub gateway_mac: Option<MacAddr>,
+    pub interface_mac: Option<MacAddr>,
+    /// Whether this is an IPv4 or IPv6 template depends on the family of the
+    /// source address.
+    pub source_addr: IpAddr,
+}
+
+/// Parts of a packet that will be different for every packet
+pub struct PacketRepr<'a> {
+    /// Must be the same family as the template's source address.
+    pub dest_addr: IpAddr,
+    pub dest_port: u16,
+    pub source_port: u16,
+    pub payload: &'a [u8],
+}
+
+impl TemplatePacket {
+    pub fn new(repr: TemplatePacketRepr) -> Self {
+        let eth_header_len = if repr.gateway_mac.is_some() {
+            ETH_HEADER_LEN
+        } else {
+            0
+        };
+
+        let ip_header_len = match repr.source_addr {
+            IpAddr::V4(_) => IPV4_HEADER_LEN,
+            IpAddr::V6(_) => IPV6_HEADER_LEN,
+        };
+
+        let mut packet = vec![0u8; eth_header_len + ip_header_len + UDP_HEADER_LEN];
+
+        match repr.source_addr {
+            IpAddr::V4(source_addr) => {
+                // IPv4
+                let mut mutable_ipv4_packet: MutableIpv4Packet =
+                    MutableIpv4Packet::new(&mut packet[eth_header_len..]).unwrap();
+                mutable_ipv4_packet.set_version(4);
+                mutable_ipv4_packet.set_header_length(5);
+                mutable_ipv4_packet.set_dscp(0);
+                mutable_ipv4_packet.set_ecn(0);
+                mutable_ipv4_packet.set_identification(1);
+                mutable_ipv4_packet.set_flags(0b010);
+                mutable_ipv4_packet.set_fragment_offset(0);
+                mutable_ipv4_packet.set_ttl(repr.initial_ttl);
+                mutable_ipv4_packet.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+                mutable_ipv4_packet.set_source(source_addr);
+                mutable_ipv4_packet.set_options(&[]);
+            }
+            IpAddr::V6(source_addr) => {
+                // IPv6
+                let mut mutable_ipv6_packet: MutableIpv6Packet =
+                    MutableIpv6Packet::new(&mut packet[eth_header_len..]).unwrap();
+                mutable_ipv6_packet.set_version(6);
+                mutable_ipv6_packet.set_traffic_class(0);
+                mutable_ipv6_packet.set_flow_label(0);
+                mutable_ipv6_packet.set_next_header(IpNextHeaderProtocols::Udp);
+                mutable_ipv6_packet.set_hop_limit(repr.initial_ttl);
+                mutable_ipv6_packet.set_source(source_addr);
+            }
+        }
+
+        if eth_header_len > 0 {
+            // Ethernet
+            let mut mutable_ethernet_packet = MutableEthernetPacket::new(&mut packet).unwrap();
+            mutable_ethernet_packet.set_destination(repr.gateway_mac.unwrap());
+            mutable_ethernet_packet.set_source(repr.interface_mac.unwrap());
+            mutable_ethernet_packet.set_ethertype(if repr.source_addr.is_ipv4() {
+                EtherTypes::Ipv4
+            } else {
+                EtherTypes::Ipv6
+            });
+        }
+
+        TemplatePacket {
+            packet,
+            source_addr: repr.source_addr,
+            eth_header_len,
+            ip_header_len,
+        }
+    }
+
+    /// Build the packet with the given options
+    pub fn build(&mut self, repr: PacketRepr) -> &[u8] {
+        let udp_len = UDP_HEADER_LEN + repr.payload.len();
+        self.packet
+            .resize(self.eth_header_len + self.ip_header_len + udp_len, 0);
+
+        // UDP
+        let mut mutable_udp_packet =
+            MutableUdpPacket::new(&mut self.packet[self.eth_header_len + self.ip_header_len..])
+                .unwrap();
+        mutable_udp_packet.set_source(repr.source_port);
+        mutable_udp_packet.set_destination(repr.dest_port);
+        mutable_udp_packet.set_length(udp_len as u16);
+        mutable_udp_packet.payload_mut().copy_from_slice(repr.payload);
+        match (self.source_addr, repr.dest_addr) {
+            (IpAddr::V4(source_addr), IpAddr::V4(dest_addr)) => {
+                let checksum = pnet::packet::udp::ipv4_checksum(
+                    &mutable_udp_packet.to_immutable(),
+                    &source_addr,
+                    &dest_addr,
+                );
+                mutable_udp_packet.set_checksum(checksum);
+
+                // IPv4
+                let mut mutable_ipv4_packet: MutableIpv4Packet =
+                    MutableIpv4Packet::new(&mut self.packet[self.eth_header_len..]).unwrap();
+                mutable_ipv4_packet.set_destination(dest_addr);
+                mutable_ipv4_packet.set_total_length((IPV4_HEADER_LEN + udp_len) as u16);
+
+                mutable_ipv4_packet
+                    .set_checksum(ipv4::checksum(&mutable_ipv4_packet.to_immutable()));
+            }
+            (IpAddr::V6(source_addr), IpAddr::V6(dest_addr)) => {
+                // the checksum is required for udp over ipv6
+                let checksum = pnet::packet::udp::ipv6_checksum(
+                    &mutable_udp_packet.to_immutable(),
+                    &source_addr,
+                    &dest_addr,
+                );
+                mutable_udp_packet.set_checksum(checksum);
+
+                // IPv6
+                let mut mutable_ipv6_packet: MutableIpv6Packet =
+                    MutableIpv6Packet::new(&mut self.packet[self.eth_header_len..]).unwrap();
+                mutable_ipv6_packet.set_destination(dest_addr);
+                mutable_ipv6_packet.set_payload_length(udp_len as u16);
+            }
+            _ => panic!("the destination address must be the same family as the template"),
+        }
+
+        // the ethernet fields are already good
+        &self.packet
+    }
+}
@@ -1,14 +1,26 @@
+pub mod bedrock;
+pub mod beta_minecraft;
+pub mod generic_probe;
+pub mod geyser;
+pub mod http;
+pub mod legacy_minecraft;
 pub mod minecraft;
 pub mod minecraft_fingerprinting;
+pub mod minecraft_forge;
+pub mod minecraft_login;
+pub mod minecraft_protocol_range;
+pub mod minecraft_proxy_protocol;
+pub mod query;
+pub mod rcon;
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     mem,
-    net::SocketAddrV4,
+    net::SocketAddr,
     sync::Arc,
     time::{Duration, SystemTime},
 };
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use async_trait::async_trait;
 use bson::{doc, Bson};
 use parking_lot::Mutex;
@@ -17,20 +29,45 @@ use tracing::trace;
 use crate::{
     config::Config,
     database::{self, bulk_write::CollectionExt, Database},
+    scanner::protocols::{self, ConnectionInfo},
     terminal_colors::*,
-    metrics::SERVERS_FOUND_COUNTER
+    metrics::{LATENCY_HISTOGRAM, SERVERS_FOUND_COUNTER},
+};
+use crate::metrics::{
+    SERVERS_BEDROCK_COUNTER, SERVERS_FINGERPRINTED_COUNTER, SERVERS_FORGE_COUNTER,
+    SERVERS_LEGACY_COUNTER, SERVERS_LOGIN_COUNTER, SERVERS_PROTOCOL_RANGE_COUNTER,
+    SERVERS_QUERIED_COUNTER, SERVERS_RCON_COUNTER, SERVERS_RESCANNED_COUNTER,
+    SERVERS_GEYSER_COUNTER, SERVERS_FLOODGATE_COUNTER, SERVERS_PROBED_COUNTER, SERVERS_PROXY_PROTOCOL_COUNTER,
+    SERVERS_TRANSFER_COUNTER, SERVERS_WEBMAP_COUNTER, SERVERS_IPV6_COUNTER,
 };
-use crate::metrics::{SERVERS_FINGERPRINTED_COUNTER, SERVERS_RESCANNED_COUNTER};
 use crate::modes::{ModeCategory, ScanMode};
 
 pub struct SharedData {
     pub database: Database,
     /// The queue of servers to process, along with their server list ping
-    /// response.
-    pub queue: VecDeque<(SocketAddrV4, Vec<u8>)>,
+    /// response and timing information about the connection.
+    pub queue: VecDeque<(SocketAddr, Vec<u8>, ConnectionInfo)>,
     /// Data from the previous scan, used for identifying players that just
     /// joined or left a server.
-    pub cached_servers: HashMap<SocketAddrV4, serde_json::Value>,
+    pub cached_servers: HashMap<SocketAddr, serde_json::Value>,
+    /// Targets that replied with a SYN+ACK but haven't sent a valid response
+    /// yet. This is only filled if the receiver is tracking them, and only up
+    /// to [`MAX_SILENT_TARGETS`](crate::scanner::MAX_SILENT_TARGETS).
+    pub silent_targets: HashSet<SocketAddr>,
+    /// What the targets of the current proxy fingerprinting scan had in their
+    /// status.
+    pub known_statuses: HashMap<SocketAddr, minecraft_fingerprinting::KnownStatus>,
+    /// The IPs of the current geyser scan, along with the clean descriptions
+    /// of the Java servers on them.
+    pub geyser_targets: HashMap<IpAddr, Vec<String>>,
+    /// The IPs of the current geyser scan that replied on any port.
+    pub geyser_answered: HashSet<IpAddr>,
+    /// The probe from the config that's currently being run, which has the
+    /// regexes for the fields.
+    pub generic_probe: Option<Arc<protocols::GenericProbe>>,
+    /// The hostnames from `target.virtual_hosts` and the addresses they
+    /// resolved to, which are stored when we find a server on them.
+    pub virtual_hosts: HashMap<SocketAddr, String>,
 
     pub total_new: usize,
     pub total_new_on_default_port: usize,
@@ -46,10 +83,13 @@ pub struct SharedData {
 
 #[async_trait]
 pub trait ProcessableProtocol: Send + 'static {
+    /// The collection that the bulk updates are written to.
+    const COLLECTION: &'static str = "servers";
+
     fn process(
         shared: &Arc<Mutex<SharedData>>,
         config: &Config,
-        target: SocketAddrV4,
+        target: SocketAddr,
         data: &[u8],
         database: &Database,
     ) -> Option<database::bulk_write::BulkUpdate>;
@@ -72,10 +112,13 @@ where
 
         let mut bulk_updates: Vec<database::bulk_write::BulkUpdate> = Vec::new();
         let updating = shared.lock().queue.drain(..).collect::<Vec<_>>();
-        for (target, data) in updating {
-            let Some(bulk_update) = P::process(&shared, &config, target, &data, &database) else {
+        for (target, data, info) in updating {
+            let Some(mut bulk_update) = P::process(&shared, &config, target, &data, &database)
+            else {
                 continue;
             };
+            add_latency(&mut bulk_update, info);
+            add_tcp_fingerprint(&mut bulk_update, info);
 
             let mode = format!("{:?}", shared.lock().mode.unwrap_or(ScanMode::Slash0));
             if let Some(category) = shared.lock().category {
@@ -89,14 +132,56 @@ where
                     ModeCategory::Fingerprint => {
                         SERVERS_FINGERPRINTED_COUNTER.inc();
                     }
+                    ModeCategory::Legacy => {
+                        SERVERS_LEGACY_COUNTER.inc();
+                    }
+                    ModeCategory::Bedrock => {
+                        SERVERS_BEDROCK_COUNTER.inc();
+                    }
+                    ModeCategory::Query => {
+                        SERVERS_QUERIED_COUNTER.inc();
+                    }
+                    ModeCategory::Login => {
+                        SERVERS_LOGIN_COUNTER.inc();
+                    }
+                    ModeCategory::ProtocolRange => {
+                        SERVERS_PROTOCOL_RANGE_COUNTER.inc();
+                    }
+                    ModeCategory::Forge => {
+                        SERVERS_FORGE_COUNTER.inc();
+                    }
+                    ModeCategory::Rcon => {
+                        SERVERS_RCON_COUNTER.inc();
+                    }
+                    ModeCategory::Webmap => {
+                        SERVERS_WEBMAP_COUNTER.inc();
+                    }
+                    ModeCategory::GenericProbe => {
+                        SERVERS_PROBED_COUNTER.inc();
+                    }
+                    ModeCategory::Geyser => {
+                        SERVERS_GEYSER_COUNTER.inc();
+                    }
+                    ModeCategory::Floodgate => {
+                        SERVERS_FLOODGATE_COUNTER.inc();
+                    }
+                    ModeCategory::ProxyProtocol => {
+                        SERVERS_PROXY_PROTOCOL_COUNTER.inc();
+                    }
+                    ModeCategory::Transfer => {
+                        SERVERS_TRANSFER_COUNTER.inc();
+                    }
+                    ModeCategory::Ipv6 => {
+                        SERVERS_IPV6_COUNTER.inc();
+                    }
                 }
             }
 
             // check if there's already a bulk update for this server
             let is_already_updating = bulk_updates.iter().any(|bulk_update| {
                 bulk_update.query.get_str("ip").ok()
-                    .and_then(|ip_str| ip_str.parse::<Ipv4Addr>().ok())
-                    .map_or(false, |ip_addr| ip_addr == *target.ip())
+                    .and_then(|ip_str| ip_str.parse::<IpAddr>().ok())
+                    .map_or(false, |ip_addr| ip_addr == target.ip())
                     && database::get_u32(&bulk_update.query, "port") == Some(target.port() as u32)
             });
             if is_already_updating {
@@ -104,8 +189,13 @@ where
             }
             bulk_updates.push(bulk_update);
             if bulk_updates.len() >= 100 {
-                if let Err(err) =
-                    flush_bulk_updates(&database, mem::take(&mut bulk_updates), &shared).await
+                if let Err(err) = flush_bulk_updates(
+                    &database,
+                    P::COLLECTION,
+                    mem::take(&mut bulk_updates),
+                    &shared,
+                )
+                .await
                 {
                     eprintln!("Failed to flush bulk updates: {err}");
                 }
@@ -113,7 +203,9 @@ where
         }
 
         if !bulk_updates.is_empty() {
-            if let Err(err) = flush_bulk_updates(&database, bulk_updates, &shared).await {
+            if let Err(err) =
+                flush_bulk_updates(&database, P::COLLECTION, bulk_updates, &shared).await
+            {
                 eprintln!("Failed to flush bulk updates: {err}");
             }
         }
@@ -122,8 +214,52 @@ where
     }
 }
 
+/// Add the latencies we measured to the update and record them in the
+/// histogram.
+fn add_latency(bulk_update: &mut database::bulk_write::BulkUpdate, info: ConnectionInfo) {
+    if bulk_update.multi {
+        // the latency was measured on another port than the servers'
+        return;
+    }
+    let Ok(set) = bulk_update.update.get_document_mut("$set") else {
+        return;
+    };
+    if let Some(tcp_rtt) = info.tcp_rtt {
+        let tcp_ms = tcp_rtt.as_secs_f64() * 1000.;
+        set.insert("latency.tcpMs", tcp_ms);
+        LATENCY_HISTOGRAM.with_label_values(&["tcp"]).observe(tcp_ms);
+    }
+    if let Some(protocol_rtt) = info.protocol_rtt {
+        let slp_ms = protocol_rtt.as_secs_f64() * 1000.;
+        set.insert("latency.slpMs", slp_ms);
+        LATENCY_HISTOGRAM.with_label_values(&["slp"]).observe(slp_ms);
+    }
+}
+
+/// Add what the SYN+ACK told us about the server's TCP/IP stack.
+fn add_tcp_fingerprint(bulk_update: &mut database::bulk_write::BulkUpdate, info: ConnectionInfo) {
+    if bulk_update.multi {
+        return;
+    }
+    let Some(signature) = info.tcp_signature else {
+        return;
+    };
+    let Ok(set) = bulk_update.update.get_document_mut("$set") else {
+        return;
+    };
+    set.insert(
+        "fingerprint.tcp",
+        doc! {
+            "signature": signature.to_string(),
+            "os": signature.guess_os(),
+            "distance": signature.distance() as i32,
+        },
+    );
+}
+
 async fn flush_bulk_updates(
     database: &Database,
+    collection: &str,
     bulk_updates: Vec<database::bulk_write::BulkUpdate>,
     shared: &Arc<Mutex<SharedData>>,
 ) -> anyhow::Result<()> {
@@ -180,11 +316,11 @@ async fn flush_bulk_updates(
 
         let db = database.matscan_database();
         let result_not_reviving = db
-            .collection::<bson::Document>("servers")
+            .collection::<bson::Document>(collection)
             .bulk_update(&db, bulk_updates_not_reviving)
             .await?;
         let result_reviving = db
-            .collection::<bson::Document>("servers")
+            .collection::<bson::Document>(collection)
             .bulk_update(&db, &bulk_updates_reviving)
             .await?;
 
@@ -211,7 +347,7 @@ async fn flush_bulk_updates(
         // fingerprinting so reviving/inserting doesn't make sense
         let db = database.matscan_database();
         let result = db
-            .collection::<bson::Document>("servers")
+            .collection::<bson::Document>(collection)
             .bulk_update(&db, bulk_updates)
             .await?;
         updated_count = result.nb_modified as usize;
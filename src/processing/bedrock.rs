@@ -0,0 +1,97 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{legacy_minecraft::strip_legacy_formatting, ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::Bedrock {
+    const COLLECTION: &'static str = "bedrock_servers";
+
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let data = String::from_utf8_lossy(data);
+        let bedrock = parse_server_id(&data)?;
+
+        let mongo_update = doc! {
+            "$set": {
+                "timestamp": bson::DateTime::from_system_time(SystemTime::now()),
+                "bedrock": bedrock,
+            }
+        };
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: mongo_update,
+            options: Some(UpdateOptions::builder().upsert(true).build()),
+            multi: false,
+        })
+    }
+}
+
+/// Parse the server id string from an unconnected pong into the document
+/// that's stored in the database.
+///
+/// The format is `MCPE;motd;protocol;version;online;max;guid;...`, where
+/// everything after the player counts is optional.
+pub fn parse_server_id(data: &str) -> Option<Document> {
+    let mut parts = data.split(';');
+
+    let edition = parts.next()?;
+    let motd = parts.next()?;
+    let protocol = parts.next()?.parse::<i32>().ok()?;
+    let version = parts.next()?;
+    let online_players = parts.next()?.parse::<i32>().ok()?;
+    let max_players = parts.next()?.parse::<i32>().ok()?;
+
+    let mut bedrock = doc! {
+        "edition": edition,
+        "motd": motd,
+        "cleanMotd": strip_legacy_formatting(motd),
+        "protocol": protocol,
+        "version": version,
+        "players": {
+            "online": online_players,
+            "max": max_players,
+        },
+    };
+
+    // the rest are optional and some servers leave them out or empty
+    if let Some(server_guid) = parts.next().filter(|s| !s.is_empty()) {
+        bedrock.insert("serverGuid", server_guid);
+    }
+    if let Some(sub_motd) = parts.next().filter(|s| !s.is_empty()) {
+        bedrock.insert("subMotd", sub_motd);
+    }
+    if let Some(game_mode) = parts.next().filter(|s| !s.is_empty()) {
+        bedrock.insert("gameMode", game_mode);
+    }
+    if let Some(game_mode_numeric) = parts.next().and_then(|s| s.parse::<i32>().ok()) {
+        bedrock.insert("gameModeNumeric", game_mode_numeric);
+    }
+    if let Some(port_v4) = parts.next().and_then(|s| s.parse::<i32>().ok()) {
+        bedrock.insert("portV4", port_v4);
+    }
+    if let Some(port_v6) = parts.next().and_then(|s| s.parse::<i32>().ok()) {
+        bedrock.insert("portV6", port_v6);
+    }
+
+    Some(bedrock)
+}
@@ -0,0 +1,68 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::doc;
+use parking_lot::Mutex;
+use tracing::error;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{
+    legacy_minecraft::strip_legacy_formatting,
+    minecraft::{create_bulk_update, missing_status_fields},
+    ProcessableProtocol, SharedData,
+};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::BetaMinecraft {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        database: &Database,
+    ) -> Option<BulkUpdate> {
+        let data = String::from_utf8_lossy(data);
+
+        // motd§online§max
+        let mut parts = data.rsplitn(3, '\u{a7}');
+        let max_players = parts.next()?.parse::<i32>().ok()?;
+        let online_players = parts.next()?.parse::<i32>().ok()?;
+        let motd = parts.next()?;
+
+        // beta servers don't tell us their version
+        let minecraft = doc! {
+            "players": {
+                "online": online_players,
+                "max": max_players,
+            },
+            "description": serde_json::Value::from(motd).to_string(),
+            "cleanDescription": strip_legacy_formatting(motd),
+            "legacy": "beta",
+        };
+
+        // like the server list ping, other probes have fields in minecraft
+        // too so we only replace the ones from the status
+        let mut set = doc! {
+            "timestamp": bson::DateTime::from_system_time(SystemTime::now()),
+        };
+        for (key, value) in minecraft {
+            set.insert(format!("minecraft.{key}"), value);
+        }
+        let mongo_update = doc! {
+            "$unset": missing_status_fields(&set),
+            "$set": set,
+        };
+        match create_bulk_update(database, &target, mongo_update) {
+            Ok(r) => Some(r),
+            Err(err) => {
+                error!("Error updating server {target}: {err}");
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::GenericProbe {
+    fn process(
+        shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        // an arc, so this doesn't copy the regexes for every result
+        let probe = shared.lock().generic_probe.clone()?;
+
+        // if nothing matched then it's probably some other service on the
+        // port, but we still store that something answered
+        let mut service = Document::new();
+        service.insert("open", true);
+        service.insert("port", target.port() as u32);
+        for (field, value) in probe.extract(data) {
+            service.insert(field, value);
+        }
+        service.insert(
+            "timestamp",
+            bson::DateTime::from_system_time(SystemTime::now()),
+        );
+
+        Some(BulkUpdate {
+            query: doc! { "ip": { "$eq": target.ip().to_string() } },
+            update: doc! { "$set": { format!("services.{}", probe.name): service } },
+            options: None,
+            multi: true,
+        })
+    }
+}
@@ -0,0 +1,264 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use azalea_chat::FormattedText;
+use bson::{doc, Bson, Document};
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{
+    bedrock::parse_server_id,
+    minecraft::{read_utf, read_varint},
+    ProcessableProtocol, SharedData,
+};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::Geyser {
+    fn process(
+        shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let java_descriptions = {
+            let mut shared = shared.lock();
+            shared.geyser_answered.insert(target.ip());
+            shared
+                .geyser_targets
+                .get(&target.ip())
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        Some(BulkUpdate {
+            query: doc! { "ip": { "$eq": target.ip().to_string() } },
+            update: crossplay_update(target, data, &java_descriptions)?,
+            options: None,
+            multi: true,
+        })
+    }
+}
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftFloodgate {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let (floodgate, login) = parse_floodgate_response(data)?;
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: doc! { "$set": {
+                "crossplay.floodgate": floodgate,
+                "crossplay.floodgateLogin": login,
+            } },
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+/// Whether the server kicked us for the Floodgate data in our hostname, and
+/// what goes in `crossplay.floodgateLogin`.
+///
+/// Floodgate's default messages for data it can't read all mention Floodgate
+/// or Geyser. Servers without it go on with the login, or kick us for
+/// something else like a whitelist.
+fn parse_floodgate_response(mut data: &[u8]) -> Option<(bool, Document)> {
+    let packet_id = read_varint(&mut data)?;
+
+    let mut login = doc! {
+        "timestamp": Bson::DateTime(bson::DateTime::from_system_time(SystemTime::now())),
+    };
+    let floodgate = match packet_id {
+        // disconnect
+        0x00 => {
+            let reason = read_utf(&mut data)?;
+            let clean_reason = serde_json::from_str::<serde_json::Value>(&reason)
+                .ok()
+                .and_then(|r| FormattedText::deserialize(&r).ok())
+                .map(|r| r.to_string())
+                .unwrap_or_else(|| reason.clone());
+            // the raw reason has the same text, just with formatting around it
+            let lowercase_reason = reason.to_lowercase();
+            let floodgate =
+                lowercase_reason.contains("floodgate") || lowercase_reason.contains("geyser");
+            login.insert("disconnect", reason);
+            login.insert("cleanDisconnect", clean_reason);
+            floodgate
+        }
+        // the login continued like normal
+        0x01..=0x05 => false,
+        _ => return None,
+    };
+
+    Some((floodgate, login))
+}
+
+/// The update for the Java servers on the IP when a Bedrock ping to `target`
+/// got a reply. It's only Geyser if the pong looks like it came from the Java
+/// server, otherwise it's stored as a Bedrock server on the same host.
+fn crossplay_update(
+    target: SocketAddr,
+    data: &[u8],
+    java_descriptions: &[String],
+) -> Option<Document> {
+    let data = String::from_utf8_lossy(data);
+    let mut bedrock = parse_server_id(&data)?;
+    bedrock.insert("port", target.port() as u32);
+
+    let now = bson::DateTime::from_system_time(SystemTime::now());
+    let update = if is_geyser(&bedrock, java_descriptions) {
+        doc! {
+            "$set": {
+                "crossplay.geyser": true,
+                "crossplay.bedrock": bedrock,
+                "crossplay.checkedAt": now,
+                "crossplay.timestamp": now,
+            },
+            "$unset": { "crossplay.bedrockOnSameHost": "" },
+        }
+    } else {
+        doc! {
+            "$set": {
+                "crossplay.geyser": false,
+                "crossplay.bedrockOnSameHost": bedrock,
+                "crossplay.checkedAt": now,
+            },
+            "$unset": { "crossplay.bedrock": "" },
+        }
+    };
+    Some(update)
+}
+
+/// Whether the pong came from Geyser in front of one of the Java servers.
+///
+/// Geyser passes the first two lines of the Java description through as the
+/// MOTD and sub-MOTD by default, and otherwise uses its own defaults unless
+/// they were changed.
+fn is_geyser(bedrock: &Document, java_descriptions: &[String]) -> bool {
+    let motd = bedrock.get_str("cleanMotd").unwrap_or_default().trim();
+    let sub_motd = bedrock.get_str("subMotd").unwrap_or_default().trim();
+    if motd == "Geyser" || sub_motd == "Geyser" || sub_motd == "Another Geyser server." {
+        return true;
+    }
+    if motd.is_empty() {
+        return false;
+    }
+
+    java_descriptions.iter().any(|description| {
+        description
+            .lines()
+            .next()
+            .is_some_and(|line| line.trim() == motd)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossplay_update() {
+        let target = "1.2.3.4:19132".parse().unwrap();
+        let update = crossplay_update(
+            target,
+            b"MCPE;\xc2\xa7aGeyser;686;1.21.2;3;100;12345;Geyser;Survival;1;19132;19133;",
+            &[],
+        )
+        .unwrap();
+        let set = update.get_document("$set").unwrap();
+        assert_eq!(set.get_bool("crossplay.geyser"), Ok(true));
+        let bedrock = set.get_document("crossplay.bedrock").unwrap();
+        assert_eq!(bedrock.get_str("version"), Ok("1.21.2"));
+        assert_eq!(bedrock.get_i32("port"), Ok(19132));
+
+        // a vanilla bedrock server next to the java one
+        let update = crossplay_update(
+            target,
+            b"MCPE;Dedicated Server;712;1.21.20;0;10;1325;Bedrock level;Survival;1;19132;19133;",
+            &["A Minecraft Server".to_string()],
+        )
+        .unwrap();
+        let set = update.get_document("$set").unwrap();
+        assert_eq!(set.get_bool("crossplay.geyser"), Ok(false));
+        assert!(set.get_document("crossplay.bedrockOnSameHost").is_ok());
+        assert!(update
+            .get_document("$unset")
+            .unwrap()
+            .contains_key("crossplay.bedrock"));
+
+        assert!(crossplay_update(target, b"not a bedrock server", &[]).is_none());
+    }
+
+    #[test]
+    fn test_parse_floodgate_response() {
+        let disconnect_packet = |reason: &str| {
+            let mut packet = vec![0x00, reason.len() as u8];
+            packet.extend_from_slice(reason.as_bytes());
+            packet
+        };
+
+        let (floodgate, login) = parse_floodgate_response(&disconnect_packet(
+            r#"{"text":"Please connect through the official Geyser"}"#,
+        ))
+        .unwrap();
+        assert!(floodgate);
+        assert!(login.contains_key("cleanDisconnect"));
+        let (floodgate, _) = parse_floodgate_response(&disconnect_packet(
+            "Received an unsupported Floodgate data version.",
+        ))
+        .unwrap();
+        assert!(floodgate);
+
+        let (floodgate, _) =
+            parse_floodgate_response(&disconnect_packet(r#"{"text":"You are not whitelisted"}"#))
+                .unwrap();
+        assert!(!floodgate);
+        // encryption request
+        let (floodgate, login) = parse_floodgate_response(&[0x01, 0x00]).unwrap();
+        assert!(!floodgate);
+        assert!(!login.contains_key("disconnect"));
+
+        assert!(parse_floodgate_response(&[0x20]).is_none());
+    }
+
+    #[test]
+    fn test_is_geyser() {
+        let bedrock = |server_id: &str| parse_server_id(server_id).unwrap();
+        let java = ["My Server\nNow with cross-play!".to_string()];
+
+        // the motd is passed through from java
+        assert!(is_geyser(
+            &bedrock("MCPE;\u{a7}6My Server;686;1.21.2;3;100;1;Now with cross-play!;Survival;1;"),
+            &java
+        ));
+        // geyser's defaults
+        assert!(is_geyser(
+            &bedrock("MCPE;Geyser;686;1.21.2;0;100;1;Another Geyser server.;Survival;1;"),
+            &[]
+        ));
+        assert!(!is_geyser(
+            &bedrock("MCPE;Other Server;686;1.21.2;0;100;1;Bedrock level;Survival;1;"),
+            &java
+        ));
+        assert!(!is_geyser(
+            &bedrock("MCPE;;686;1.21.2;0;100;"),
+            &["".to_string()]
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use parking_lot::Mutex;
+use regex::Regex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols::{self, header},
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+static TITLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+/// The web maps we know, and the strings (in lowercase) that show up in their
+/// title or server header.
+const WEBMAPS: &[(&str, &[&str])] = &[
+    ("dynmap", &["minecraft dynamic map", "dynmap"]),
+    ("bluemap", &["bluemap"]),
+    ("pl3xmap", &["pl3xmap"]),
+    ("squaremap", &["squaremap"]),
+    ("overviewer", &["minecraft overviewer", "overviewer"]),
+];
+
+#[async_trait]
+impl ProcessableProtocol for protocols::Http {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let response = String::from_utf8_lossy(data);
+        // anything else was already marked as checked when the ips were picked
+        let (webmap_type, title) = find_webmap(&response)?;
+
+        let mut webmap = Document::new();
+        webmap.insert("open", true);
+        webmap.insert("type", webmap_type);
+        webmap.insert("port", target.port() as u32);
+        if let Some(title) = title {
+            webmap.insert("title", title);
+        }
+        webmap.insert(
+            "timestamp",
+            bson::DateTime::from_system_time(SystemTime::now()),
+        );
+
+        // same as rcon, the map is on another port than the server
+        Some(BulkUpdate {
+            query: doc! { "ip": { "$eq": target.ip().to_string() } },
+            update: doc! { "$set": { "services.webmap": webmap } },
+            options: None,
+            multi: true,
+        })
+    }
+}
+
+/// Get the type of web map and the page's title from an HTTP response. Only
+/// the title and server header are checked, since lots of other pages mention
+/// or link to maps.
+fn find_webmap(response: &str) -> Option<(&'static str, Option<String>)> {
+    let (headers, body) = response.split_once("\r\n\r\n")?;
+
+    let title = TITLE_REGEX
+        .captures(body)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().trim().to_string());
+    let server = header(headers, "server").unwrap_or_default();
+
+    let sources = [
+        title.as_deref().unwrap_or_default().to_lowercase(),
+        server.to_lowercase(),
+    ];
+    let webmap_type = WEBMAPS
+        .iter()
+        .find(|(_, needles)| {
+            sources
+                .iter()
+                .any(|source| needles.iter().any(|needle| source.contains(needle)))
+        })
+        .map(|(webmap_type, _)| *webmap_type)?;
+
+    Some((webmap_type, title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_webmap() {
+        assert_eq!(
+            find_webmap(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><head><title>Minecraft Dynamic Map</title></head></html>"
+            ),
+            Some(("dynmap", Some("Minecraft Dynamic Map".to_string())))
+        );
+        assert_eq!(
+            find_webmap("HTTP/1.1 200 OK\r\nServer: BlueMap v5.3\r\n\r\n<html></html>"),
+            Some(("bluemap", None))
+        );
+        // a page that only links to a map isn't one
+        assert_eq!(
+            find_webmap(
+                "HTTP/1.1 200 OK\r\n\r\n<html><head><title>My Server</title></head><a href=\"/dynmap\">Map</a></html>"
+            ),
+            None
+        );
+    }
+}
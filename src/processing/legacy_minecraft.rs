@@ -0,0 +1,89 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::doc;
+use parking_lot::Mutex;
+use tracing::error;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{
+    minecraft::{create_bulk_update, missing_status_fields},
+    ProcessableProtocol, SharedData,
+};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::LegacyMinecraft {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        database: &Database,
+    ) -> Option<BulkUpdate> {
+        let data = String::from_utf8_lossy(data);
+
+        // §1\0protocol\0version\0motd\0online\0max
+        let mut parts = data.split('\0').skip(1);
+        let protocol_version = parts.next()?.parse::<i32>().ok()?;
+        let version_name = parts.next()?;
+        let motd = parts.next()?;
+        let online_players = parts.next()?.parse::<i32>().ok()?;
+        let max_players = parts.next()?.parse::<i32>().ok()?;
+
+        let minecraft = doc! {
+            "version": {
+                "name": version_name,
+                "protocol": protocol_version,
+            },
+            "players": {
+                "online": online_players,
+                "max": max_players,
+            },
+            // modern descriptions are stored as json so do the same here
+            "description": serde_json::Value::from(motd).to_string(),
+            "cleanDescription": strip_legacy_formatting(motd),
+            // the 1.4-1.6 ping (§1 kick string)
+            "legacy": "1.4",
+        };
+
+        // like the server list ping, other probes have fields in minecraft
+        // too so we only replace the ones from the status
+        let mut set = doc! {
+            "timestamp": bson::DateTime::from_system_time(SystemTime::now()),
+        };
+        for (key, value) in minecraft {
+            set.insert(format!("minecraft.{key}"), value);
+        }
+        let mongo_update = doc! {
+            "$unset": missing_status_fields(&set),
+            "$set": set,
+        };
+        match create_bulk_update(database, &target, mongo_update) {
+            Ok(r) => Some(r),
+            Err(err) => {
+                error!("Error updating server {target}: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// Remove the §-prefixed formatting codes from a legacy string.
+pub fn strip_legacy_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{a7}' {
+            // skip the code too
+            chars.next();
+            continue;
+        }
+        stripped.push(c);
+    }
+    stripped
+}
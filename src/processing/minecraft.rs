@@ -1,7 +1,7 @@
 use std::{
     collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
-    net::SocketAddrV4,
+    net::SocketAddr,
     sync::{Arc, LazyLock},
     time::SystemTime,
 };
@@ -26,12 +26,35 @@ use super::{ProcessableProtocol, SharedData};
 
 const ANONYMOUS_PLAYER_NAME: &str = "Anonymous Player";
 
+/// Fields in `minecraft` that come from the server list ping, these are unset
+/// if the server stops sending them. `forgeData` is only unset once the server
+/// isn't modded anymore, since the full mod list from the forge probe is kept
+/// in it.
+const STATUS_FIELDS: &[&str] = &[
+    "description",
+    "cleanDescription",
+    "players",
+    "version",
+    "favicon",
+    "modinfo",
+    "forgeData",
+    "isForge",
+    "isModded",
+    // set by the proxy protocol probe, if the normal ping works then it's not
+    // needed anymore
+    "requiresProxyProtocol",
+    "enforcesSecureChat",
+    "previewsChat",
+    "preventsChatReports",
+    "legacy",
+];
+
 #[async_trait]
 impl ProcessableProtocol for protocols::Minecraft {
     fn process(
         shared: &Arc<Mutex<SharedData>>,
         config: &Config,
-        target: SocketAddrV4,
+        target: SocketAddr,
         data: &[u8],
         database: &Database,
     ) -> Option<BulkUpdate> {
@@ -219,7 +242,30 @@ impl ProcessableProtocol for protocols::Minecraft {
         }
 
         if let Some(cleaned_data) = clean_response_data(&data, passive_fingerprint) {
-            let mongo_update = doc! { "$set": cleaned_data };
+            let mut cleaned_data = cleaned_data;
+            // so rescans can use the hostname too
+            if let Some(hostname) = shared.lock().virtual_hosts.get(&target) {
+                cleaned_data.insert("hostname", hostname.clone());
+            }
+            let mut set_on_insert = Document::new();
+            if cleaned_data.get_bool("minecraft.forgeData.truncated") == Ok(true) {
+                // don't overwrite the full list that we got from logging in
+                if let Some(mods) = cleaned_data.remove("minecraft.forgeData.mods") {
+                    set_on_insert.insert("minecraft.forgeData.mods", mods);
+                }
+            }
+            let mut mongo_update = doc! { "$set": &cleaned_data };
+            let unset = missing_status_fields(&cleaned_data);
+            if !unset.is_empty() {
+                mongo_update.insert("$unset", unset);
+            }
+            if !cleaned_data.contains_key("onlineModeGuess") {
+                // new servers default to offline until we know more
+                set_on_insert.insert("onlineModeGuess", Bson::Int32(0));
+            }
+            if !set_on_insert.is_empty() {
+                mongo_update.insert("$setOnInsert", set_on_insert);
+            }
             match create_bulk_update(database, &target, mongo_update) {
                 Ok(r) => Some(r),
                 Err(err) => {
@@ -233,6 +279,24 @@ impl ProcessableProtocol for protocols::Minecraft {
     }
 }
 
+/// The [`STATUS_FIELDS`] that aren't in the `$set` of a status update, for
+/// its `$unset`. The fields are set one by one (like `minecraft.version`) so
+/// the ones that other probes put in `minecraft` are kept.
+pub(super) fn missing_status_fields(set: &Document) -> Document {
+    let is_modded = set.get_bool("minecraft.isModded") == Ok(true);
+    STATUS_FIELDS
+        .iter()
+        .filter(|&&field| !(field == "forgeData" && is_modded))
+        .map(|field| format!("minecraft.{field}"))
+        // forgeData is set one field at a time
+        .filter(|field| {
+            !set.keys()
+                .any(|key| key == field || key.starts_with(&format!("{field}.")))
+        })
+        .map(|field| (field, Bson::String(String::new())))
+        .collect()
+}
+
 fn decode_optimized(encoded: &str) -> Option<Vec<u8>> {
     if encoded.len() < 2 {
         return None;
@@ -267,7 +331,7 @@ fn decode_optimized(encoded: &str) -> Option<Vec<u8>> {
     Some(bytes)
 }
 
-fn read_varint(bytes: &mut &[u8]) -> Option<u32> {
+pub(super) fn read_varint(bytes: &mut &[u8]) -> Option<u32> {
     let mut num = 0u32;
     let mut shift = 0;
     for _ in 0..5 {
@@ -282,7 +346,7 @@ fn read_varint(bytes: &mut &[u8]) -> Option<u32> {
     None
 }
 
-fn read_utf(bytes: &mut &[u8]) -> Option<String> {
+pub(super) fn read_utf(bytes: &mut &[u8]) -> Option<String> {
     let len = read_varint(bytes)? as usize;
     if bytes.len() < len {
         return None;
@@ -307,11 +371,12 @@ fn read_u16(bytes: &mut &[u8]) -> Option<u16> {
     Some(val)
 }
 
-fn extract_forge_mods(encoded: &str) -> Option<Vec<Bson>> {
+/// Read the mods from `forgeData.d`, and whether the list was truncated.
+fn extract_forge_mods(encoded: &str) -> Option<(bool, Vec<Bson>)> {
     let decoded_vec = decode_optimized(encoded)?;
     let mut bytes = decoded_vec.as_slice();
 
-    let _truncated = read_bool(&mut bytes)?;
+    let truncated = read_bool(&mut bytes)?;
     let mods_size = read_u16(&mut bytes)? as usize;
 
     let mut mods = Vec::new();
@@ -341,7 +406,7 @@ fn extract_forge_mods(encoded: &str) -> Option<Vec<Bson>> {
         mods.push(Bson::Document(mod_doc));
     }
 
-    Some(mods)
+    Some((truncated, mods))
 }
 
 /// Clean up the response data from the server into something we can insert into
@@ -371,11 +436,18 @@ fn clean_response_data(
     let legacy_forge = data.contains_key("modinfo");
     let new_forge = data.contains_key("forgeData");
     data.insert("isForge", legacy_forge || new_forge);
+    // newer neoforge versions only say that they're modded, without forgeData
+    let is_modded = legacy_forge || new_forge || data.get_bool("isModded") == Ok(true);
+    data.insert("isModded", is_modded);
 
     if let Some(forge_data) = data.get_mut("forgeData").and_then(Bson::as_document_mut) {
         if let Some(d_val) = forge_data.get("d").and_then(Bson::as_str) {
-            if let Some(mods) = extract_forge_mods(d_val) {
+            if let Some((truncated, mods)) = extract_forge_mods(d_val) {
                 forge_data.insert("mods", Bson::Array(mods));
+                forge_data.insert("truncated", truncated);
+                if !truncated {
+                    forge_data.insert("complete", true);
+                }
             }
         }
     }
@@ -475,16 +547,33 @@ fn clean_response_data(
 
     let mut final_cleaned = doc! {
         "timestamp": bson::DateTime::from_system_time(SystemTime::now()),
-        "minecraft": data,
     };
+    // the fields are set separately so we don't overwrite the ones that other
+    // probes put in `minecraft` (like supportedProtocols)
+    for (key, value) in data {
+        if key.is_empty() || key.contains('.') || key.starts_with('$') {
+            continue;
+        }
+        if let (true, Bson::Document(forge_data)) = (key == "forgeData", &value) {
+            // same for forgeData, since the forge probe adds to it
+            for (forge_key, forge_value) in forge_data {
+                if forge_key.is_empty() || forge_key.contains('.') || forge_key.starts_with('$') {
+                    continue;
+                }
+                final_cleaned.insert(format!("minecraft.forgeData.{forge_key}"), forge_value.clone());
+            }
+            continue;
+        }
+        final_cleaned.insert(format!("minecraft.{key}"), value);
+    }
 
-    // C# enums are serialized as an int32 inside the BSON document
+    // C# enums are serialized as an int32 inside the BSON document.
+    // this is only a fallback for the login probe (`login.onlineMode`), so if
+    // the sample doesn't tell us anything we keep whatever was there before
     if mixed_online_mode {
         final_cleaned.insert("onlineModeGuess", Bson::Int32(2)); // mixed
-    } else if let Some(_) = is_online_mode {
-        final_cleaned.insert("onlineModeGuess", Bson::Int32(1)); // online
-    } else {
-        final_cleaned.insert("onlineModeGuess", Bson::Int32(0)); // offline
+    } else if let Some(is_online_mode) = is_online_mode {
+        final_cleaned.insert("onlineModeGuess", Bson::Int32(is_online_mode as i32)); // online/offline
     }
 
     if !fake_sample {
@@ -528,23 +617,37 @@ fn clean_response_data(
 
 pub fn create_bulk_update(
     database: &Database,
-    target: &SocketAddrV4,
+    target: &SocketAddr,
     mongo_update: Document,
 ) -> anyhow::Result<BulkUpdate> {
-    if database.shared.lock().bad_ips.contains(target.ip()) && target.port() != 25565 {
+    if database.shared.lock().bad_ips.contains(&target.ip()) && target.port() != 25565 {
         // no
         bail!("bad ip");
     }
 
     fn determine_hash(mongo_update: &Document) -> anyhow::Result<u64> {
         let set_data = mongo_update.get_document("$set")?;
-        let minecraft = set_data.get_document("minecraft")?;
+        let minecraft = match set_data.get_document("minecraft") {
+            Ok(minecraft) => minecraft.clone(),
+            // the server list ping sets each field separately
+            Err(_) => set_data
+                .iter()
+                .filter_map(|(key, value)| {
+                    Some((key.strip_prefix("minecraft.")?.to_string(), value.clone()))
+                })
+                .collect::<Document>(),
+        };
 
-        let version = minecraft.get_document("version")?;
+        // beta servers don't have a version
+        let version = minecraft.get_document("version").ok();
 
         let description = minecraft.get_str("description").unwrap_or_default();
-        let version_name = version.get_str("name").unwrap_or_default();
-        let version_protocol = database::get_i32(version, "protocol").unwrap_or_default();
+        let version_name = version
+            .and_then(|v| v.get_str("name").ok())
+            .unwrap_or_default();
+        let version_protocol = version
+            .and_then(|v| database::get_i32(v, "protocol"))
+            .unwrap_or_default();
         let max_players = minecraft
             .get_document("players")
             .ok()
@@ -558,7 +661,7 @@ pub fn create_bulk_update(
 
     let mut is_bad_ip = false;
     let mut shared = database.shared.lock();
-    let ips_with_same_hash = shared.ips_with_same_hash.get_mut(target.ip());
+    let ips_with_same_hash = shared.ips_with_same_hash.get_mut(&target.ip());
     if let Some((data, previously_checked_ports)) = ips_with_same_hash {
         if !previously_checked_ports.contains(&target.port()) {
             if let Some(count) = &mut data.count {
@@ -584,7 +687,7 @@ pub fn create_bulk_update(
     } else {
         let this_server_hash = determine_hash(&mongo_update)?;
         shared.ips_with_same_hash.insert(
-            *target.ip(),
+            target.ip(),
             (
                 CachedIpHash {
                     count: Some(1),
@@ -596,7 +699,7 @@ pub fn create_bulk_update(
     }
 
     if is_bad_ip {
-        tokio::spawn(database.to_owned().add_to_bad_ips(*target.ip()));
+        tokio::spawn(database.to_owned().add_to_bad_ips(target.ip()));
         bail!("bad ip {target:?}");
     }
 
@@ -607,6 +710,7 @@ pub fn create_bulk_update(
         },
         update: mongo_update,
         options: Some(UpdateOptions::builder().upsert(true).build()),
+        multi: false,
     })
 }
 
@@ -729,3 +833,54 @@ pub fn generate_passive_fingerprint(data: &str) -> anyhow::Result<PassiveMinecra
         empty_favicon,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_modded() {
+        let neoforge = serde_json::json!({
+            "description": "A Minecraft Server",
+            "players": { "max": 20, "online": 0 },
+            "version": { "name": "1.21.1", "protocol": 767 },
+            "isModded": true,
+        });
+        let cleaned = clean_response_data(&neoforge, None).unwrap();
+        assert_eq!(cleaned.get_bool("minecraft.isModded"), Ok(true));
+        assert_eq!(cleaned.get_bool("minecraft.isForge"), Ok(false));
+
+        let vanilla = serde_json::json!({
+            "description": "A Minecraft Server",
+            "players": { "max": 20, "online": 0 },
+            "version": { "name": "1.21.1", "protocol": 767 },
+        });
+        let cleaned = clean_response_data(&vanilla, None).unwrap();
+        assert_eq!(cleaned.get_bool("minecraft.isModded"), Ok(false));
+    }
+
+    #[test]
+    fn test_missing_status_fields() {
+        let forge = doc! {
+            "minecraft.description": "{}",
+            "minecraft.isModded": true,
+            "minecraft.forgeData.fmlNetworkVersion": 3,
+        };
+        let unset = missing_status_fields(&forge);
+        assert!(!unset.contains_key("minecraft.description"));
+        assert!(!unset.contains_key("minecraft.forgeData"));
+        assert!(unset.contains_key("minecraft.version"));
+
+        // a legacy ping means the server isn't modded anymore, but the fields
+        // from other probes are left alone
+        let legacy = doc! {
+            "minecraft.description": "{}",
+            "minecraft.legacy": "1.4",
+        };
+        let unset = missing_status_fields(&legacy);
+        assert!(unset.contains_key("minecraft.forgeData"));
+        assert!(unset.contains_key("minecraft.favicon"));
+        assert!(!unset.contains_key("minecraft.legacy"));
+        assert!(!unset.contains_key("minecraft.supportedProtocols"));
+    }
+}
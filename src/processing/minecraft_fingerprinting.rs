@@ -1,6 +1,6 @@
 use std::{
     fmt::Display,
-    net::SocketAddrV4,
+    net::SocketAddr,
     sync::{Arc, LazyLock},
     time::SystemTime,
 };
@@ -16,11 +16,48 @@ use crate::{
     scanner::protocols,
 };
 
-use super::{ProcessableProtocol, SharedData};
+use super::{
+    minecraft::{read_utf, read_varint},
+    ProcessableProtocol, SharedData,
+};
 
 static VANILLA_ERROR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"java\.io\.IOException: Packet (?:\d+|login)\/\d+ \(([^)]+)\)").unwrap()
 });
+/// Spigot servers behind bungeecord kick everyone that didn't connect through
+/// the proxy.
+static BUNGEECORD_FORWARDING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"If you wish to use IP forwarding, please enable it in your BungeeCord config").unwrap()
+});
+/// Bungeecord and its forks (like waterfall) say which versions they support
+/// like `1.8.x-1.21.x`.
+static BUNGEECORD_OUTDATED_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"Outdated client! Please use (\d+\.\d+\.x-\d+\.\d+\.x)").unwrap()
+});
+/// Velocity (and gate, which is a port of it) use the translation that vanilla
+/// uses for outdated clients, but with a range of versions like `1.7.2-1.21.4`
+/// instead of one version.
+static VELOCITY_OUTDATED_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""(\d+\.\d+(?:\.\d+)?-\d+\.\d+(?:\.\d+)?)""#).unwrap()
+});
+static FORGE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bforge\b").unwrap());
+static FABRIC_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:fabric|quilt)\b").unwrap());
+
+/// What the server's status said last time we pinged it, which is used for
+/// telling apart proxies that look the same otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct KnownStatus {
+    pub version_name: String,
+    pub is_forge: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ProxyType {
+    Velocity,
+    Bungeecord,
+    Gate,
+}
 
 #[derive(Eq, PartialEq)]
 enum ServerType {
@@ -38,11 +75,18 @@ impl ProcessableProtocol for protocols::MinecraftFingerprinting {
     fn process(
         _shared: &Arc<Mutex<SharedData>>,
         _config: &Config,
-        target: SocketAddrV4,
+        target: SocketAddr,
         data: &[u8],
         _database: &Database,
     ) -> Option<BulkUpdate> {
         let data_string = String::from_utf8_lossy(data);
+        // backends are only checked here, the proxies themselves are found
+        // with MinecraftProxyFingerprinting
+        let proxy_backend = if BUNGEECORD_FORWARDING_REGEX.is_match(&data_string) {
+            Some(ProxyType::Bungeecord)
+        } else {
+            None
+        };
         let server_type = if let Some(packet_name) = VANILLA_ERROR_REGEX
             .captures(&data_string)
             .and_then(|c| c.get(1))
@@ -85,6 +129,68 @@ impl ProcessableProtocol for protocols::MinecraftFingerprinting {
                 server_type.to_string(),
             );
         }
+        if let Some(proxy_type) = proxy_backend {
+            mongo_update.insert("fingerprint.active.proxy.type", proxy_type.to_string());
+            mongo_update.insert("fingerprint.active.proxy.role", "backend");
+        }
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: doc! { "$set": mongo_update },
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftProxyFingerprinting {
+    fn process(
+        shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let mut data = data;
+        let mut mongo_update = doc! {
+            "fingerprint.active.proxy.timestamp": Bson::DateTime(bson::DateTime::from_system_time(SystemTime::now())),
+        };
+
+        // 0x00 is disconnect, anything else is weird but it still means the
+        // server isn't a proxy that we know
+        if read_varint(&mut data)? == 0x00 {
+            let reason = read_utf(&mut data)?;
+            // it's only needed once, so it doesn't have to stay in memory
+            let known_status = shared
+                .lock()
+                .known_statuses
+                .remove(&target)
+                .unwrap_or_default();
+
+            if let Some((proxy_type, versions)) = identify_proxy(&reason, &known_status) {
+                mongo_update.insert("fingerprint.active.proxy.type", proxy_type.to_string());
+                mongo_update.insert("fingerprint.active.proxy.role", "proxy");
+                mongo_update.insert("fingerprint.active.proxy.versions", versions);
+                // proxies show the status of their backend if ping passthrough
+                // is on, or they announce forge themselves
+                let modded_backend = if known_status.is_forge
+                    || FORGE_REGEX.is_match(&known_status.version_name)
+                {
+                    Some("forge")
+                } else if FABRIC_REGEX.is_match(&known_status.version_name) {
+                    Some("fabric")
+                } else {
+                    None
+                };
+                if let Some(modded_backend) = modded_backend {
+                    mongo_update.insert("fingerprint.active.proxy.moddedBackend", modded_backend);
+                }
+            }
+        }
 
         Some(BulkUpdate {
             query: doc! {
@@ -93,6 +199,45 @@ impl ProcessableProtocol for protocols::MinecraftFingerprinting {
             },
             update: doc! { "$set": mongo_update },
             options: None,
+            multi: false,
+        })
+    }
+}
+
+/// Figure out the proxy from the disconnect message that we got for using an
+/// outdated version, and return it along with the versions it supports.
+fn identify_proxy(reason: &str, known_status: &KnownStatus) -> Option<(ProxyType, String)> {
+    if let Some(versions) = BUNGEECORD_OUTDATED_REGEX
+        .captures(reason)
+        .and_then(|c| c.get(1))
+    {
+        return Some((ProxyType::Bungeecord, versions.as_str().to_string()));
+    }
+    if !reason.contains("multiplayer.disconnect.outdated_client") {
+        return None;
+    }
+    if let Some(versions) = VELOCITY_OUTDATED_REGEX
+        .captures(reason)
+        .and_then(|c| c.get(1))
+    {
+        // gate sends the same message as velocity, but its status says gate
+        // unless it's passed through from the backend
+        let proxy_type = if known_status.version_name.starts_with("Gate ") {
+            ProxyType::Gate
+        } else {
+            ProxyType::Velocity
+        };
+        return Some((proxy_type, versions.as_str().to_string()));
+    }
+    None
+}
+
+impl Display for ProxyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ProxyType::Velocity => "velocity",
+            ProxyType::Bungeecord => "bungeecord",
+            ProxyType::Gate => "gate",
         })
     }
 }
@@ -110,3 +255,79 @@ impl Display for ServerType {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_proxy() {
+        let status = |version_name: &str| KnownStatus {
+            version_name: version_name.to_string(),
+            is_forge: false,
+        };
+        let cases = [
+            (
+                r#"{"text":"Outdated client! Please use 1.8.x-1.21.x"}"#,
+                status("Waterfall 1.8.x-1.21.x"),
+                Some((ProxyType::Bungeecord, "1.8.x-1.21.x")),
+            ),
+            (
+                r#"{"translate":"multiplayer.disconnect.outdated_client","with":["1.7.2-1.21.4"]}"#,
+                status("Velocity 3.4.0"),
+                Some((ProxyType::Velocity, "1.7.2-1.21.4")),
+            ),
+            (
+                r#"{"translate":"multiplayer.disconnect.outdated_client","with":["1.8-1.21"]}"#,
+                status("Gate 1.8-1.21"),
+                Some((ProxyType::Gate, "1.8-1.21")),
+            ),
+            // a gate proxy with ping passthrough looks like velocity
+            (
+                r#"{"translate":"multiplayer.disconnect.outdated_client","with":["1.8-1.21"]}"#,
+                status("Paper 1.21.4"),
+                Some((ProxyType::Velocity, "1.8-1.21")),
+            ),
+            // vanilla only says one version
+            (
+                r#"{"translate":"multiplayer.disconnect.outdated_client","with":["1.21.4"]}"#,
+                status("1.21.4"),
+                None,
+            ),
+            // a range that isn't in the outdated message
+            (
+                r#"{"text":"This server supports 1.8-1.21"}"#,
+                status("Paper 1.21.4"),
+                None,
+            ),
+            (r#"{"text":"You are not whitelisted"}"#, status(""), None),
+        ];
+        for (reason, known_status, expected) in cases {
+            assert_eq!(
+                identify_proxy(reason, &known_status),
+                expected.map(|(proxy_type, versions)| (proxy_type, versions.to_string())),
+                "{reason}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_modded_backend_regexes() {
+        for (version_name, forge, fabric) in [
+            ("Forge 1.20.1", true, false),
+            ("Velocity 3.4.0 (forge)", true, false),
+            ("fabric 1.21", false, true),
+            ("Quilt 1.20", false, true),
+            ("Paper 1.21.4", false, false),
+            // only whole words count
+            ("Forgery 1.0", false, false),
+        ] {
+            assert_eq!(FORGE_REGEX.is_match(version_name), forge, "{version_name}");
+            assert_eq!(
+                FABRIC_REGEX.is_match(version_name),
+                fabric,
+                "{version_name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::{doc, Bson, Document};
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols::{self, ForgeModList},
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftForge {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let mut mongo_update = doc! {
+            "minecraft.forgeData.modsTimestamp": Bson::DateTime(bson::DateTime::from_system_time(SystemTime::now())),
+        };
+
+        // empty means we couldn't log in (probably online mode), but we still
+        // don't want to try again right away
+        if let Ok(mod_list) = serde_json::from_slice::<ForgeModList>(data) {
+            if !mod_list.mods.is_empty() {
+                let mods = mod_list
+                    .mods
+                    .into_iter()
+                    .map(|(mod_id, version)| {
+                        // channels are namespaced by the mod that registered them
+                        let channels = mod_list
+                            .channels
+                            .iter()
+                            .filter(|(name, _)| name.split(':').next() == Some(mod_id.as_str()))
+                            .map(|(name, version)| doc! { "name": name, "version": version })
+                            .collect::<Vec<_>>();
+
+                        // same format as the mods from the status
+                        let mut mod_doc = Document::new();
+                        mod_doc.insert("modId", mod_id);
+                        if let Some(version) = version {
+                            mod_doc.insert("modmarker", version);
+                        }
+                        mod_doc.insert("channels", channels);
+                        Bson::Document(mod_doc)
+                    })
+                    .collect::<Vec<_>>();
+
+                mongo_update.insert("minecraft.forgeData.mods", mods);
+                mongo_update.insert("minecraft.forgeData.complete", mod_list.complete);
+            }
+        }
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: doc! { "$set": mongo_update },
+            options: None,
+            multi: false,
+        })
+    }
+}
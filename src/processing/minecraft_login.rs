@@ -0,0 +1,220 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use azalea_chat::FormattedText;
+use bson::{doc, Bson, Document};
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{
+    minecraft::{read_utf, read_varint},
+    ProcessableProtocol, SharedData,
+};
+
+/// The translation key that vanilla disconnects with when transfers are off.
+const TRANSFERS_DISABLED_KEY: &str = "multiplayer.disconnect.transfers_disabled";
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftLogin {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let mut data = data;
+        let packet_id = read_varint(&mut data)?;
+
+        // backends with velocity's modern forwarding ask for the player info
+        // that the proxy is supposed to send
+        let mut behind_velocity = false;
+        let mut login = doc! {
+            "timestamp": Bson::DateTime(bson::DateTime::from_system_time(SystemTime::now())),
+        };
+        match packet_id {
+            // disconnect
+            0x00 => {
+                let reason = read_utf(&mut data)?;
+                let clean_reason = serde_json::from_str::<serde_json::Value>(&reason)
+                    .ok()
+                    .and_then(|r| FormattedText::deserialize(&r).ok())
+                    .map(|r| r.to_string())
+                    .unwrap_or_else(|| reason.clone());
+
+                login.insert("result", "disconnect");
+                login.insert("disconnectReason", classify_disconnect(&clean_reason));
+                login.insert("disconnect", reason);
+                login.insert("cleanDisconnect", clean_reason);
+            }
+            // encryption request, the server wants to authenticate us with mojang
+            0x01 => {
+                login.insert("result", "encryption");
+                login.insert("onlineMode", true);
+            }
+            // login success
+            0x02 => {
+                login.insert("result", "success");
+                login.insert("onlineMode", false);
+            }
+            // set compression, this is only sent before login success
+            0x03 => {
+                login.insert("result", "compression");
+                login.insert("onlineMode", false);
+            }
+            // login plugin request, usually a backend server asking for
+            // velocity's modern forwarding
+            0x04 => {
+                let _message_id = read_varint(&mut data)?;
+                let channel = read_utf(&mut data)?;
+                behind_velocity = channel == "velocity:player_info";
+                login.insert("result", "pluginRequest");
+                login.insert("pluginChannel", channel);
+            }
+            // cookie request (1.20.5+)
+            0x05 => {
+                login.insert("result", "cookieRequest");
+            }
+            _ => return None,
+        }
+
+        let mut update = doc! { "login": Bson::Document(login) };
+        if behind_velocity {
+            update.insert("fingerprint.active.proxy.type", "velocity");
+            update.insert("fingerprint.active.proxy.role", "backend");
+        }
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: doc! { "$set": update },
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftTransfer {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let (accepts_transfers, transfer) = parse_transfer_response(data)?;
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            // separate from `login` so the login probe doesn't overwrite it
+            update: doc! { "$set": {
+                "transfer": transfer,
+                "acceptsTransfers": accepts_transfers,
+            } },
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+/// Whether the server let us log in with the transfer intent, and what goes in
+/// `transfer`.
+fn parse_transfer_response(mut data: &[u8]) -> Option<(bool, Document)> {
+    let packet_id = read_varint(&mut data)?;
+
+    let mut transfer = doc! {
+        "timestamp": Bson::DateTime(bson::DateTime::from_system_time(SystemTime::now())),
+    };
+    let accepts_transfers = match packet_id {
+        // disconnect
+        0x00 => {
+            let reason = read_utf(&mut data)?;
+            let reason_json = serde_json::from_str::<serde_json::Value>(&reason).ok();
+            // we got past the transfer check unless it's vanilla's message,
+            // other kicks (like a whitelist) come after it
+            let accepts_transfers = reason_json
+                .as_ref()
+                .and_then(|r| r.get("translate")?.as_str())
+                != Some(TRANSFERS_DISABLED_KEY);
+            let clean_reason = reason_json
+                .and_then(|r| FormattedText::deserialize(&r).ok())
+                .map(|r| r.to_string())
+                .unwrap_or_else(|| reason.clone());
+            transfer.insert("disconnect", reason);
+            transfer.insert("cleanDisconnect", clean_reason);
+            accepts_transfers
+        }
+        // the login continued like normal
+        0x01..=0x05 => true,
+        _ => return None,
+    };
+
+    Some((accepts_transfers, transfer))
+}
+
+/// Guess why we were disconnected from the reason that the server gave us.
+pub(super) fn classify_disconnect(reason: &str) -> &'static str {
+    let reason = reason.to_lowercase();
+    if reason.contains("whitelist") || reason.contains("white-list") {
+        "whitelist"
+    } else if reason.contains("banned") {
+        "ban"
+    } else if reason.contains("outdated")
+        || reason.contains("incompatible")
+        || reason.contains("version")
+    {
+        "version"
+    } else if reason.contains("forwarding") || reason.contains("ip forward") {
+        "forwarding"
+    } else {
+        "other"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disconnect_packet(reason: &str) -> Vec<u8> {
+        let mut packet = vec![0x00, reason.len() as u8];
+        packet.extend_from_slice(reason.as_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_parse_transfer_response() {
+        let (accepts_transfers, transfer) = parse_transfer_response(&disconnect_packet(
+            r#"{"translate":"multiplayer.disconnect.transfers_disabled"}"#,
+        ))
+        .unwrap();
+        assert!(!accepts_transfers);
+        assert!(transfer.contains_key("disconnect"));
+
+        // a plugin kicking us for something that mentions transfers is still
+        // past the vanilla check
+        let (accepts_transfers, _) = parse_transfer_response(&disconnect_packet(
+            r#"{"text":"You can't transfer here, you're not whitelisted"}"#,
+        ))
+        .unwrap();
+        assert!(accepts_transfers);
+
+        // encryption request
+        let (accepts_transfers, transfer) = parse_transfer_response(&[0x01, 0x00]).unwrap();
+        assert!(accepts_transfers);
+        assert!(!transfer.contains_key("disconnect"));
+
+        assert!(parse_transfer_response(&[0x20]).is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use azalea_chat::FormattedText;
+use bson::doc;
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    modes::protocol_range::probed_field,
+    scanner::protocols::{self, parse_probe, ProbeMethod},
+};
+
+use super::{
+    minecraft::{read_utf, read_varint},
+    minecraft_login::classify_disconnect,
+    ProcessableProtocol, SharedData,
+};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftProtocolRange {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let (method, candidate, data) = parse_probe(data)?;
+
+        let now = bson::DateTime::from_system_time(SystemTime::now());
+        let mut update = doc! {
+            "$set": {
+                "minecraft.supportedProtocols.timestamp": now,
+                probed_field(candidate, method): now,
+            },
+        };
+        let supported_version = match method {
+            ProbeMethod::Status => {
+                // whatever version the server says it's on is supported, which
+                // is either the one we sent or its own
+                let status = serde_json::from_slice::<serde_json::Value>(data).ok()?;
+                let protocol_version = status
+                    .get("version")
+                    .and_then(|v| v.get("protocol"))
+                    .and_then(|v| v.as_i64())
+                    .filter(|&v| v > 0 && v <= i32::MAX as i64)?
+                    as i32;
+                update.insert(
+                    "$inc",
+                    doc! { "minecraft.supportedProtocols.statusProbes": 1 },
+                );
+                Some(protocol_version)
+            }
+            ProbeMethod::Login => login_accepted(data)?.then_some(candidate),
+        };
+
+        if let Some(version) = supported_version {
+            update.insert("$min", doc! { "minecraft.supportedProtocols.min": version });
+            update.insert("$max", doc! { "minecraft.supportedProtocols.max": version });
+        }
+
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update,
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+/// Whether the first login packet means that the server accepted our protocol
+/// version. Returns None if we can't tell.
+fn login_accepted(mut data: &[u8]) -> Option<bool> {
+    let packet_id = read_varint(&mut data)?;
+    match packet_id {
+        // disconnect
+        0x00 => {
+            let reason = read_utf(&mut data)?;
+            let clean_reason = serde_json::from_str::<serde_json::Value>(&reason)
+                .ok()
+                .and_then(|r| FormattedText::deserialize(&r).ok())
+                .map(|r| r.to_string())
+                .unwrap_or(reason);
+            match classify_disconnect(&clean_reason) {
+                "version" => Some(false),
+                // the version is checked before these
+                "whitelist" | "ban" => Some(true),
+                _ => None,
+            }
+        }
+        // encryption request, login success, set compression, plugin request,
+        // and cookie request all come after the version check
+        0x01..=0x05 => Some(true),
+        _ => None,
+    }
+}
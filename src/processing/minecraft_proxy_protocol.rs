@@ -0,0 +1,43 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols,
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::MinecraftProxyProtocol {
+    fn process(
+        shared: &Arc<Mutex<SharedData>>,
+        config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        database: &Database,
+    ) -> Option<BulkUpdate> {
+        // it's a normal status, we just have to remember that it needed the
+        // header
+        let mut bulk_update = <protocols::Minecraft as ProcessableProtocol>::process(
+            shared, config, target, data, database,
+        )?;
+
+        if let Ok(unset) = bulk_update.update.get_document_mut("$unset") {
+            unset.remove("minecraft.requiresProxyProtocol");
+            if unset.is_empty() {
+                bulk_update.update.remove("$unset");
+            }
+        }
+        bulk_update
+            .update
+            .get_document_mut("$set")
+            .ok()?
+            .insert("minecraft.requiresProxyProtocol", true);
+
+        Some(bulk_update)
+    }
+}
@@ -0,0 +1,88 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::{doc, Document};
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols::{self, parse_full_stat},
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::Query {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let full_stat = parse_full_stat(data)?;
+
+        let mut query = Document::new();
+        query.insert("enabled", true);
+        for (key, value) in full_stat.key_values {
+            match key.as_str() {
+                "map" => {
+                    query.insert("map", value);
+                }
+                "gametype" => {
+                    query.insert("gameType", value);
+                }
+                "version" => {
+                    query.insert("version", value);
+                }
+                "plugins" => {
+                    let (software, plugins) = parse_plugins(&value);
+                    if let Some(software) = software {
+                        query.insert("software", software);
+                    }
+                    query.insert("plugins", plugins);
+                }
+                _ => {}
+            }
+        }
+        query.insert("players", full_stat.players);
+        query.insert(
+            "timestamp",
+            bson::DateTime::from_system_time(SystemTime::now()),
+        );
+
+        // only servers that we already found with the server list ping are
+        // queried, so this never upserts
+        Some(BulkUpdate {
+            query: doc! {
+                "ip": { "$eq": target.ip().to_string() },
+                "port": { "$eq": target.port() as u32 }
+            },
+            update: doc! { "$set": { "query": query } },
+            options: None,
+            multi: false,
+        })
+    }
+}
+
+/// Split the plugins string into the server software and the list of plugins.
+///
+/// It looks like `Paper on 1.21: WorldEdit 7.3.0; Essentials 2.20`, but
+/// vanilla servers leave it empty and some servers only send the software.
+fn parse_plugins(plugins: &str) -> (Option<String>, Vec<String>) {
+    let (software, plugins) = match plugins.split_once(": ") {
+        Some((software, plugins)) => (software, plugins),
+        None => (plugins, ""),
+    };
+    let software = Some(software.trim())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let plugins = plugins
+        .split("; ")
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    (software, plugins)
+}
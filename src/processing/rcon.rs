@@ -0,0 +1,45 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use bson::doc;
+use parking_lot::Mutex;
+
+use crate::{
+    config::Config,
+    database::{bulk_write::BulkUpdate, Database},
+    scanner::protocols::{self, parse_auth_response, RconAuth},
+};
+
+use super::{ProcessableProtocol, SharedData};
+
+#[async_trait]
+impl ProcessableProtocol for protocols::Rcon {
+    fn process(
+        _shared: &Arc<Mutex<SharedData>>,
+        _config: &Config,
+        target: SocketAddr,
+        data: &[u8],
+        _database: &Database,
+    ) -> Option<BulkUpdate> {
+        let auth = parse_auth_response(data)?;
+
+        // rcon isn't on the same port as the server, so every server on the
+        // ip gets it
+        Some(BulkUpdate {
+            query: doc! { "ip": { "$eq": target.ip().to_string() } },
+            update: doc! { "$set": {
+                "services.rcon": {
+                    "open": true,
+                    "port": target.port() as u32,
+                    "auth": match auth {
+                        RconAuth::Rejected => "rejected",
+                        RconAuth::Accepted => "accepted",
+                    },
+                    "timestamp": bson::DateTime::from_system_time(SystemTime::now()),
+                }
+            } },
+            options: None,
+            multi: true,
+        })
+    }
+}
@@ -1,14 +1,15 @@
 pub mod protocols;
 pub mod targets;
 pub mod throttle;
+pub mod udp;
 
 use std::{
-    borrow::BorrowMut,
     collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
-    net::SocketAddrV4,
+    net::SocketAddr,
+    ops::Range,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread,
@@ -17,14 +18,28 @@ use std::{
 
 use parking_lot::{Mutex, RwLock};
 use perfect_rand::PerfectRng;
-use pnet::packet::tcp::TcpFlags;
+use pnet::packet::{
+    tcp::{TcpFlags, TcpPacket},
+    Packet,
+};
 use serde::Deserialize;
 use tracing::trace;
 
 use crate::{
-    net::tcp::{StatelessTcp, StatelessTcpWriteHalf},
+    metrics::{PACKETS_DROPPED_COUNTER, PACKETS_RECEIVED_COUNTER, PACKETS_TRUNCATED_COUNTER},
+    net::{
+        interface::Network,
+        p0f::TcpSignature,
+        raw_sockets::PacketStatistics,
+        tcp::{
+            rtt_from_timestamp, IpHeader, StatelessTcp, StatelessTcpReadHalf, StatelessTcpWriteHalf,
+        },
+        udp::StatelessUdpWriteHalf,
+    },
     processing::SharedData,
-    scanner::protocols::{ParseResponseError, Response},
+    scanner::protocols::{
+        ConnectionInfo, Exchange, ParseResponseError, Response, Session, UdpProtocol,
+    },
 };
 use crate::config::FingerprintConfig;
 use crate::net::fingerprint::TcpFingerprint;
@@ -34,10 +49,33 @@ use self::{
     throttle::Throttler,
 };
 
+/// How long we wait for the server to answer when the protocol already has
+/// something to process, like a status that we're waiting for the pong of.
+const STALLED_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long we keep what we learned from a SYN+ACK while waiting for the
+/// server's first data. Servers answer within a second or two, so this is only
+/// for slow ones.
+const SYN_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most SYN+ACKs that we keep at once per receive thread, so hosts that
+/// accept every connection and never send anything can't fill up memory.
+const MAX_SYN_ACKS: usize = 100_000;
+/// The most targets that are kept in [`SharedData::silent_targets`] during a
+/// scan. Most SYN+ACKs in a big scan are from hosts that aren't Minecraft
+/// servers, and the targets that do respond are taken out again, so this is
+/// more than the legacy and proxy protocol limits need by default.
+pub const MAX_SILENT_TARGETS: usize = 2_000_000;
+
 pub struct Scanner {
     pub seed: u64,
-    pub client: StatelessTcp,
-    pub conns: HashMap<SocketAddrV4, ConnState>,
+    pub write: StatelessTcpWriteHalf,
+    pub conns: HashMap<SocketAddr, ConnState>,
+    /// What we learned from SYN+ACKs that we haven't received data for yet,
+    /// along with when they were received. Entries are dropped after
+    /// [`SYN_ACK_TIMEOUT`] and there are at most [`MAX_SYN_ACKS`].
+    pub syn_acks: HashMap<SocketAddr, (ConnectionInfo, Instant)>,
+    /// When we last got a response from each target, so the handshakes from
+    /// retried SYNs can be ignored.
+    pub responded: HashMap<SocketAddr, Instant>,
 }
 
 pub struct ActiveFingerprintingData {
@@ -45,7 +83,13 @@ pub struct ActiveFingerprintingData {
 }
 
 impl Scanner {
-    pub fn new(source_port: SourcePort, fingerprint_config: Option<FingerprintConfig>) -> Self {
+    /// Returns the scanner and the read half of its socket, which should be
+    /// passed to [`ScannerReceiver::recv_loop`].
+    pub fn new(
+        network: &Network,
+        source_port: SourcePort,
+        fingerprint_config: Option<FingerprintConfig>,
+    ) -> (Self, StatelessTcpReadHalf) {
         let seed = rand::random::<u64>();
 
         let mut fingerprint = TcpFingerprint::default();
@@ -53,15 +97,32 @@ impl Scanner {
             fingerprint = TcpFingerprint::parse_signature(cfg.signature.as_str(), cfg.mss);
         }
 
-        let client = StatelessTcp::new(source_port, fingerprint);
-        Scanner {
+        let StatelessTcp { read, write } = StatelessTcp::new(network, source_port, fingerprint);
+        let scanner = Scanner {
             seed,
-            client,
-            conns: HashMap::<SocketAddrV4, ConnState>::new(),
+            write,
+            conns: HashMap::<SocketAddr, ConnState>::new(),
+            syn_acks: HashMap::new(),
+            responded: HashMap::new(),
+        };
+        (scanner, read)
+    }
+
+    /// Another scanner with the same seed for a different receive thread. It
+    /// gets its own socket to reply from and its own connections, since the
+    /// packets for a connection only ever go to one thread.
+    pub fn shard(&self) -> Self {
+        Scanner {
+            seed: self.seed,
+            write: self.write.clone(),
+            conns: HashMap::new(),
+            syn_acks: HashMap::new(),
+            responded: HashMap::new(),
         }
     }
 
-    pub fn purge_old_conns(&mut self, ping_timeout: Duration) {
+    /// Remove connections that are older than `ping_timeout` and return them.
+    pub fn purge_old_conns(&mut self, ping_timeout: Duration) -> Vec<(SocketAddr, ConnState)> {
         let now = Instant::now();
         let mut to_delete = Vec::new();
         for (addr, conn) in &mut self.conns {
@@ -71,9 +132,34 @@ impl Scanner {
                 to_delete.push(*addr)
             }
         }
-        for key in &to_delete {
-            self.conns.remove(key);
-        }
+        to_delete
+            .into_iter()
+            .filter_map(|key| self.conns.remove_entry(&key))
+            .collect()
+    }
+
+    /// Remove connections that the server stopped sending to for
+    /// [`STALLED_TIMEOUT`] while the protocol already has data, and return
+    /// them. Otherwise the data would wait for [`Self::purge_old_conns`], and
+    /// might be processed after we moved on to another protocol.
+    ///
+    /// The SYN+ACKs that are older than [`SYN_ACK_TIMEOUT`] are dropped too.
+    pub fn purge_stalled_conns(&mut self) -> Vec<(SocketAddr, ConnState)> {
+        self.syn_acks
+            .retain(|_, (_, received)| received.elapsed() <= SYN_ACK_TIMEOUT);
+
+        let stalled = self
+            .conns
+            .iter()
+            .filter(|(_, conn)| {
+                !conn.session.data.is_empty() && conn.last_received.elapsed() > STALLED_TIMEOUT
+            })
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<_>>();
+        stalled
+            .into_iter()
+            .filter_map(|key| self.conns.remove_entry(&key))
+            .collect()
     }
 }
 
@@ -82,306 +168,465 @@ pub struct ScannerReceiver {
     pub shared_process_data: Arc<Mutex<SharedData>>,
     pub scanner: Scanner,
     pub has_ended: Arc<AtomicBool>,
+    /// Whether targets that reply with a SYN+ACK but never send a valid
+    /// response should be added to [`SharedData::silent_targets`]. This
+    /// changes with the mode, since only some use them.
+    pub track_silent_targets: Arc<AtomicBool>,
+    /// How long after a response we ignore SYN+ACKs and data from the same
+    /// target, since they're from a retried SYN. Zero if there are no
+    /// retries.
+    pub duplicate_window: Duration,
+}
+
+/// Add the statistics of a receiving socket from the last minute to the
+/// metrics, and tell the user if packets were lost.
+fn report_statistics(statistics: PacketStatistics) {
+    PACKETS_RECEIVED_COUNTER.inc_by(statistics.packets as u64);
+    PACKETS_DROPPED_COUNTER.inc_by(statistics.drops as u64);
+    PACKETS_TRUNCATED_COUNTER.inc_by(statistics.truncated as u64);
+    if statistics.drops > 0 {
+        println!(
+            "The kernel dropped {} of {} received packets in the last minute",
+            statistics.drops, statistics.packets
+        );
+    }
+    if statistics.truncated > 0 {
+        println!(
+            "Dropped {} packets that didn't fit in the RX ring in the last minute, try turning off GRO with `ethtool -K <interface> gro off`",
+            statistics.truncated
+        );
+    }
+}
+
+/// Only used for logging.
+#[derive(Default)]
+struct RecvCounters {
+    syn_acks_received: usize,
+    connections_started: usize,
+}
+
+/// What a receive thread adds to [`SharedData`], kept until the end of the
+/// poll so the threads only take the lock once per batch of packets.
+#[derive(Default)]
+struct PendingData {
+    silent_targets: HashSet<SocketAddr>,
+    responses: Vec<(SocketAddr, Vec<u8>, ConnectionInfo)>,
+}
+
+impl PendingData {
+    fn flush(&mut self, shared_process_data: &Mutex<SharedData>) {
+        if self.silent_targets.is_empty() && self.responses.is_empty() {
+            return;
+        }
+        let shared_process_data = &mut *shared_process_data.lock();
+        // responses go first, since a SYN+ACK that came after one in the
+        // same poll is from a new connection that might stay silent
+        for (address, _, _) in &self.responses {
+            shared_process_data.silent_targets.remove(address);
+        }
+        shared_process_data.queue.extend(self.responses.drain(..));
+        for address in self.silent_targets.drain() {
+            if shared_process_data.silent_targets.len() >= MAX_SILENT_TARGETS {
+                break;
+            }
+            shared_process_data.silent_targets.insert(address);
+        }
+    }
 }
 
 impl ScannerReceiver {
-    pub fn recv_loop(&mut self, ping_timeout: Duration) {
-        let mut received_from_ips = HashSet::<SocketAddrV4>::new();
-        let mut syn_acks_received: usize = 0;
-        let mut connections_started: usize = 0;
+    /// Receive and reply to packets from the read half that
+    /// [`Scanner::new`] returned, until the scan has ended.
+    pub fn recv_loop(&mut self, read: &mut StatelessTcpReadHalf, ping_timeout: Duration) {
+        let mut counters = RecvCounters::default();
+        let mut pending = PendingData::default();
 
         let mut last_purge = Instant::now();
+        let mut last_stalled_purge = Instant::now();
 
         loop {
             if self.has_ended.load(Ordering::Relaxed) {
                 break;
             }
 
-            let protocol = self.protocol.read();
-            while let Some((ipv4, tcp)) = self.scanner.client.read.recv() {
-                let address = SocketAddrV4::new(ipv4.source, tcp.source);
-
-                if tcp.flags & TcpFlags::RST != 0 {
-                    // RST
-                    trace!("RST :( {}", address);
-
-                    if self.scanner.conns.contains_key(&address) {
-                        // the rst might have significance for this protocol
-                        if let Ok(data) = protocol.parse_response(Response::Rst) {
-                            self.shared_process_data
-                                .lock()
-                                .queue
-                                .push_back((address, data));
-                        }
-                    }
+            // this waits for packets, so there's no need to sleep
+            let protocol = self.protocol.clone();
+            let protocol = protocol.read();
+            read.recv_with(Duration::from_millis(50), |ip, tcp| {
+                self.handle_tcp(protocol.as_ref(), ip, tcp, &mut counters, &mut pending)
+            });
+            drop(protocol);
 
-                    continue;
-                } else if tcp.flags & TcpFlags::FIN != 0 {
-                    // FIN
+            if last_stalled_purge.elapsed() > Duration::from_secs(1) {
+                for (address, conn) in self.scanner.purge_stalled_conns() {
+                    trace!("{address} stopped replying, queueing what we have");
+                    self.queue_session_data(address, conn.session, &mut pending);
+                }
+                last_stalled_purge = Instant::now();
+            }
 
-                    if let Some(conn) = self.scanner.conns.get_mut(&address) {
-                        self.scanner.client.write.send_ack(
-                            address,
-                            tcp.destination,
-                            conn.local_seq,
-                            tcp.sequence + 1,
-                        );
+            if last_purge.elapsed() > Duration::from_secs(60) {
+                for (address, conn) in self.scanner.purge_old_conns(ping_timeout) {
+                    self.queue_session_data(address, conn.session, &mut pending);
+                }
+                let duplicate_window = self.duplicate_window;
+                self.scanner
+                    .responded
+                    .retain(|_, responded| responded.elapsed() < duplicate_window);
+                last_purge = Instant::now();
 
-                        if !conn.fin_sent {
-                            self.scanner.client.write.send_fin(
-                                address,
-                                tcp.destination,
-                                conn.local_seq,
-                                tcp.sequence + 1,
-                            );
-                            conn.fin_sent = true;
-                        }
+                if let Some(statistics) = read.statistics() {
+                    report_statistics(statistics);
+                }
+            }
 
-                        if conn.data.is_empty() {
-                            trace!("FIN with no data :( {}:{}", ipv4.source, tcp.source);
-                            // if there was no data then parse that as a response
-                            if let Ok(data) = protocol.parse_response(Response::Data(vec![])) {
-                                self.shared_process_data
-                                    .lock()
-                                    .queue
-                                    .push_back((address, data));
-                            }
-                        } else {
-                            trace!("FIN {}:{}", ipv4.source, tcp.source);
-                            self.scanner.conns.borrow_mut().remove(&address);
+            // one lock for everything we got in this poll
+            pending.flush(&self.shared_process_data);
+        }
+
+        for (address, conn) in self.scanner.purge_old_conns(ping_timeout) {
+            self.queue_session_data(address, conn.session, &mut pending);
+        }
+        pending.flush(&self.shared_process_data);
+    }
+
+    /// Handle a TCP packet that was sent to us.
+    fn handle_tcp(
+        &mut self,
+        protocol: &dyn Protocol,
+        ip: &IpHeader,
+        tcp: &TcpPacket,
+        counters: &mut RecvCounters,
+        pending: &mut PendingData,
+    ) {
+        let address = SocketAddr::new(ip.source, tcp.get_source());
+
+        if tcp.get_flags() & TcpFlags::RST != 0 {
+            // RST
+            trace!("RST :( {}", address);
+
+            if let Some(mut conn) = self.scanner.conns.remove(&address) {
+                // the rst might have significance for this protocol
+                match protocol.handle_response(&mut conn.session, Response::Rst) {
+                    Ok(Exchange::Done(data)) => {
+                        self.queue_response(address, data, conn.session.info, pending)
+                    }
+                    // can't reply to a closed connection, so just keep what we have
+                    _ => self.queue_session_data(address, conn.session, pending),
+                }
+            }
+        } else if tcp.get_flags() & TcpFlags::FIN != 0 {
+            // FIN
+
+            if let Some(mut conn) = self.scanner.conns.remove(&address) {
+                self.scanner.write.send_ack(
+                    address,
+                    tcp.get_destination(),
+                    conn.local_seq,
+                    tcp.get_sequence() + 1,
+                );
+
+                if !conn.fin_sent {
+                    self.scanner.write.send_fin(
+                        address,
+                        tcp.get_destination(),
+                        conn.local_seq,
+                        tcp.get_sequence() + 1,
+                    );
+                }
+
+                if conn.data.is_empty() {
+                    trace!("FIN with no data :( {}", address);
+                    // if there was no data then parse that as a response
+                    match protocol.handle_response(&mut conn.session, Response::Data(vec![])) {
+                        Ok(Exchange::Done(data)) => {
+                            self.queue_response(address, data, conn.session.info, pending)
                         }
-                    } else {
-                        trace!(
-                            "FIN with no connection, probably already forgotten by us {}:{}",
-                            ipv4.source,
-                            tcp.source
-                        );
-                        self.scanner.client.write.send_ack(
-                            address,
-                            tcp.destination,
-                            tcp.acknowledgement,
-                            tcp.sequence + 1,
-                        );
+                        _ => self.queue_session_data(address, conn.session, pending),
                     }
+                } else {
+                    trace!("FIN {}", address);
+                    self.queue_session_data(address, conn.session, pending);
+                }
+            } else {
+                trace!(
+                    "FIN with no connection, probably already forgotten by us {}",
+                    address
+                );
+                self.scanner.write.send_ack(
+                    address,
+                    tcp.get_destination(),
+                    tcp.get_acknowledgement(),
+                    tcp.get_sequence() + 1,
+                );
+            }
+        } else if tcp.get_flags() & TcpFlags::SYN != 0 && tcp.get_flags() & TcpFlags::ACK != 0 {
+            trace!("SYN+ACK {}", address);
+
+            // SYN+ACK
+            // verify that the ack is the cookie+1
+            let ack_number = tcp.get_acknowledgement();
+
+            let original_cookie = cookie(&address, self.scanner.seed);
+            let expected_ack = original_cookie + 1;
+            if ack_number != expected_ack {
+                trace!("cookie mismatch for {address} (expected {expected_ack}, got {ack_number})");
+                return;
+            }
 
-                    continue;
-                } else if tcp.flags & TcpFlags::SYN != 0 && tcp.flags & TcpFlags::ACK != 0 {
-                    trace!("SYN+ACK {}:{}", ipv4.source, tcp.source);
+            if self.is_duplicate(address) {
+                trace!("SYN+ACK from a retry to {address}, we already have a response");
+                // close it so the server doesn't wait for us
+                self.scanner.write.send_rst(
+                    address,
+                    tcp.get_destination(),
+                    tcp.get_acknowledgement(),
+                    tcp.get_sequence() + 1,
+                );
+                return;
+            }
 
-                    received_from_ips.insert(address);
+            self.scanner.write.send_ack(
+                address,
+                tcp.get_destination(),
+                tcp.get_acknowledgement(),
+                tcp.get_sequence() + 1,
+            );
+
+            let info = ConnectionInfo {
+                tcp_rtt: rtt_from_timestamp(tcp),
+                tcp_signature: Some(TcpSignature::new(ip, tcp)),
+                ..Default::default()
+            };
+            if self.scanner.syn_acks.len() < MAX_SYN_ACKS {
+                self.scanner
+                    .syn_acks
+                    .insert(address, (info, Instant::now()));
+            }
 
-                    // SYN+ACK
-                    // verify that the ack is the cookie+1
-                    let ack_number = tcp.acknowledgement;
+            let Some(payload) = initial_payload(protocol, address) else {
+                // this means we're skipping this server, give them an rst
+                self.scanner.write.send_rst(
+                    address,
+                    tcp.get_destination(),
+                    tcp.get_acknowledgement(),
+                    tcp.get_sequence() + 1,
+                );
+                return;
+            };
+            // if the server talks first then the ack was all we had to send
+            if !payload.is_empty() {
+                self.scanner.write.send_data(
+                    address,
+                    tcp.get_destination(),
+                    tcp.get_acknowledgement(),
+                    tcp.get_sequence() + 1,
+                    &payload,
+                );
+            }
 
-                    let original_cookie = cookie(&address, self.scanner.seed);
-                    let expected_ack = original_cookie + 1;
-                    if ack_number != expected_ack {
-                        trace!("cookie mismatch for {address} (expected {expected_ack}, got {ack_number})");
-                        continue;
-                    }
+            counters.syn_acks_received += 1;
+            trace!("syn acks: {}", counters.syn_acks_received);
 
-                    self.scanner.client.write.send_ack(
-                        address,
-                        tcp.destination,
-                        tcp.acknowledgement,
-                        tcp.sequence + 1,
-                    );
+            if self.track_silent_targets.load(Ordering::Relaxed) {
+                pending.silent_targets.insert(address);
+            }
+        } else if tcp.get_flags() & TcpFlags::ACK != 0 {
+            // ACK
+            trace!(
+                "ACK {address} with data: {}",
+                String::from_utf8_lossy(tcp.payload())
+            );
+
+            // cookie +packet size + 1
+            let actual_ack = tcp.get_acknowledgement();
+
+            if tcp.payload().is_empty() {
+                // just an ack and not data
+                return;
+            }
+
+            // check if it's already in the connections map
+            let is_tracked = if let Some(conn) = self.scanner.conns.get_mut(&address) {
+                let actual_seq = tcp.get_sequence();
+                let expected_seq = conn.remote_seq;
+                // this means it's adding more data to this connection
+                match conn.receive(actual_seq, actual_ack, tcp.payload()) {
+                    Ok(()) => {}
+                    Err(InvalidSegment::WrongSeq) => {
+                        let difference = actual_seq as i64 - expected_seq as i64;
+                        trace!(
+                            "Got wrong seq number {actual_seq}! expected {expected_seq} (difference = {difference}). This is probably because of a re-transmission.",
+                        );
 
-                    let payload = protocol.payload(address);
-                    if payload.is_empty() {
-                        // this means we're skipping this server, give them an rst
-                        self.scanner.client.write.send_rst(
+                        self.scanner.write.send_ack(
                             address,
-                            tcp.destination,
-                            tcp.acknowledgement,
-                            tcp.sequence + 1,
+                            tcp.get_destination(),
+                            conn.local_seq,
+                            expected_seq,
+                        );
+                        return;
+                    }
+                    Err(InvalidSegment::WrongAck) => {
+                        trace!(
+                            "Got wrong ack number {actual_ack} from {address}, expected {}",
+                            conn.local_seq
                         );
-                        continue;
+                        return;
+                    }
+                }
+                true
+            } else {
+                // this means it's the first data packet we got, verify it
+                let original_cookie = cookie(&address, self.scanner.seed);
+                // the only thing we sent before the connection is
+                // tracked is the SYN and initial payload, so this is
+                // fine
+                let expected_ack = expected_first_ack(original_cookie, &protocol.payload(address));
+                if actual_ack != expected_ack {
+                    trace!("cookie mismatch when reading data for {address} (expected {expected_ack}, got {actual_ack}, initial was {original_cookie})");
+                    return;
+                }
+                if self.is_duplicate(address) {
+                    trace!("data from a retried handshake with {address}, ignoring");
+                    return;
+                }
+
+                let mut session = Session {
+                    address: Some(address),
+                    ..Default::default()
+                };
+                if let Some((info, syn_ack_time)) = self.scanner.syn_acks.remove(&address) {
+                    session.info = info;
+                    // we don't remember when the syn was sent, so without
+                    // timestamps the closest thing is how long the server
+                    // took to answer our ack
+                    if session.info.tcp_rtt.is_none() {
+                        session.info.tcp_rtt = Some(syn_ack_time.elapsed());
                     }
-                    self.scanner.client.write.send_data(
+                }
+                self.scanner.conns.insert(
+                    address,
+                    ConnState::new(tcp.get_sequence(), actual_ack, tcp.payload(), session),
+                );
+                counters.connections_started += 1;
+                trace!("connection #{} started", counters.connections_started);
+                false
+            };
+
+            let conn = self.scanner.conns.get_mut(&address).unwrap();
+            let exchange =
+                protocol.handle_response(&mut conn.session, Response::Data(conn.data.clone()));
+
+            match exchange {
+                Ok(Exchange::Done(data)) => {
+                    let data_string = String::from_utf8_lossy(&data);
+                    trace!("\n\n{address} {data_string}");
+
+                    // the data is in the response now so it shouldn't
+                    // be queued again when the connection closes
+                    conn.session.data.clear();
+
+                    self.scanner.write.send_ack(
                         address,
-                        tcp.destination,
-                        tcp.acknowledgement,
-                        tcp.sequence + 1,
-                        &payload,
+                        tcp.get_destination(),
+                        conn.local_seq,
+                        conn.remote_seq,
                     );
-
-                    syn_acks_received += 1;
-                    trace!("syn acks: {syn_acks_received}");
-
-                } else if tcp.flags & TcpFlags::ACK != 0 {
-                    // ACK
-                    trace!(
-                        "ACK {address} with data: {}",
-                        String::from_utf8_lossy(&tcp.payload)
+                    self.scanner.write.send_fin(
+                        address,
+                        tcp.get_destination(),
+                        conn.local_seq,
+                        conn.remote_seq,
                     );
+                    conn.fin_sent = true;
 
-                    // cookie +packet size + 1
-                    let actual_ack = tcp.acknowledgement;
-
-                    if tcp.payload.is_empty() {
-                        // just an ack and not data
-                        continue;
-                    }
-
-                    // check if it's already in the connections map
-                    let (ping_response, is_tracked) = if let Some(conn) =
-                        self.scanner.conns.get_mut(&address)
-                    {
-                        let actual_seq = tcp.sequence;
-                        let expected_seq = conn.remote_seq;
-                        if actual_seq != conn.remote_seq {
-                            let difference = actual_seq as i64 - expected_seq as i64;
-                            trace!(
-                                "Got wrong seq number {actual_seq}! expected {expected_seq} (difference = {difference}). This is probably because of a re-transmission.",
-                            );
-
-                            self.scanner.client.write.send_ack(
-                                address,
-                                tcp.destination,
-                                actual_ack,
-                                expected_seq,
-                            );
-
-                            continue;
-                        }
-                        // this means it's adding more data to this connection
-                        conn.data.extend(tcp.payload.clone());
-                        conn.remote_seq = actual_seq + tcp.payload.len() as u32;
-                        (
-                            protocol.parse_response(Response::Data(conn.data.clone())),
-                            true,
-                        )
-                    } else {
-                        // this means it's the first data packet we got, verify it
-                        let original_cookie = cookie(&address, self.scanner.seed);
-                        // we never send anything other than the SYN and initial ping so this is
-                        // fine
-                        let packet_size = protocol.payload(address).len();
-                        let cookie_offset = (packet_size + 1) as u32;
-
-                        let expected_ack = original_cookie.wrapping_add(cookie_offset);
-                        if actual_ack != expected_ack {
-                            trace!("cookie mismatch when reading data for {address} (expected {expected_ack}, got {actual_ack}, initial was {original_cookie})");
-                            continue;
-                        }
-
-                        let ping_response =
-                            protocol.parse_response(Response::Data(tcp.payload.clone()));
-                        (ping_response, false)
-                    };
-
-                    match ping_response {
-                        Ok(data) => {
-                            let data_string = String::from_utf8_lossy(&data);
-                            trace!("\n\n{address} {data_string}");
-
-                            if !is_tracked {
-                                self.scanner.conns.borrow_mut().insert(
-                                    address,
-                                    ConnState {
-                                        data: tcp.payload.to_vec(),
-                                        remote_seq: tcp
-                                            .sequence
-                                            .wrapping_add(tcp.payload.len() as u32),
-                                        local_seq: tcp.acknowledgement,
-                                        started: Instant::now(),
-                                        fin_sent: false,
-                                    },
-                                );
-                                connections_started += 1;
-                                trace!("connection #{connections_started} started");
-                            }
+                    let info = conn.session.info;
+                    self.queue_response(address, data, info, pending);
+                }
+                Ok(Exchange::Reply(payload)) => {
+                    trace!("replying to {address} with {} bytes", payload.len());
 
-                            let conn = self.scanner.conns.get(&address).unwrap();
-
-                            self.shared_process_data
-                                .lock()
-                                .queue
-                                .push_back((address, data));
-
-                            self.scanner.client.write.send_ack(
-                                address,
-                                tcp.destination,
-                                actual_ack,
-                                conn.remote_seq,
-                            );
-                            self.scanner.client.write.send_fin(
-                                address,
-                                tcp.destination,
-                                actual_ack,
-                                conn.remote_seq,
-                            );
-
-                            if !is_tracked {
-                                connections_started += 1;
-                                trace!("connection #{connections_started} started and ended immediately");
-                            }
-                        }
-                        Err(e) => {
-                            match e {
-                                ParseResponseError::Invalid => {
-                                    trace!("packet error, ignoring");
-                                }
-                                ParseResponseError::Incomplete { .. } => {
-                                    if !is_tracked {
-                                        self.scanner.conns.borrow_mut().insert(
-                                            address,
-                                            ConnState {
-                                                data: tcp.payload.to_vec(),
-                                                remote_seq: tcp
-                                                    .sequence
-                                                    .wrapping_add(tcp.payload.len() as u32),
-                                                local_seq: tcp.acknowledgement,
-                                                started: Instant::now(),
-                                                fin_sent: false,
-                                            },
-                                        );
-                                        connections_started += 1;
-                                        trace!("connection #{connections_started} started");
-                                    }
-
-                                    let conn = self.scanner.conns.get(&address).unwrap();
-                                    // always ack whatever they send
-                                    // a better tcp implementation would only ack every 2 packets or
-                                    // after .5 seconds but this technically still follows the spec
-                                    self.scanner.client.write.send_ack(
-                                        address,
-                                        tcp.destination,
-                                        actual_ack,
-                                        conn.remote_seq,
-                                    );
-                                }
-                            };
-                        }
+                    self.scanner.write.send_data(
+                        address,
+                        tcp.get_destination(),
+                        conn.local_seq,
+                        conn.remote_seq,
+                        &payload,
+                    );
+                    conn.reply(payload.len());
+                }
+                Err(ParseResponseError::Invalid) => {
+                    trace!("packet error, ignoring");
+                    if !is_tracked {
+                        self.scanner.conns.remove(&address);
                     }
                 }
+                Err(ParseResponseError::Incomplete { .. }) => {
+                    // always ack whatever they send
+                    // a better tcp implementation would only ack every 2 packets or
+                    // after .5 seconds but this technically still follows the spec
+                    self.scanner.write.send_ack(
+                        address,
+                        tcp.get_destination(),
+                        conn.local_seq,
+                        conn.remote_seq,
+                    );
+                }
             }
-            drop(protocol);
+        }
+    }
 
-            // sleep for 50ms
-            thread::sleep(Duration::from_millis(50));
+    /// Whether we already got a response from the target recently, which
+    /// means anything else from it is from a retried SYN.
+    fn is_duplicate(&self, address: SocketAddr) -> bool {
+        self.scanner
+            .responded
+            .get(&address)
+            .is_some_and(|responded| responded.elapsed() < self.duplicate_window)
+    }
 
-            if last_purge.elapsed() > Duration::from_secs(60) {
-                self.scanner.purge_old_conns(ping_timeout);
-                last_purge = Instant::now();
-            }
+    /// Add a response to the processing queue once the poll is done.
+    fn queue_response(
+        &mut self,
+        address: SocketAddr,
+        data: Vec<u8>,
+        info: ConnectionInfo,
+        pending: &mut PendingData,
+    ) {
+        if !self.duplicate_window.is_zero() {
+            self.scanner.responded.insert(address, Instant::now());
         }
+        pending.silent_targets.remove(&address);
+        pending.responses.push((address, data, info));
+    }
 
-        self.scanner.purge_old_conns(ping_timeout);
+    /// Queue whatever a session collected before its connection was closed.
+    fn queue_session_data(
+        &mut self,
+        address: SocketAddr,
+        session: Session,
+        pending: &mut PendingData,
+    ) {
+        if !session.data.is_empty() {
+            self.queue_response(address, session.data, session.info, pending);
+        }
     }
 }
 
 pub struct ScanSession {
     pub rng: PerfectRng,
     pub ranges: StaticScanRanges,
+    /// How many more times every target is sent to.
+    pub retries: u64,
+    /// The time between the attempts for the same target.
+    pub retry_spacing: Duration,
 }
 
 /// The state stored for active connections. We try to keep this existing for
 /// the shortest amount of time possible.
 pub struct ConnState {
-    /// The data we've received so far.
+    /// The data we've received since our last reply.
     data: Vec<u8>,
 
     /// The last received sequence number + payload length
@@ -391,19 +636,75 @@ pub struct ConnState {
     /// aka the next expected starting sequence number.
     remote_seq: u32,
 
-    /// The sequence number we send.
+    /// The sequence number we send. This goes up by the length of every
+    /// reply we send.
     local_seq: u32,
 
     /// The time that the connection was created. Connections are closed 30
     /// seconds after creation (if it wasn't closed earlier).
     started: Instant,
 
+    /// When we last got data from the server.
+    last_received: Instant,
+
     /// Whether we've sent a fin packet.
     fin_sent: bool,
+
+    /// The protocol's state for this connection.
+    session: Session,
+}
+
+/// Why a data segment for a tracked connection was rejected.
+#[derive(Debug, PartialEq)]
+enum InvalidSegment {
+    /// Not the next sequence number, usually a re-transmission.
+    WrongSeq,
+    /// Doesn't acknowledge everything we sent, so it's not a reply to our last
+    /// exchange.
+    WrongAck,
+}
+
+impl ConnState {
+    /// Start tracking a connection from the first data segment, which was
+    /// already checked against the cookie.
+    fn new(seq: u32, ack: u32, payload: &[u8], session: Session) -> Self {
+        Self {
+            data: payload.to_vec(),
+            remote_seq: seq.wrapping_add(payload.len() as u32),
+            local_seq: ack,
+            started: Instant::now(),
+            last_received: Instant::now(),
+            fin_sent: false,
+            session,
+        }
+    }
+
+    /// Add the payload of a data segment if it's the next one and it acks
+    /// everything we sent.
+    fn receive(&mut self, seq: u32, ack: u32, payload: &[u8]) -> Result<(), InvalidSegment> {
+        if seq != self.remote_seq {
+            return Err(InvalidSegment::WrongSeq);
+        }
+        if ack != self.local_seq {
+            return Err(InvalidSegment::WrongAck);
+        }
+        self.data.extend_from_slice(payload);
+        self.remote_seq = seq.wrapping_add(payload.len() as u32);
+        self.last_received = Instant::now();
+        Ok(())
+    }
+
+    /// Update the state after we sent a reply of `len` bytes.
+    fn reply(&mut self, len: usize) {
+        // the next response is parsed on its own
+        self.data.clear();
+        self.session.exchanges += 1;
+        self.local_seq = self.local_seq.wrapping_add(len as u32);
+    }
 }
 
 pub struct PingResponse {
-    pub target: SocketAddrV4,
+    pub target: SocketAddr,
     pub data: String,
 }
 
@@ -412,95 +713,276 @@ impl ScanSession {
         Self {
             rng: PerfectRng::new(ranges.count() as u64, rand::random(), 3),
             ranges: ranges.to_static(),
+            retries: 0,
+            retry_spacing: Duration::from_secs(1),
         }
     }
 
     /// Run the scanner for `scan_duration_secs` and then sleep for
     /// `sleep_secs`.
     ///
-    /// Returns the number of packets sent.
+    /// Returns the number of targets that were sent to, retries aren't
+    /// counted.
     pub fn run(
         self,
         max_packets_per_second: u64,
-        scanner_writer: &mut StatelessTcpWriteHalf,
+        scanner_writer: StatelessTcpWriteHalf,
         seed: u64,
         scan_duration_secs: u64,
+        threads: usize,
     ) -> u64 {
-        let mut throttler = Throttler::new(max_packets_per_second);
+        let senders = clone_for_threads(scanner_writer, threads)
+            .into_iter()
+            .map(|mut scanner_writer| {
+                move |targets: &mut dyn Iterator<Item = SocketAddr>| {
+                    for destination_addr in targets {
+                        trace!("sending syn to {destination_addr}");
+                        scanner_writer.send_syn(destination_addr, cookie(&destination_addr, seed));
+                    }
+                    // one syscall per batch instead of per packet
+                    scanner_writer.flush();
+                }
+            })
+            .collect();
+        self.run_with(max_packets_per_second, scan_duration_secs, senders)
+    }
 
-        let mut packets_sent: u64 = 0;
+    /// Like [`Self::run`], but sends the protocol's payload in a UDP datagram
+    /// instead of a SYN.
+    pub fn run_udp(
+        self,
+        max_packets_per_second: u64,
+        scanner_writer: StatelessUdpWriteHalf,
+        protocol: &dyn UdpProtocol,
+        seed: u64,
+        scan_duration_secs: u64,
+        threads: usize,
+    ) -> u64 {
+        let senders = clone_for_threads(scanner_writer, threads)
+            .into_iter()
+            .map(|mut scanner_writer| {
+                move |targets: &mut dyn Iterator<Item = SocketAddr>| {
+                    for destination_addr in targets {
+                        trace!("sending datagram to {destination_addr}");
+                        let cookie = cookie(&destination_addr, seed);
+                        let payload = protocol.payload(destination_addr, cookie);
+                        scanner_writer.send_udp(destination_addr, cookie, &payload);
+                    }
+                }
+            })
+            .collect();
+        self.run_with(max_packets_per_second, scan_duration_secs, senders)
+    }
 
+    /// Send to every target at the given rate, with one thread per sender.
+    /// The senders are called with the targets of every throttler batch.
+    fn run_with<F>(
+        self,
+        max_packets_per_second: u64,
+        scan_duration_secs: u64,
+        senders: Vec<F>,
+    ) -> u64
+    where
+        F: FnMut(&mut dyn Iterator<Item = SocketAddr>) + Send,
+    {
+        // the retries use up some of the time too
         let target_count = u64::min(
             self.ranges.count as u64,
-            max_packets_per_second * scan_duration_secs,
+            max_packets_per_second * scan_duration_secs / (self.retries + 1),
         );
 
-        let start = Instant::now();
-
-        let mut packets_sent_last_print = 0;
-        let mut last_print_time = Instant::now();
+        let state = SendState {
+            throttler: Mutex::new(Throttler::new(max_packets_per_second)),
+            next_step: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            targets_sent: AtomicU64::new(0),
+        };
 
-        loop {
-            // print info about packets per second every 5 seconds
-            let time_since_last_print = Instant::now() - last_print_time;
-            if packets_sent != 0 && time_since_last_print > Duration::from_secs(5) {
-                let packets_per_second = (packets_sent - packets_sent_last_print) as f64
-                    / (Instant::now() - last_print_time).as_secs_f64();
-
-                let packets_per_info = if packets_per_second > 10_000_000. {
-                    format!("{} mpps", (packets_per_second / 1_000_000.).round() as u64)
-                } else if packets_per_second > 10_000. {
-                    format!("{} kpps", (packets_per_second / 1_000.).round() as u64)
-                } else {
-                    format!("{} pps", packets_per_second.round() as u64)
-                };
+        let start = Instant::now();
 
-                let packets_per_second = throttler.estimated_packets_per_second() as f64;
-                let throttler_packets_per_info = if packets_per_second > 10_000_000. {
-                    format!("{} mpps", (packets_per_second / 1_000_000.).round() as u64)
-                } else if packets_per_second > 10_000. {
-                    format!("{} kpps", (packets_per_second / 1_000.).round() as u64)
-                } else {
-                    format!("{} pps", packets_per_second.round() as u64)
-                };
-                println!("Sent {packets_sent} packets ({packets_per_info}, throttler estimate: {throttler_packets_per_info})");
+        thread::scope(|s| {
+            let handles = senders
+                .into_iter()
+                .map(|send_batch| {
+                    let session = &self;
+                    let state = &state;
+                    s.spawn(move || {
+                        session.send_indexes(
+                            0..target_count,
+                            max_packets_per_second,
+                            start + Duration::from_secs(scan_duration_secs),
+                            state,
+                            send_batch,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut packets_sent_last_print = 0;
+            let mut last_print_time = Instant::now();
+            while !handles.iter().all(|handle| handle.is_finished()) {
+                thread::sleep(Duration::from_millis(100));
+
+                // print info about packets per second every 5 seconds
+                let time_since_last_print = Instant::now() - last_print_time;
+                let packets_sent = state.packets_sent.load(Ordering::Relaxed);
+                if packets_sent != 0 && time_since_last_print > Duration::from_secs(5) {
+                    let packets_per_second = (packets_sent - packets_sent_last_print) as f64
+                        / (Instant::now() - last_print_time).as_secs_f64();
+
+                    let packets_per_info = if packets_per_second > 10_000_000. {
+                        format!("{} mpps", (packets_per_second / 1_000_000.).round() as u64)
+                    } else if packets_per_second > 10_000. {
+                        format!("{} kpps", (packets_per_second / 1_000.).round() as u64)
+                    } else {
+                        format!("{} pps", packets_per_second.round() as u64)
+                    };
 
-                packets_sent_last_print = packets_sent;
-                last_print_time = Instant::now();
-            }
+                    let packets_per_second =
+                        state.throttler.lock().estimated_packets_per_second() as f64;
+                    let throttler_packets_per_info = if packets_per_second > 10_000_000. {
+                        format!("{} mpps", (packets_per_second / 1_000_000.).round() as u64)
+                    } else if packets_per_second > 10_000. {
+                        format!("{} kpps", (packets_per_second / 1_000.).round() as u64)
+                    } else {
+                        format!("{} pps", packets_per_second.round() as u64)
+                    };
+                    println!("Sent {packets_sent} packets ({packets_per_info}, throttler estimate: {throttler_packets_per_info})");
 
-            let mut batch_size = throttler.next_batch();
-            if packets_sent + batch_size > target_count {
-                batch_size = target_count - packets_sent;
+                    packets_sent_last_print = packets_sent;
+                    last_print_time = Instant::now();
+                }
             }
+        });
+
+        let packets_sent = state.packets_sent.into_inner();
+        let targets_sent = state.targets_sent.into_inner();
+        if targets_sent >= target_count {
+            println!("Finished sending {packets_sent} packets to {targets_sent} targets.");
+        } else {
+            // if it's been more than 5 minutes since we started, the scan was
+            // finished early
+            println!("{scan_duration_secs} seconds passed, finishing scan.");
+        }
 
-            // tight packet-sending loop
-            for _ in 0..batch_size {
-                let shuffled_index = self.rng.shuffle(packets_sent);
-                let destination_addr = self.ranges.index(shuffled_index as usize);
-                trace!("sending syn to {destination_addr}");
-                scanner_writer.send_syn(destination_addr, cookie(&destination_addr, seed));
-                packets_sent += 1;
-            }
+        targets_sent
+    }
 
-            if packets_sent >= target_count {
-                println!("Finished sending {packets_sent} packets.");
-                break;
-            }
-            // if it's been more than 5 minutes since we started, finish the scan
-            else if (Instant::now() - start).as_secs() > scan_duration_secs {
-                println!("{scan_duration_secs} seconds passed, finishing scan.");
+    /// Send to the targets at the given shuffled indexes until `deadline`.
+    /// Every sender thread calls this with the same indexes and `state`, and
+    /// takes the next steps that nobody has sent yet.
+    ///
+    /// Every retry is sent `retry_spacing` after the previous attempt for the
+    /// same target, in between the attempts for other targets. So with 1
+    /// retry, the order looks like `a b c ... a' b' c' ...` where `a` and `a'`
+    /// are `retry_spacing` apart.
+    fn send_indexes(
+        &self,
+        indexes: Range<u64>,
+        max_packets_per_second: u64,
+        deadline: Instant,
+        state: &SendState,
+        mut send_batch: impl FnMut(&mut dyn Iterator<Item = SocketAddr>),
+    ) {
+        let retries = self.retries;
+        let start = indexes.start;
+        let count = indexes.end - indexes.start;
+        // the number of steps between attempts for the same target, every
+        // step sends up to `retries + 1` packets
+        let spacing = ((max_packets_per_second as f64 * self.retry_spacing.as_secs_f64()) as u64
+            / (retries + 1))
+            .max(1);
+        let step_count = count + retries * spacing;
+
+        while Instant::now() < deadline {
+            let batch_size = state.throttler.lock().next_batch();
+            let batch_steps = (batch_size / (retries + 1)).max(1);
+            let first_step = state.next_step.fetch_add(batch_steps, Ordering::Relaxed);
+            if first_step >= step_count {
                 break;
             }
+            let steps = first_step..u64::min(first_step + batch_steps, step_count);
+
+            let mut sent = 0;
+            let mut first_attempts = 0;
+            // tight packet-sending loop
+            let mut targets = retry_steps(start, count, retries, spacing, steps)
+                .flatten()
+                .map(|(attempt, i)| {
+                    sent += 1;
+                    if attempt == 0 {
+                        first_attempts += 1;
+                    }
+                    let shuffled_index = self.rng.shuffle(i);
+                    self.ranges.index(shuffled_index as usize)
+                });
+            send_batch(&mut targets);
+
+            state.packets_sent.fetch_add(sent, Ordering::Relaxed);
+            state
+                .targets_sent
+                .fetch_add(first_attempts, Ordering::Relaxed);
         }
+    }
+}
+
+/// What the sender threads share. They all take their batches from the same
+/// throttler and the next unsent steps, so a thread that falls behind doesn't
+/// lower the rate or hold on to targets that the other threads could send.
+struct SendState {
+    throttler: Mutex<Throttler>,
+    /// The first step of [`retry_steps`] that no thread has taken yet.
+    next_step: AtomicU64,
+    packets_sent: AtomicU64,
+    targets_sent: AtomicU64,
+}
 
-        packets_sent
+/// The `(attempt, index)` pairs to send at each of the given steps, so over
+/// all `count + retries * spacing` steps every index in `start..start + count`
+/// is sent `retries + 1` times with `spacing` steps between the attempts.
+fn retry_steps(
+    start: u64,
+    count: u64,
+    retries: u64,
+    spacing: u64,
+    steps: Range<u64>,
+) -> impl Iterator<Item = impl Iterator<Item = (u64, u64)>> {
+    steps.map(move |step| {
+        (0..=retries).filter_map(move |attempt| {
+            let i = step.checked_sub(attempt * spacing)?;
+            (i < count).then_some((attempt, start + i))
+        })
+    })
+}
+
+/// The item and `threads - 1` clones of it, so every thread has its own.
+fn clone_for_threads<T: Clone>(item: T, threads: usize) -> Vec<T> {
+    let mut items = vec![item];
+    while items.len() < threads {
+        items.push(items[0].clone());
     }
+    items
+}
+
+/// The payload that's sent after the handshake, which is empty if we wait for
+/// the server to talk first. None if the target is skipped.
+fn initial_payload(protocol: &dyn Protocol, address: SocketAddr) -> Option<Vec<u8>> {
+    let payload = protocol.payload(address);
+    if payload.is_empty() && !protocol.server_talks_first() {
+        return None;
+    }
+    Some(payload)
+}
+
+/// The ack number of the first data from the server, which acknowledges the
+/// SYN and our initial payload.
+fn expected_first_ack(cookie: u32, payload: &[u8]) -> u32 {
+    cookie.wrapping_add(payload.len() as u32 + 1)
 }
 
-fn cookie(address: &SocketAddrV4, seed: u64) -> u32 {
+pub(crate) fn cookie(address: &SocketAddr, seed: u64) -> u32 {
     let mut hasher = DefaultHasher::new();
-    (*address.ip(), address.port(), seed).hash(&mut hasher);
+    (address.ip(), address.port(), seed).hash(&mut hasher);
     hasher.finish() as u32
 }
 
@@ -539,3 +1021,237 @@ impl Default for SourcePort {
         SourcePort::Number(61000)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{HostnameStrategy, ProbeConfig},
+        scanner::targets::ScanRange,
+    };
+
+    /// Sends a fixed payload, or nothing to skip the target.
+    struct FixedPayload(&'static [u8]);
+
+    impl Protocol for FixedPayload {
+        fn payload(&self, _address: SocketAddr) -> Vec<u8> {
+            self.0.to_vec()
+        }
+
+        fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+            match response {
+                Response::Data(data) => Ok(data),
+                Response::Rst => Err(ParseResponseError::Invalid),
+            }
+        }
+    }
+
+    #[test]
+    fn test_listen_only_probe() {
+        let address = SocketAddr::from(([1, 2, 3, 4], 22));
+        let probe = protocols::GenericProbe::new(&ProbeConfig {
+            name: "ssh".to_string(),
+            payload: None,
+            payload_hex: None,
+            ports: vec![22],
+            max_bytes: None,
+            complete: Some(r"\r\n".to_string()),
+            fields: HashMap::from([("banner".to_string(), r"^SSH-[^\r\n]+".to_string())]),
+        })
+        .unwrap();
+
+        // we wait for the banner instead of skipping the server
+        assert_eq!(initial_payload(&probe, address), Some(vec![]));
+        // so the banner only acks the SYN
+        assert_eq!(expected_first_ack(1000, &[]), 1001);
+
+        let mut session = Session::default();
+        let Ok(Exchange::Done(data)) = probe.handle_response(
+            &mut session,
+            Response::Data(b"SSH-2.0-OpenSSH_9.6\r\n".to_vec()),
+        ) else {
+            panic!("the banner should be complete");
+        };
+        assert_eq!(
+            probe.extract(&data),
+            vec![("banner".to_string(), "SSH-2.0-OpenSSH_9.6".to_string())]
+        );
+
+        // other protocols skip the target with an empty payload
+        assert_eq!(initial_payload(&FixedPayload(b""), address), None);
+        let payload = initial_payload(&FixedPayload(b"hello"), address).unwrap();
+        assert_eq!(expected_first_ack(u32::MAX, &payload), 5);
+    }
+
+    #[test]
+    fn test_retry_steps() {
+        let (start, count, retries, spacing) = (100, 50, 2, 7);
+
+        let mut steps_by_index = HashMap::<u64, Vec<(u64, u64)>>::new();
+        let steps = 0..count + retries * spacing;
+        for (step, attempts) in retry_steps(start, count, retries, spacing, steps).enumerate() {
+            for (attempt, i) in attempts {
+                steps_by_index
+                    .entry(i)
+                    .or_default()
+                    .push((attempt, step as u64));
+            }
+        }
+
+        assert_eq!(steps_by_index.len(), count as usize);
+        for (i, steps) in steps_by_index {
+            assert!((start..start + count).contains(&i));
+            assert_eq!(steps.len(), retries as usize + 1);
+            for (n, window) in steps.windows(2).enumerate() {
+                assert_eq!(window[0].0, n as u64);
+                assert_eq!(window[1].0, n as u64 + 1);
+                assert_eq!(window[1].1 - window[0].1, spacing);
+            }
+        }
+    }
+
+    #[test]
+    fn test_send_indexes_counts_first_attempts() {
+        let mut ranges = ScanRanges::new();
+        ranges.extend(vec![ScanRange::single_port(
+            [10, 0, 0, 0],
+            [10, 0, 0, 99],
+            25565,
+        )]);
+        let mut session = ScanSession::new(ranges);
+        session.retries = 2;
+        session.retry_spacing = Duration::from_millis(10);
+
+        let state = SendState {
+            throttler: Mutex::new(Throttler::new(1_000_000)),
+            next_step: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            targets_sent: AtomicU64::new(0),
+        };
+        let mut sent = HashMap::<SocketAddr, u64>::new();
+        session.send_indexes(
+            0..100,
+            1_000_000,
+            Instant::now() + Duration::from_secs(10),
+            &state,
+            |targets| {
+                for target in targets {
+                    *sent.entry(target).or_default() += 1;
+                }
+            },
+        );
+
+        assert_eq!(sent.len(), 100);
+        assert!(sent.values().all(|&n| n == 3));
+        assert_eq!(state.packets_sent.load(Ordering::Relaxed), 300);
+        assert_eq!(state.targets_sent.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn test_send_indexes_shares_steps_between_threads() {
+        let mut ranges = ScanRanges::new();
+        ranges.extend(vec![ScanRange::single_port(
+            [10, 0, 0, 0],
+            [10, 0, 3, 255],
+            25565,
+        )]);
+        let mut session = ScanSession::new(ranges);
+        session.retries = 1;
+        session.retry_spacing = Duration::from_millis(10);
+
+        let state = SendState {
+            throttler: Mutex::new(Throttler::new(1_000_000)),
+            next_step: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            targets_sent: AtomicU64::new(0),
+        };
+        let sent = Mutex::new(HashMap::<SocketAddr, u64>::new());
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    session.send_indexes(
+                        0..1024,
+                        1_000_000,
+                        Instant::now() + Duration::from_secs(10),
+                        &state,
+                        |targets| {
+                            let mut sent = sent.lock();
+                            for target in targets {
+                                *sent.entry(target).or_default() += 1;
+                            }
+                        },
+                    )
+                });
+            }
+        });
+
+        let sent = sent.into_inner();
+        assert_eq!(sent.len(), 1024);
+        assert!(sent.values().all(|&n| n == 2));
+        assert_eq!(state.targets_sent.load(Ordering::Relaxed), 1024);
+    }
+
+    #[test]
+    fn test_multi_exchange_session() {
+        let address = SocketAddr::from(([1, 2, 3, 4], 25565));
+        let minecraft =
+            protocols::Minecraft::new("localhost", 25565, 47, HostnameStrategy::default());
+        let local_seq = expected_first_ack(1000, &minecraft.payload(address));
+
+        // the status is split over two segments
+        let status = br#"{"version":{"name":"1.8.9","protocol":47},"description":"hi"}"#;
+        let mut packet = vec![status.len() as u8 + 2, 0x00, status.len() as u8];
+        packet.extend_from_slice(status);
+        let (first, second) = packet.split_at(10);
+
+        let mut conn = ConnState::new(5000, local_seq, first, Session::default());
+        assert!(matches!(
+            minecraft.handle_response(&mut conn.session, Response::Data(conn.data.clone())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+        // a re-transmission of the first segment
+        assert_eq!(
+            conn.receive(5000, local_seq, first),
+            Err(InvalidSegment::WrongSeq)
+        );
+        conn.receive(5010, local_seq, second).unwrap();
+
+        let Ok(Exchange::Reply(ping)) =
+            minecraft.handle_response(&mut conn.session, Response::Data(conn.data.clone()))
+        else {
+            panic!("the status should be answered with a ping");
+        };
+        conn.reply(ping.len());
+        assert!(conn.data.is_empty());
+        assert_eq!(conn.session.exchanges, 1);
+        assert_eq!(conn.local_seq, local_seq + ping.len() as u32);
+
+        // data that doesn't ack the ping isn't the pong
+        let remote_seq = conn.remote_seq;
+        // the pong has the same payload as the ping
+        let pong = ping.clone();
+        assert_eq!(
+            conn.receive(remote_seq, local_seq, &pong),
+            Err(InvalidSegment::WrongAck)
+        );
+        assert!(conn.data.is_empty());
+
+        conn.receive(remote_seq, conn.local_seq, &pong).unwrap();
+        let Ok(Exchange::Done(data)) =
+            minecraft.handle_response(&mut conn.session, Response::Data(conn.data.clone()))
+        else {
+            panic!("the pong should finish the session");
+        };
+        assert_eq!(data, status);
+        assert!(conn.session.info.protocol_rtt.is_some());
+
+        // without the ping the status finishes the session
+        let minecraft = minecraft.with_ping(false);
+        let Ok(Exchange::Done(data)) =
+            minecraft.handle_response(&mut Session::default(), Response::Data(packet))
+        else {
+            panic!("the status should finish the session");
+        };
+        assert_eq!(data, status);
+    }
+}
@@ -1,10 +1,34 @@
+mod bedrock;
+mod beta_minecraft;
+mod generic_probe;
+mod http;
+mod legacy_minecraft;
 mod minecraft;
 mod minecraft_fingerprinting;
+mod minecraft_forge;
+mod minecraft_login;
+mod minecraft_protocol_range;
+mod minecraft_proxy_protocol;
+mod query;
+mod rcon;
 
-use std::net::SocketAddrV4;
+use std::{any::Any, net::SocketAddr, time::Duration};
 
-pub use minecraft::Minecraft;
-pub use minecraft_fingerprinting::MinecraftFingerprinting;
+use crate::net::p0f::TcpSignature;
+
+pub use bedrock::{Bedrock, Geyser};
+pub use beta_minecraft::BetaMinecraft;
+pub use generic_probe::GenericProbe;
+pub use http::{header, Http};
+pub use legacy_minecraft::LegacyMinecraft;
+pub use minecraft::{HandshakeIntent, Minecraft, TargetInfo};
+pub use minecraft_fingerprinting::{MinecraftFingerprinting, MinecraftProxyFingerprinting};
+pub use minecraft_forge::{ForgeModList, ForgeTarget, MinecraftForge};
+pub use minecraft_login::{MinecraftFloodgate, MinecraftLogin, MinecraftTransfer};
+pub use minecraft_protocol_range::{parse_probe, MinecraftProtocolRange, ProbeMethod};
+pub use minecraft_proxy_protocol::{MinecraftProxyProtocol, ProxyHeaderVersion};
+pub use query::{parse_full_stat, Query};
+pub use rcon::{parse_auth_response, Rcon, RconAuth};
 
 #[derive(Debug)]
 pub enum ParseResponseError {
@@ -17,7 +41,102 @@ pub enum Response {
     Rst,
 }
 
+/// What to do after a complete response was received from the server.
+pub enum Exchange {
+    /// Queue the data for processing and close the connection.
+    Done(Vec<u8>),
+    /// Send another payload to the server and wait for the next response.
+    Reply(Vec<u8>),
+}
+
+/// The state that's kept for a connection while a protocol is exchanging
+/// packets with the server. This is created when the first data is received,
+/// so it doesn't break the statelessness of the SYN scan.
+#[derive(Default)]
+pub struct Session {
+    /// The target that the session is with, so protocols can look up what
+    /// they know about it.
+    pub address: Option<SocketAddr>,
+    /// The number of replies we've sent, not counting the initial payload.
+    pub exchanges: usize,
+    /// Data that the protocol collected from previous responses. If the
+    /// connection closes before the protocol is done then this is queued for
+    /// processing (if it's not empty).
+    pub data: Vec<u8>,
+    /// Anything else the protocol has to remember between exchanges, like
+    /// whether the connection is compressed. Protocols get it with
+    /// [`Self::state`]. This is never processed.
+    pub state: Option<Box<dyn Any + Send>>,
+    /// Timing information that's queued along with the data.
+    pub info: ConnectionInfo,
+}
+
+impl Session {
+    /// The protocol's own state for the session, which starts out as the
+    /// default.
+    pub fn state<T: Default + Send + 'static>(&mut self) -> &mut T {
+        if !self.state.as_ref().is_some_and(|state| state.is::<T>()) {
+            self.state = Some(Box::<T>::default());
+        }
+        self.state
+            .as_mut()
+            .and_then(|state| state.downcast_mut())
+            .unwrap()
+    }
+}
+
+/// Information about the connection that isn't part of the response itself.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct ConnectionInfo {
+    /// The time between sending the SYN and receiving the SYN+ACK, from the
+    /// TCP timestamps. If the server doesn't support them it's the time
+    /// between the SYN+ACK and the first data instead, which also includes
+    /// however long the server took to answer.
+    pub tcp_rtt: Option<Duration>,
+    /// The round-trip time measured by the protocol itself, like the server
+    /// list ping's ping request.
+    pub protocol_rtt: Option<Duration>,
+    /// What the SYN+ACK looked like, for guessing the server's OS.
+    pub tcp_signature: Option<TcpSignature>,
+}
+
 pub trait Protocol: Send + Sync {
-    fn payload(&self, address: SocketAddrV4) -> Vec<u8>;
+    /// The first payload that's sent to the target after the handshake. This
+    /// can be different for every target, but it must always be the same for
+    /// the same target since its length is used to verify the response.
+    fn payload(&self, address: SocketAddr) -> Vec<u8>;
+    /// Whether we wait for the server to send something first instead of
+    /// sending a payload, like for SSH or FTP banners. Otherwise an empty
+    /// payload means the target is skipped.
+    fn server_talks_first(&self) -> bool {
+        false
+    }
     fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError>;
+
+    /// Handle a response that was received for the session. The response only
+    /// includes the data received since our last reply.
+    ///
+    /// Most protocols only need one exchange, so by default this just calls
+    /// [`Self::parse_response`].
+    fn handle_response(
+        &self,
+        _session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        self.parse_response(response).map(Exchange::Done)
+    }
+}
+
+/// A protocol that's sent over UDP. Since there's no handshake, the cookie has
+/// to be put somewhere in the payload that the server will echo back.
+pub trait UdpProtocol: Send + Sync {
+    fn payload(&self, address: SocketAddr, cookie: u32) -> Vec<u8>;
+    /// Some protocols need more than one round trip. If this returns a payload
+    /// then it's sent back to the server and the datagram isn't parsed.
+    fn reply(&self, _cookie: u32, _response: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+    /// Parse a datagram from the server. This should return
+    /// [`ParseResponseError::Invalid`] if it doesn't contain the cookie.
+    fn parse_response(&self, cookie: u32, response: &[u8]) -> Result<Vec<u8>, ParseResponseError>;
 }
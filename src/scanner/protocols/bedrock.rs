@@ -0,0 +1,117 @@
+use std::net::SocketAddr;
+
+use super::{ParseResponseError, UdpProtocol};
+
+/// The magic bytes that are in every RakNet offline message.
+const RAKNET_MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Bedrock Edition's server list ping, which is a RakNet unconnected ping.
+///
+/// The ping has a timestamp that the server copies into the pong, so we put our
+/// cookie there.
+#[derive(Clone)]
+pub struct Bedrock {
+    client_guid: u64,
+}
+
+impl Bedrock {
+    pub fn new() -> Self {
+        Self {
+            client_guid: rand::random(),
+        }
+    }
+}
+
+impl Default for Bedrock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UdpProtocol for Bedrock {
+    fn payload(&self, _address: SocketAddr, cookie: u32) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(33);
+        buffer.push(UNCONNECTED_PING);
+        buffer.extend_from_slice(&(cookie as u64).to_be_bytes()); // "time"
+        buffer.extend_from_slice(&RAKNET_MAGIC);
+        buffer.extend_from_slice(&self.client_guid.to_be_bytes());
+        buffer
+    }
+
+    fn parse_response(&self, cookie: u32, response: &[u8]) -> Result<Vec<u8>, ParseResponseError> {
+        // id + time + server guid + magic + string length
+        if response.len() < 35 || response[0] != UNCONNECTED_PONG {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        let time = u64::from_be_bytes(response[1..9].try_into().unwrap());
+        if time != cookie as u64 {
+            return Err(ParseResponseError::Invalid);
+        }
+        if response[17..33] != RAKNET_MAGIC {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        let length = u16::from_be_bytes([response[33], response[34]]) as usize;
+        let Some(server_id) = response.get(35..35 + length) else {
+            return Err(ParseResponseError::Invalid);
+        };
+
+        // MCPE;motd;protocol;version;online;max;guid;...
+        if !server_id.starts_with(b"MCPE;") && !server_id.starts_with(b"MCEE;") {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        Ok(server_id.to_vec())
+    }
+}
+
+/// The same ping as [`Bedrock`], but sent to the IPs of Java servers to find
+/// the ones that also have Bedrock with Geyser. It's its own protocol so the
+/// pongs are processed differently.
+#[derive(Clone, Default)]
+pub struct Geyser(Bedrock);
+
+impl UdpProtocol for Geyser {
+    fn payload(&self, address: SocketAddr, cookie: u32) -> Vec<u8> {
+        self.0.payload(address, cookie)
+    }
+
+    fn parse_response(&self, cookie: u32, response: &[u8]) -> Result<Vec<u8>, ParseResponseError> {
+        self.0.parse_response(cookie, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_pong() {
+        let server_id =
+            b"MCPE;Dedicated Server;712;1.21.20;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+        let mut response = vec![UNCONNECTED_PONG];
+        response.extend_from_slice(&1234u64.to_be_bytes());
+        response.extend_from_slice(&5678u64.to_be_bytes());
+        response.extend_from_slice(&RAKNET_MAGIC);
+        response.extend_from_slice(&(server_id.len() as u16).to_be_bytes());
+        response.extend_from_slice(server_id);
+
+        let protocol = Bedrock::new();
+        assert_eq!(protocol.parse_response(1234, &response).unwrap(), server_id);
+        // wrong cookie
+        assert!(protocol.parse_response(4321, &response).is_err());
+
+        let payload = protocol.payload(SocketAddr::from((Ipv4Addr::LOCALHOST, 19132)), 1234);
+        assert_eq!(payload.len(), 33);
+        assert_eq!(payload[1..9], 1234u64.to_be_bytes());
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+
+use super::{legacy_minecraft::read_kick_string, ParseResponseError, Protocol, Response};
+
+/// The server list ping used by clients from beta 1.8 to 1.3. It's just a
+/// single 0xFE byte, and the server replies with `motd§online§max`.
+#[derive(Clone)]
+pub struct BetaMinecraft;
+
+impl Protocol for BetaMinecraft {
+    fn payload(&self, _address: SocketAddr) -> Vec<u8> {
+        vec![0xfe]
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let response = match response {
+            Response::Data(r) => r,
+            Response::Rst => return Err(ParseResponseError::Invalid),
+        };
+
+        let kick_string = read_kick_string(&response)?;
+
+        // the motd can contain § too, so only the last two parts are checked
+        let mut parts = kick_string.rsplitn(3, '\u{a7}');
+        let (Some(max), Some(online), Some(_motd)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseResponseError::Invalid);
+        };
+        if max.parse::<i32>().is_err() || online.parse::<i32>().is_err() {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        Ok(kick_string.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kick_packet(reason: &str) -> Vec<u8> {
+        let code_units = reason.encode_utf16().collect::<Vec<_>>();
+        let mut packet = vec![0xff];
+        packet.extend_from_slice(&(code_units.len() as u16).to_be_bytes());
+        for unit in code_units {
+            packet.extend_from_slice(&unit.to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let parse =
+            |reason: &str| BetaMinecraft.parse_response(Response::Data(kick_packet(reason)));
+
+        let reason = "A \u{a7}aBeta\u{a7}r Server\u{a7}3\u{a7}20";
+        assert_eq!(parse(reason).unwrap(), reason.as_bytes());
+        // a normal kick, not a ping response
+        assert!(matches!(
+            parse("Outdated server!"),
+            Err(ParseResponseError::Invalid)
+        ));
+        assert!(matches!(
+            parse("motd\u{a7}three\u{a7}20"),
+            Err(ParseResponseError::Invalid)
+        ));
+
+        let mut packet = kick_packet("A Server\u{a7}3\u{a7}20");
+        packet.truncate(10);
+        assert!(matches!(
+            BetaMinecraft.parse_response(Response::Data(packet)),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+use std::net::SocketAddr;
+
+use anyhow::{bail, Context};
+use regex::bytes::Regex;
+
+use super::{Exchange, ParseResponseError, Protocol, Response, Session};
+use crate::config::ProbeConfig;
+
+const DEFAULT_MAX_BYTES: usize = 4096;
+/// The services in `services.<name>` that have their own probes, so a probe
+/// from the config with the same name would overwrite their results.
+const RESERVED_NAMES: &[&str] = &["rcon", "webmap"];
+
+/// A probe that's defined in the config instead of in code. It sends a fixed
+/// payload (or nothing, for services that talk first) and reads the response
+/// until the completion regex matches, the connection closes, or we have
+/// `max_bytes`.
+#[derive(Clone, Debug)]
+pub struct GenericProbe {
+    pub name: String,
+    pub ports: Vec<u16>,
+    payload: Vec<u8>,
+    max_bytes: usize,
+    complete: Option<Regex>,
+    /// The document fields and the regexes whose first capture group is put
+    /// in them.
+    fields: Vec<(String, Regex)>,
+}
+
+impl GenericProbe {
+    pub fn new(config: &ProbeConfig) -> anyhow::Result<Self> {
+        if !is_valid_key(&config.name) {
+            bail!("Invalid probe name {:?}", config.name);
+        }
+        if RESERVED_NAMES.contains(&config.name.as_str()) {
+            bail!(
+                "Probe name {:?} is already used by a built-in probe",
+                config.name
+            );
+        }
+        let payload = match (&config.payload, &config.payload_hex) {
+            (Some(_), Some(_)) => {
+                bail!(
+                    "Probe {:?} can't have both payload and payload_hex",
+                    config.name
+                )
+            }
+            (Some(payload), None) => unescape(payload)
+                .with_context(|| format!("Invalid payload for probe {:?}", config.name))?,
+            (None, Some(payload_hex)) => decode_hex(payload_hex)
+                .with_context(|| format!("Invalid payload_hex for probe {:?}", config.name))?,
+            (None, None) => vec![],
+        };
+        let complete = config
+            .complete
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| format!("Invalid completion regex for probe {:?}", config.name))?;
+
+        let mut fields = Vec::new();
+        for (field, regex) in &config.fields {
+            if !is_valid_key(field) {
+                bail!("Invalid field name {field:?} in probe {:?}", config.name);
+            }
+            let regex = Regex::new(regex).with_context(|| {
+                format!("Invalid regex for {field:?} in probe {:?}", config.name)
+            })?;
+            fields.push((field.clone(), regex));
+        }
+        // so the documents always have the same order
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(Self {
+            name: config.name.clone(),
+            ports: config.ports.clone(),
+            payload,
+            max_bytes: config.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            complete,
+            fields,
+        })
+    }
+
+    /// Run the field regexes on the response and return the ones that
+    /// matched.
+    pub fn extract(&self, data: &[u8]) -> Vec<(String, String)> {
+        self.fields
+            .iter()
+            .filter_map(|(field, regex)| {
+                let captures = regex.captures(data)?;
+                // the whole match if there's no group
+                let value = captures.get(1).or(captures.get(0))?;
+                Some((
+                    field.clone(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl Protocol for GenericProbe {
+    fn payload(&self, _address: SocketAddr) -> Vec<u8> {
+        self.payload.clone()
+    }
+
+    fn server_talks_first(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let response = match response {
+            Response::Data(r) => r,
+            Response::Rst => return Err(ParseResponseError::Invalid),
+        };
+
+        if response.len() >= self.max_bytes {
+            return Ok(response[..self.max_bytes].to_vec());
+        }
+        if self
+            .complete
+            .as_ref()
+            .is_some_and(|complete| complete.is_match(&response))
+        {
+            return Ok(response);
+        }
+        Err(ParseResponseError::Incomplete {
+            expected_length: self.max_bytes as u32,
+        })
+    }
+
+    fn handle_response(
+        &self,
+        session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        if let Response::Data(data) = &response {
+            // processed if the connection closes before it's complete
+            session.data = data.clone();
+        }
+        self.parse_response(response).map(Exchange::Done)
+    }
+}
+
+/// Whether the string can be used as a key in a document without being
+/// nested or treated as an operator.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains('.') && !key.starts_with('$')
+}
+
+/// Turn the escapes in a payload like `\r\n` and `\x00` into bytes.
+fn unescape(payload: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(payload.len());
+    let mut chars = payload.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                bytes.extend(decode_hex(&hex)?);
+            }
+            c => bail!(
+                "Unknown escape \\{}",
+                c.map(String::from).unwrap_or_default()
+            ),
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    let hex = hex.split_whitespace().collect::<String>();
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        bail!("Invalid hex {hex:?}");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .with_context(|| format!("Invalid hex {:?}", &hex[i..i + 2]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_generic_probe() {
+        let config = ProbeConfig {
+            name: "redis".to_string(),
+            payload: Some(r"PING\r\n".to_string()),
+            payload_hex: None,
+            ports: vec![6379],
+            max_bytes: None,
+            complete: Some(r"\r\n$".to_string()),
+            fields: HashMap::from([("reply".to_string(), r"^\+(\w+)".to_string())]),
+        };
+        let probe = GenericProbe::new(&config).unwrap();
+        assert_eq!(probe.payload, b"PING\r\n");
+
+        assert!(matches!(
+            probe.parse_response(Response::Data(b"+PO".to_vec())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+        let data = probe
+            .parse_response(Response::Data(b"+PONG\r\n".to_vec()))
+            .unwrap();
+        assert_eq!(
+            probe.extract(&data),
+            vec![("reply".to_string(), "PONG".to_string())]
+        );
+
+        assert_eq!(unescape(r"\x16\x03a").unwrap(), b"\x16\x03a");
+        assert_eq!(decode_hex("fe fd 09").unwrap(), b"\xfe\xfd\x09");
+        assert!(unescape(r"\q").is_err());
+
+        // these would overwrite the results of other probes
+        for name in ["rcon", "webmap", "a.b", "$set"] {
+            assert!(GenericProbe::new(&ProbeConfig {
+                name: name.to_string(),
+                ..config.clone()
+            })
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::net::SocketAddr;
+
+use super::{Exchange, ParseResponseError, Protocol, Response, Session};
+
+/// We only need the headers and the start of the page, so anything after this
+/// is cut off.
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// An HTTP/1.1 `GET /`, used for finding web maps like Dynmap on the IPs of
+/// servers.
+pub struct Http;
+
+impl Protocol for Http {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        format!(
+            "GET / HTTP/1.1\r\nHost: {address}\r\nUser-Agent: matscan\r\nAccept: text/html\r\nConnection: close\r\n\r\n"
+        )
+        .into_bytes()
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let response = match response {
+            Response::Data(r) => r,
+            Response::Rst => return Err(ParseResponseError::Invalid),
+        };
+
+        if !response.starts_with(b"HTTP/1.") {
+            // might just be the start of the status line
+            if b"HTTP/1.".starts_with(&response) {
+                return Err(ParseResponseError::Incomplete { expected_length: 7 });
+            }
+            return Err(ParseResponseError::Invalid);
+        }
+        let Some(headers_end) = find(&response, b"\r\n\r\n").map(|i| i + 4) else {
+            if response.len() >= MAX_RESPONSE_SIZE {
+                return Err(ParseResponseError::Invalid);
+            }
+            return Err(ParseResponseError::Incomplete {
+                expected_length: MAX_RESPONSE_SIZE as u32,
+            });
+        };
+
+        let headers = String::from_utf8_lossy(&response[..headers_end]);
+        let content_length =
+            header(&headers, "content-length").and_then(|v| v.parse::<usize>().ok());
+        let is_chunked = header(&headers, "transfer-encoding")
+            .is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+
+        let expected_length = match content_length {
+            Some(content_length) => headers_end + content_length,
+            None => MAX_RESPONSE_SIZE,
+        }
+        .min(MAX_RESPONSE_SIZE);
+        let is_complete = response.len() >= expected_length
+            || (is_chunked && response.ends_with(b"\r\n0\r\n\r\n"));
+        if !is_complete {
+            return Err(ParseResponseError::Incomplete {
+                expected_length: expected_length as u32,
+            });
+        }
+
+        Ok(response[..response.len().min(MAX_RESPONSE_SIZE)].to_vec())
+    }
+
+    fn handle_response(
+        &self,
+        session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        let data = match &response {
+            Response::Data(r) => r.clone(),
+            Response::Rst => vec![],
+        };
+        match self.parse_response(response) {
+            Ok(data) => Ok(Exchange::Done(data)),
+            Err(ParseResponseError::Incomplete { expected_length }) => {
+                // most servers don't say how long the page is and just close
+                // the connection, so whatever we have is processed then
+                if find(&data, b"\r\n\r\n").is_some() {
+                    session.data = data;
+                }
+                Err(ParseResponseError::Incomplete { expected_length })
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Get the value of a header from the response head.
+pub fn header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_response() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 38\r\n\r\n<title>Minecraft Dynamic Map</title>\r\n";
+        assert_eq!(
+            Http.parse_response(Response::Data(response.to_vec()))
+                .unwrap(),
+            response
+        );
+        assert!(matches!(
+            Http.parse_response(Response::Data(response[..70].to_vec())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+        assert!(matches!(
+            Http.parse_response(Response::Data(b"\x00\x01".to_vec())),
+            Err(ParseResponseError::Invalid)
+        ));
+
+        // without a length we wait until the connection closes
+        let mut session = Session::default();
+        let response = b"HTTP/1.1 200 OK\r\nServer: BlueMap\r\n\r\n<html>";
+        assert!(Http
+            .handle_response(&mut session, Response::Data(response.to_vec()))
+            .is_err());
+        assert_eq!(session.data, response);
+
+        assert_eq!(
+            header(&String::from_utf8_lossy(response), "server"),
+            Some("BlueMap")
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::net::SocketAddr;
+
+use super::{ParseResponseError, Protocol, Response};
+
+/// The protocol version we claim to be in the ping. 74 is 1.6.4, servers from
+/// 1.4 and 1.5 ignore it and reply anyways.
+const LEGACY_PROTOCOL_VERSION: u8 = 74;
+
+/// The server list ping used by 1.4-1.6 clients. Servers that are too old to
+/// understand the modern handshake (or some badly configured proxies) will
+/// only reply to this.
+#[derive(Clone)]
+pub struct LegacyMinecraft {
+    legacy_request: Vec<u8>,
+}
+
+impl LegacyMinecraft {
+    pub fn new(hostname: &str, port: u16) -> Self {
+        let legacy_request = build_legacy_request(hostname, port);
+        Self { legacy_request }
+    }
+}
+
+impl Protocol for LegacyMinecraft {
+    fn payload(&self, _address: SocketAddr) -> Vec<u8> {
+        self.legacy_request.clone()
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let response = match response {
+            Response::Data(r) => r,
+            Response::Rst => return Err(ParseResponseError::Invalid),
+        };
+
+        let kick_string = read_kick_string(&response)?;
+
+        // 1.4+ servers start their response with §1 followed by a null
+        if !kick_string.starts_with("\u{a7}1\0") {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        Ok(kick_string.into_bytes())
+    }
+}
+
+/// Read the string from a legacy kick packet (0xFF), which is what servers
+/// reply with when they get a legacy ping.
+///
+/// This is also used for the beta ping since the reply is the same packet.
+pub fn read_kick_string(response: &[u8]) -> Result<String, ParseResponseError> {
+    if response.len() < 3 || response[0] != 0xff {
+        return Err(ParseResponseError::Invalid);
+    }
+
+    // the length is the number of utf-16 code units, not bytes
+    let length = u16::from_be_bytes([response[1], response[2]]) as usize;
+    let string_bytes = &response[3..];
+    if string_bytes.len() < length * 2 {
+        return Err(ParseResponseError::Incomplete {
+            expected_length: (length * 2) as u32,
+        });
+    }
+
+    let code_units = string_bytes[..length * 2]
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+
+    String::from_utf16(&code_units).map_err(|_| ParseResponseError::Invalid)
+}
+
+/// https://wiki.vg/Server_List_Ping#1.6
+pub fn build_legacy_request(hostname: &str, port: u16) -> Vec<u8> {
+    let hostname_utf16 = hostname.encode_utf16().collect::<Vec<_>>();
+
+    let mut buffer = vec![
+        0xfe, // packet id: server list ping
+        0x01, // payload, always 1
+        0xfa, // packet id: plugin message
+    ];
+
+    write_utf16_string(&mut buffer, "MC|PingHost");
+
+    // length of the rest of the data
+    let rest_length = 7 + hostname_utf16.len() * 2;
+    buffer.extend_from_slice(&(rest_length as u16).to_be_bytes());
+
+    buffer.push(LEGACY_PROTOCOL_VERSION);
+    write_utf16_string(&mut buffer, hostname);
+    buffer.extend_from_slice(&(port as u32).to_be_bytes());
+
+    buffer
+}
+
+/// Write a string prefixed by its length in code units, the way legacy
+/// clients do it.
+fn write_utf16_string(buffer: &mut Vec<u8>, string: &str) {
+    let code_units = string.encode_utf16().collect::<Vec<_>>();
+    buffer.extend_from_slice(&(code_units.len() as u16).to_be_bytes());
+    for code_unit in code_units {
+        buffer.extend_from_slice(&code_unit.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_response() {
+        let kick_string = "\u{a7}1\u{0}74\u{0}1.6.4\u{0}A Minecraft Server\u{0}3\u{0}20";
+        let code_units = kick_string.encode_utf16().collect::<Vec<_>>();
+
+        let mut response = vec![0xff];
+        response.extend_from_slice(&(code_units.len() as u16).to_be_bytes());
+        for code_unit in code_units {
+            response.extend_from_slice(&code_unit.to_be_bytes());
+        }
+
+        let protocol = LegacyMinecraft::new("matscan", 25565);
+        assert_eq!(
+            protocol
+                .parse_response(Response::Data(response.clone()))
+                .unwrap(),
+            kick_string.as_bytes()
+        );
+        assert!(matches!(
+            protocol.parse_response(Response::Data(response[..10].to_vec())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+    }
+}
@@ -1,59 +1,207 @@
 use std::{
+    collections::HashMap,
     io::{Cursor, Read, Write},
-    net::SocketAddrV4,
+    mem,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use super::{ParseResponseError, Protocol, Response};
+use super::{Exchange, ParseResponseError, Protocol, Response, Session};
+use crate::config::HostnameStrategy;
 
 #[derive(Clone)]
 pub struct Minecraft {
-    minecraft_request: Vec<u8>,
+    hostname: String,
+    port: u16,
+    protocol_version: i32,
+    hostname_strategy: HostnameStrategy,
+    /// Whether we send a ping request after the status to measure the
+    /// latency.
+    ping: bool,
+    /// What we know about the targets from previous scans.
+    targets: Arc<HashMap<SocketAddr, TargetInfo>>,
+}
+
+/// What we know about a server from the database, used to make the handshake
+/// look like it's from a real client.
+#[derive(Clone, Debug, Default)]
+pub struct TargetInfo {
+    /// The protocol version that the server said it's on last time.
+    pub protocol_version: Option<i32>,
+    /// A hostname that's known to point to the server.
+    pub hostname: Option<String>,
 }
 
 impl Minecraft {
-    pub fn new(hostname: &str, port: u16, protocol_version: i32) -> Self {
-        let minecraft_request = build_latest_request(hostname, port, protocol_version);
-        Self { minecraft_request }
+    pub fn new(
+        hostname: &str,
+        port: u16,
+        protocol_version: i32,
+        hostname_strategy: HostnameStrategy,
+    ) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            port,
+            protocol_version,
+            hostname_strategy,
+            ping: true,
+            targets: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Whether to send a ping request after the status. Without it the
+    /// connection is closed as soon as we have the status, but there's no
+    /// server list ping latency.
+    pub fn with_ping(self, ping: bool) -> Self {
+        Self { ping, ..self }
+    }
+
+    /// Use the given protocol versions and hostnames for the handshake when
+    /// pinging these targets.
+    pub fn with_targets(&self, targets: HashMap<SocketAddr, TargetInfo>) -> Self {
+        Self {
+            targets: Arc::new(targets),
+            ..self.clone()
+        }
     }
 }
 
 impl Protocol for Minecraft {
-    fn payload(&self, _address: SocketAddrV4) -> Vec<u8> {
-        self.minecraft_request.clone()
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let target = self.targets.get(&address);
+
+        let protocol_version = target
+            .and_then(|t| t.protocol_version)
+            // some servers send nonsense versions
+            .filter(|&v| v > 0)
+            .unwrap_or(self.protocol_version);
+
+        let target_ip = address.ip().to_string();
+        let (hostname, port) = match self.hostname_strategy {
+            HostnameStrategy::Fixed => (self.hostname.as_str(), self.port),
+            HostnameStrategy::TargetIp => (target_ip.as_str(), address.port()),
+            HostnameStrategy::VirtualHost => (
+                target
+                    .and_then(|t| t.hostname.as_deref())
+                    .unwrap_or(&target_ip),
+                address.port(),
+            ),
+        };
+
+        build_latest_request(hostname, port, protocol_version)
     }
 
     fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
-        let response = match response {
-            Response::Data(r) => r,
-            Response::Rst => return Err(ParseResponseError::Invalid),
-        };
+        parse_status(response)
+    }
 
-        // ignore the packet length
-        let mut stream = Cursor::new(response);
-        read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
-        let packet_id = read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
-        let response_length = read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
-        if packet_id != 0x00 || response_length < 0 {
-            return Err(ParseResponseError::Invalid);
+    fn handle_response(
+        &self,
+        session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        if !self.ping {
+            return self.parse_response(response).map(Exchange::Done);
         }
-        // read until end
-        let position = stream.position() as usize;
-        let status_buffer = &stream.into_inner()[position..];
-        if status_buffer.len() < response_length as usize {
-            return Err(ParseResponseError::Incomplete {
-                expected_length: response_length as u32,
-            });
+        if session.exchanges == 0 {
+            // keep the status and send a ping request so we can measure the latency
+            session.data = self.parse_response(response)?;
+            return Ok(Exchange::Reply(build_ping_request(unix_millis())));
         }
 
-        let status_string = String::from_utf8_lossy(status_buffer).to_string();
-
-        // make sure it starts with {
-        if !status_string.starts_with('{') {
+        let Response::Data(response) = response else {
             return Err(ParseResponseError::Invalid);
+        };
+        match parse_pong(&response) {
+            Ok(sent_at) => {
+                session.info.protocol_rtt =
+                    Some(Duration::from_millis(unix_millis().saturating_sub(sent_at)));
+            }
+            Err(ParseResponseError::Incomplete { expected_length }) => {
+                return Err(ParseResponseError::Incomplete { expected_length });
+            }
+            // some servers close the connection or send garbage instead of a
+            // pong, the status is still good though
+            Err(ParseResponseError::Invalid) => {}
         }
+        Ok(Exchange::Done(mem::take(&mut session.data)))
+    }
+}
 
-        Ok(status_string.into_bytes())
+/// Parse a status response and return the JSON.
+pub(super) fn parse_status(response: Response) -> Result<Vec<u8>, ParseResponseError> {
+    let response = match response {
+        Response::Data(r) => r,
+        Response::Rst => return Err(ParseResponseError::Invalid),
+    };
+
+    // ignore the packet length
+    let mut stream = Cursor::new(response);
+    read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
+    let packet_id = read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
+    let response_length = read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
+    if packet_id != 0x00 || response_length < 0 {
+        return Err(ParseResponseError::Invalid);
+    }
+    // read until end
+    let position = stream.position() as usize;
+    let status_buffer = &stream.into_inner()[position..];
+    if status_buffer.len() < response_length as usize {
+        return Err(ParseResponseError::Incomplete {
+            expected_length: response_length as u32,
+        });
+    }
+
+    let status_string = String::from_utf8_lossy(status_buffer).to_string();
+
+    // make sure it starts with {
+    if !status_string.starts_with('{') {
+        return Err(ParseResponseError::Invalid);
     }
+
+    Ok(status_string.into_bytes())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Build a ping request, the server will reply with a pong that has the same
+/// payload.
+pub fn build_ping_request(payload: u64) -> Vec<u8> {
+    let mut buffer = vec![
+        9,    // length of packet id + data
+        0x01, // packet id: 1 for ping request
+    ];
+    buffer.extend_from_slice(&payload.to_be_bytes());
+    buffer
+}
+
+/// Parse a pong and return its payload.
+fn parse_pong(response: &[u8]) -> Result<u64, ParseResponseError> {
+    if response.len() < 10 {
+        if response.is_empty() || response[0] == 9 {
+            return Err(ParseResponseError::Incomplete { expected_length: 9 });
+        }
+        return Err(ParseResponseError::Invalid);
+    }
+    if response[0] != 9 || response[1] != 0x01 {
+        return Err(ParseResponseError::Invalid);
+    }
+    Ok(u64::from_be_bytes(response[2..10].try_into().unwrap()))
+}
+
+/// The `next state` in the handshake.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HandshakeIntent {
+    Status = 1,
+    Login = 2,
+    /// Only exists since 1.20.5.
+    Transfer = 3,
 }
 
 /// https://github.com/kiwiyou/craftping/blob/master/src/lib.rs#L73
@@ -72,8 +220,8 @@ pub fn build_latest_request(hostname: &str, port: u16, protocol_version: i32) ->
     buffer.extend_from_slice(hostname.as_bytes());
     buffer.extend_from_slice(&[
         (port >> 8) as u8,
-        (port & 0b1111_1111) as u8, // server port as unsigned short
-        0x01,                       // next state: 1 (status) as VarInt
+        (port & 0b1111_1111) as u8,    // server port as unsigned short
+        HandshakeIntent::Status as u8, // next state: 1 (status) as VarInt
     ]);
 
     // buffer for the 1st and 2nd packet
@@ -88,7 +236,7 @@ pub fn build_latest_request(hostname: &str, port: u16, protocol_version: i32) ->
     full_buffer
 }
 
-fn write_varint(writer: &mut Vec<u8>, mut value: i32) {
+pub(super) fn write_varint(writer: &mut Vec<u8>, mut value: i32) {
     let mut buffer = [0];
     if value == 0 {
         writer.write_all(&buffer).unwrap();
@@ -103,7 +251,7 @@ fn write_varint(writer: &mut Vec<u8>, mut value: i32) {
     }
 }
 
-fn read_varint(reader: &mut (dyn Read + Unpin + Send)) -> Option<i32> {
+pub(super) fn read_varint(reader: &mut (dyn Read + Unpin + Send)) -> Option<i32> {
     let mut buffer = [0];
     let mut ans = 0;
     for i in 0..5 {
@@ -1,19 +1,19 @@
-use std::{collections::HashMap, io::Write, net::SocketAddrV4};
+use std::{collections::HashMap, io::Write, net::SocketAddr};
 
-use super::{ParseResponseError, Protocol, Response};
+use super::{minecraft_login::parse_login_packet, ParseResponseError, Protocol, Response};
 
 pub struct MinecraftFingerprinting {
-    protocol_versions: HashMap<SocketAddrV4, i32>,
+    protocol_versions: HashMap<SocketAddr, i32>,
 }
 
 impl MinecraftFingerprinting {
-    pub fn new(protocol_versions: HashMap<SocketAddrV4, i32>) -> Self {
+    pub fn new(protocol_versions: HashMap<SocketAddr, i32>) -> Self {
         Self { protocol_versions }
     }
 }
 
 impl Protocol for MinecraftFingerprinting {
-    fn payload(&self, address: SocketAddrV4) -> Vec<u8> {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
         let Some(&protocol_version) = self.protocol_versions.get(&address) else {
             return vec![];
         };
@@ -28,6 +28,44 @@ impl Protocol for MinecraftFingerprinting {
     }
 }
 
+/// Connect to servers with a protocol version that nothing supports. Proxies
+/// handle this themselves (even if they pass the status through to a backend)
+/// and the disconnect message says which versions they support, which is
+/// different for every proxy.
+pub struct MinecraftProxyFingerprinting;
+
+impl Protocol for MinecraftProxyFingerprinting {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        build_outdated_handshake(&address.ip().to_string(), address.port())
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        // the disconnect packet
+        parse_login_packet(response)
+    }
+}
+
+/// Create a handshake for logging in with protocol version 1, which servers
+/// reject right away.
+pub fn build_outdated_handshake(hostname: &str, port: u16) -> Vec<u8> {
+    let mut buffer = vec![
+        // 0 for handshake packet
+        0x00,
+    ];
+    write_varint(&mut buffer, 1); // protocol version (older than 1.7.2, so always outdated)
+    write_varint(&mut buffer, hostname.len() as i32);
+    buffer.extend_from_slice(hostname.as_bytes());
+    buffer.extend_from_slice(&[
+        (port >> 8) as u8,
+        (port & 0b1111_1111) as u8, // server port as unsigned short
+        0x02,                       // next state: 2 (login)
+    ]);
+    let mut full_buffer = vec![];
+    write_varint(&mut full_buffer, buffer.len() as i32);
+    full_buffer.append(&mut buffer);
+    full_buffer
+}
+
 /// Create a request that will cause servers to respond with an error (which we
 /// can then use to identify the server software).
 pub fn build_fingerprint_request(hostname: &str, port: u16, protocol_version: i32) -> Vec<u8> {
@@ -0,0 +1,485 @@
+use std::{collections::HashMap, io::Read, net::SocketAddr};
+
+use flate2::read::ZlibDecoder;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    minecraft::read_varint,
+    minecraft_login::{build_login_request, has_configuration_phase},
+    Exchange, ParseResponseError, Protocol, Response, Session,
+};
+
+/// Decompressed packets bigger than this are ignored.
+const MAX_PACKET_SIZE: usize = 8 * 1024 * 1024;
+
+/// Log in to modded servers just far enough to get their full mod list, since
+/// the one in the status is truncated for big modpacks. This only works for
+/// servers in offline mode.
+pub struct MinecraftForge {
+    targets: HashMap<SocketAddr, ForgeTarget>,
+    username: String,
+    uuid: u128,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ForgeTarget {
+    pub protocol_version: i32,
+    /// The `forgeData.fmlNetworkVersion` from the status. This is None for
+    /// newer NeoForge servers, which negotiate in the configuration phase
+    /// instead of with the FML login handshake.
+    pub fml_network_version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ForgeModList {
+    /// Mod ids and their versions, if the server sent them.
+    pub mods: Vec<(String, Option<String>)>,
+    /// Network channels and their versions.
+    pub channels: Vec<(String, String)>,
+    /// Whether the server told us its mods. NeoForge only tells us channels,
+    /// so the mods are guessed from their namespaces.
+    pub complete: bool,
+}
+
+/// What we remember about a connection between responses. Modpacks can send
+/// megabytes of registries in many segments, so the packets are only read
+/// once instead of every time more of the response arrives.
+#[derive(Default)]
+struct ForgeSession {
+    compression_threshold: Option<i32>,
+    /// How much of the response since our last reply we already read.
+    parsed: usize,
+    mod_list: ForgeModList,
+    got_mod_list: bool,
+    got_mod_data: bool,
+}
+
+impl MinecraftForge {
+    pub fn new(targets: HashMap<SocketAddr, ForgeTarget>, username: &str) -> Self {
+        Self {
+            targets,
+            username: username.to_string(),
+            uuid: rand::random(),
+        }
+    }
+}
+
+impl Protocol for MinecraftForge {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let Some(target) = self.targets.get(&address) else {
+            return vec![];
+        };
+        // forge clients mark themselves in the hostname, otherwise the server
+        // treats us like a vanilla client
+        let mut hostname = address.ip().to_string();
+        if let Some(fml_network_version) = target.fml_network_version {
+            hostname.push_str(&format!("\0FML{fml_network_version}\0"));
+        }
+        build_login_request(
+            &hostname,
+            address.port(),
+            target.protocol_version,
+            &self.username,
+            self.uuid,
+        )
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        // everything is done in handle_response, this is only used if there's
+        // a single exchange
+        match self.handle_response(&mut Session::default(), response)? {
+            Exchange::Done(data) => Ok(data),
+            Exchange::Reply(_) => Err(ParseResponseError::Invalid),
+        }
+    }
+
+    fn handle_response(
+        &self,
+        session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        let Response::Data(response) = response else {
+            return Err(ParseResponseError::Invalid);
+        };
+
+        let target = session.address.and_then(|a| self.targets.get(&a)).copied();
+        let in_configuration = session.exchanges > 0;
+        let state = session.state::<ForgeSession>();
+        let result = state.read_packets(&response, target, in_configuration);
+        if result.is_err() && state.got_mod_list {
+            // in case the connection closes before we get the rest
+            session.data = serde_json::to_vec(&state.mod_list).unwrap_or_default();
+        }
+        result
+    }
+}
+
+impl ForgeSession {
+    /// Read the packets in the response that we didn't read yet.
+    fn read_packets(
+        &mut self,
+        response: &[u8],
+        target: Option<ForgeTarget>,
+        in_configuration: bool,
+    ) -> Result<Exchange, ParseResponseError> {
+        let mut rest = response
+            .get(self.parsed..)
+            .ok_or(ParseResponseError::Invalid)?;
+        loop {
+            let (packet, packet_length) = read_packet(rest, self.compression_threshold)?;
+            rest = &rest[packet_length..];
+            self.parsed += packet_length;
+
+            let mut packet = packet.as_slice();
+            let packet_id = read_varint(&mut packet).ok_or(ParseResponseError::Invalid)?;
+
+            if in_configuration {
+                // configuration phase, the plugin message id changed in 1.20.5
+                if (packet_id == 0x00 || packet_id == 0x01)
+                    && read_string(&mut packet).as_deref() == Some("neoforge:network")
+                {
+                    read_neoforge_network(&mut packet, &mut self.mod_list)
+                        .ok_or(ParseResponseError::Invalid)?;
+                    return Ok(self.done());
+                }
+                continue;
+            }
+
+            match packet_id {
+                // disconnect or encryption request (online mode), we can't get
+                // any further
+                0x00 | 0x01 => return Ok(Exchange::Done(vec![])),
+                // login success, neoforge servers (and vanilla ones) let us into
+                // the configuration phase if we acknowledge it
+                0x02 => {
+                    if !target.is_some_and(|t| has_configuration_phase(t.protocol_version)) {
+                        // older servers put us straight into play, so there's
+                        // nothing more to get
+                        return Ok(self.done());
+                    }
+                    // the next response starts after our reply
+                    self.parsed = 0;
+                    return Ok(Exchange::Reply(write_packet(
+                        &[0x03],
+                        self.compression_threshold,
+                    )));
+                }
+                // set compression
+                0x03 => {
+                    self.compression_threshold =
+                        Some(read_varint(&mut packet).ok_or(ParseResponseError::Invalid)?);
+                }
+                // login plugin request
+                0x04 => {
+                    let _message_id = read_varint(&mut packet);
+                    if read_string(&mut packet).as_deref() != Some("fml:loginwrapper") {
+                        continue;
+                    }
+                    let _inner_channel = read_string(&mut packet);
+                    let _inner_length = read_varint(&mut packet);
+                    let fml_packet_id = read_varint(&mut packet);
+                    match fml_packet_id {
+                        // mod list
+                        Some(1) => {
+                            read_fml_mod_list(&mut packet, &mut self.mod_list)
+                                .ok_or(ParseResponseError::Invalid)?;
+                            self.mod_list.complete = true;
+                            self.got_mod_list = true;
+                        }
+                        // mod data, newer forge versions send the versions
+                        // separately
+                        Some(5) => {
+                            read_fml_mod_data(&mut packet, &mut self.mod_list)
+                                .ok_or(ParseResponseError::Invalid)?;
+                            self.got_mod_data = true;
+                        }
+                        _ => {}
+                    }
+                    // the registries and configs come after the mod list and
+                    // data, so if we got one of those then there's no mod data
+                    if self.got_mod_list
+                        && (self.got_mod_data || !matches!(fml_packet_id, Some(1 | 5)))
+                    {
+                        return Ok(self.done());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn done(&self) -> Exchange {
+        Exchange::Done(serde_json::to_vec(&self.mod_list).unwrap_or_default())
+    }
+}
+
+/// Read one packet, and decompress it if the connection is compressed. Returns
+/// the packet id and data, and the number of bytes that were read.
+fn read_packet(
+    data: &[u8],
+    compression_threshold: Option<i32>,
+) -> Result<(Vec<u8>, usize), ParseResponseError> {
+    let mut reader = data;
+    let packet_length =
+        read_varint(&mut reader).ok_or(ParseResponseError::Incomplete { expected_length: 1 })?;
+    if packet_length <= 0 {
+        return Err(ParseResponseError::Invalid);
+    }
+    let header_length = data.len() - reader.len();
+    let Some(packet) = reader.get(..packet_length as usize) else {
+        return Err(ParseResponseError::Incomplete {
+            expected_length: packet_length as u32,
+        });
+    };
+    let total_length = header_length + packet_length as usize;
+
+    if compression_threshold.is_none() {
+        return Ok((packet.to_vec(), total_length));
+    }
+
+    let mut packet = packet;
+    let data_length = read_varint(&mut packet).ok_or(ParseResponseError::Invalid)?;
+    if data_length == 0 {
+        // not compressed since it's below the threshold
+        return Ok((packet.to_vec(), total_length));
+    }
+    if data_length < 0 || data_length as usize > MAX_PACKET_SIZE {
+        return Err(ParseResponseError::Invalid);
+    }
+    let mut decompressed = Vec::with_capacity(data_length as usize);
+    ZlibDecoder::new(packet)
+        .take(data_length as u64)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| ParseResponseError::Invalid)?;
+    Ok((decompressed, total_length))
+}
+
+fn write_packet(packet: &[u8], compression_threshold: Option<i32>) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    // we only send tiny packets so they're never actually compressed
+    let length = packet.len() + compression_threshold.is_some() as usize;
+    buffer.push(length as u8);
+    if compression_threshold.is_some() {
+        buffer.push(0x00);
+    }
+    buffer.extend_from_slice(packet);
+    buffer
+}
+
+fn read_string(reader: &mut &[u8]) -> Option<String> {
+    let length = read_varint(reader)?;
+    if length < 0 || reader.len() < length as usize {
+        return None;
+    }
+    let (string, rest) = reader.split_at(length as usize);
+    *reader = rest;
+    Some(String::from_utf8_lossy(string).to_string())
+}
+
+fn read_bool(reader: &mut &[u8]) -> Option<bool> {
+    let (&byte, rest) = reader.split_first()?;
+    *reader = rest;
+    Some(byte != 0)
+}
+
+/// Read FML's `S2CModList`, which is the same in FML2 and FML3 except that FML3
+/// adds datapack registries at the end (which we don't care about).
+fn read_fml_mod_list(reader: &mut &[u8], mod_list: &mut ForgeModList) -> Option<()> {
+    let mod_count = read_varint(reader)?;
+    for _ in 0..mod_count {
+        let mod_id = read_string(reader)?;
+        // the mod data might've come first
+        if !mod_list.mods.iter().any(|(id, _)| *id == mod_id) {
+            mod_list.mods.push((mod_id, None));
+        }
+    }
+    let channel_count = read_varint(reader)?;
+    for _ in 0..channel_count {
+        let name = read_string(reader)?;
+        let version = read_string(reader)?;
+        mod_list.channels.push((name, version));
+    }
+    Some(())
+}
+
+/// Read FML's `S2CModData`, which has the display names and versions of the
+/// mods.
+fn read_fml_mod_data(reader: &mut &[u8], mod_list: &mut ForgeModList) -> Option<()> {
+    let mod_count = read_varint(reader)?;
+    for _ in 0..mod_count {
+        let mod_id = read_string(reader)?;
+        let _display_name = read_string(reader)?;
+        let version = read_string(reader)?;
+        match mod_list.mods.iter_mut().find(|(id, _)| *id == mod_id) {
+            Some((_, mod_version)) => *mod_version = Some(version),
+            None => mod_list.mods.push((mod_id, Some(version))),
+        }
+    }
+    Some(())
+}
+
+/// Read NeoForge's modded network query, which has the channels for the
+/// configuration and play phases. NeoForge doesn't send its mod list so we
+/// guess the mods from the channel namespaces.
+fn read_neoforge_network(reader: &mut &[u8], mod_list: &mut ForgeModList) -> Option<()> {
+    for _phase in 0..2 {
+        let channel_count = read_varint(reader)?;
+        for _ in 0..channel_count {
+            let name = read_string(reader)?;
+            let version = if read_bool(reader)? {
+                read_string(reader)?
+            } else {
+                String::new()
+            };
+            // the packet flow
+            if read_bool(reader)? {
+                read_varint(reader)?;
+            }
+            let _optional = read_bool(reader)?;
+
+            if let Some((namespace, _)) = name.split_once(':') {
+                if namespace != "minecraft" && !mod_list.mods.iter().any(|(id, _)| id == namespace)
+                {
+                    mod_list.mods.push((namespace.to_string(), None));
+                }
+            }
+            if !mod_list.channels.iter().any(|(n, _)| *n == name) {
+                mod_list.channels.push((name, version));
+            }
+        }
+    }
+    mod_list.complete = false;
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::protocols::minecraft::write_varint;
+
+    fn write_string(buffer: &mut Vec<u8>, string: &str) {
+        write_varint(buffer, string.len() as i32);
+        buffer.extend_from_slice(string.as_bytes());
+    }
+
+    fn login_wrapper_packet(fml_packet: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x04];
+        write_varint(&mut packet, 0);
+        write_string(&mut packet, "fml:loginwrapper");
+        write_string(&mut packet, "fml:handshake");
+        write_varint(&mut packet, fml_packet.len() as i32);
+        packet.extend_from_slice(fml_packet);
+
+        let mut full = vec![];
+        write_varint(&mut full, packet.len() as i32);
+        full.extend(packet);
+        full
+    }
+
+    #[test]
+    fn test_fml_mod_list() {
+        let mut mod_list_packet = vec![1];
+        write_varint(&mut mod_list_packet, 2);
+        write_string(&mut mod_list_packet, "forge");
+        write_string(&mut mod_list_packet, "create");
+        write_varint(&mut mod_list_packet, 1);
+        write_string(&mut mod_list_packet, "create:main");
+        write_string(&mut mod_list_packet, "1");
+        write_varint(&mut mod_list_packet, 0);
+
+        let mut mod_data_packet = vec![5];
+        write_varint(&mut mod_data_packet, 1);
+        write_string(&mut mod_data_packet, "create");
+        write_string(&mut mod_data_packet, "Create");
+        write_string(&mut mod_data_packet, "0.5.1");
+
+        let mut response = login_wrapper_packet(&mod_list_packet);
+        // only the mod list so far
+        let protocol = MinecraftForge::new(HashMap::new(), "matscan");
+        let mut session = Session::default();
+        assert!(matches!(
+            protocol.handle_response(&mut session, Response::Data(response.clone())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+        assert!(!session.data.is_empty());
+        // the mod list isn't read again when the rest arrives
+        assert_eq!(session.state::<ForgeSession>().parsed, response.len());
+
+        response.extend(login_wrapper_packet(&mod_data_packet));
+        let Ok(Exchange::Done(data)) =
+            protocol.handle_response(&mut session, Response::Data(response))
+        else {
+            panic!("expected the mod list to be done");
+        };
+        let mod_list: ForgeModList = serde_json::from_slice(&data).unwrap();
+        assert!(mod_list.complete);
+        assert_eq!(
+            mod_list.mods,
+            vec![
+                ("forge".to_string(), None),
+                ("create".to_string(), Some("0.5.1".to_string()))
+            ]
+        );
+        assert_eq!(
+            mod_list.channels,
+            vec![("create:main".to_string(), "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_login_success() {
+        let old = SocketAddr::from(([1, 2, 3, 4], 25565));
+        let new = SocketAddr::from(([1, 2, 3, 5], 25565));
+        let protocol = MinecraftForge::new(
+            HashMap::from([
+                (
+                    old,
+                    ForgeTarget {
+                        protocol_version: 763,
+                        fml_network_version: Some(3),
+                    },
+                ),
+                (
+                    new,
+                    ForgeTarget {
+                        protocol_version: 767,
+                        fml_network_version: None,
+                    },
+                ),
+            ]),
+            "matscan",
+        );
+        // login success with an empty uuid and username
+        let mut login_success = vec![0x02];
+        login_success.extend_from_slice(&[0; 16]);
+        write_string(&mut login_success, "");
+        let mut response = vec![];
+        write_varint(&mut response, login_success.len() as i32);
+        response.extend(login_success);
+
+        // 1.20.1 has no configuration phase, so we stop there
+        let mut session = Session {
+            address: Some(old),
+            ..Default::default()
+        };
+        let Ok(Exchange::Done(data)) =
+            protocol.handle_response(&mut session, Response::Data(response.clone()))
+        else {
+            panic!("expected to stop at login success");
+        };
+        let mod_list: ForgeModList = serde_json::from_slice(&data).unwrap();
+        assert!(mod_list.mods.is_empty());
+
+        // but newer servers get the login acknowledged
+        let mut session = Session {
+            address: Some(new),
+            ..Default::default()
+        };
+        let Ok(Exchange::Reply(reply)) =
+            protocol.handle_response(&mut session, Response::Data(response))
+        else {
+            panic!("expected login acknowledged");
+        };
+        assert_eq!(reply, vec![0x01, 0x03]);
+    }
+}
@@ -0,0 +1,309 @@
+use std::{collections::HashMap, io::Cursor, net::SocketAddr};
+
+use super::{
+    minecraft::{read_varint, write_varint, HandshakeIntent},
+    ParseResponseError, Protocol, Response,
+};
+
+/// Snapshots have their own protocol versions, which are this plus the
+/// snapshot's number.
+const SNAPSHOT_PROTOCOL_BASE: i32 = 0x40000000;
+/// 1.20.2, which added the configuration phase.
+const CONFIGURATION_PHASE_RELEASE: i32 = 764;
+/// 23w31a, the first snapshot with the configuration phase.
+const CONFIGURATION_PHASE_SNAPSHOT: i32 = SNAPSHOT_PROTOCOL_BASE + 144;
+
+/// Whether the protocol version has the configuration phase between login and
+/// play, which came with the uuid in login start being required.
+pub(super) fn has_configuration_phase(protocol_version: i32) -> bool {
+    if protocol_version >= SNAPSHOT_PROTOCOL_BASE {
+        protocol_version >= CONFIGURATION_PHASE_SNAPSHOT
+    } else {
+        protocol_version >= CONFIGURATION_PHASE_RELEASE
+    }
+}
+
+/// Start logging in to servers to find out whether they're in online mode.
+/// We only look at the first packet the server sends back, so we never
+/// actually join.
+pub struct MinecraftLogin {
+    protocol_versions: HashMap<SocketAddr, i32>,
+    username: String,
+    uuid: u128,
+}
+
+impl MinecraftLogin {
+    pub fn new(protocol_versions: HashMap<SocketAddr, i32>, username: &str) -> Self {
+        Self {
+            protocol_versions,
+            username: username.to_string(),
+            uuid: rand::random(),
+        }
+    }
+}
+
+impl Protocol for MinecraftLogin {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let Some(&protocol_version) = self.protocol_versions.get(&address) else {
+            return vec![];
+        };
+        build_login_request(
+            &address.ip().to_string(),
+            address.port(),
+            protocol_version,
+            &self.username,
+            self.uuid,
+        )
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        parse_login_packet(response)
+    }
+}
+
+/// Log in with the transfer intent from 1.20.5, like a client that was sent
+/// to the server with a transfer packet. Servers with
+/// `accepts-transfers=false` disconnect us right away.
+pub struct MinecraftTransfer {
+    protocol_versions: HashMap<SocketAddr, i32>,
+    username: String,
+    uuid: u128,
+}
+
+impl MinecraftTransfer {
+    pub fn new(protocol_versions: HashMap<SocketAddr, i32>, username: &str) -> Self {
+        Self {
+            protocol_versions,
+            username: username.to_string(),
+            uuid: rand::random(),
+        }
+    }
+}
+
+impl Protocol for MinecraftTransfer {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let Some(&protocol_version) = self.protocol_versions.get(&address) else {
+            return vec![];
+        };
+        build_login_request_with_intent(
+            &address.ip().to_string(),
+            address.port(),
+            protocol_version,
+            &self.username,
+            self.uuid,
+            HandshakeIntent::Transfer,
+        )
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        parse_login_packet(response)
+    }
+}
+
+/// What Geyser puts in front of the Floodgate data that it adds to the
+/// hostname, followed by the data format version (0) offset by `>`.
+const FLOODGATE_HEADER: &str = "^Floodgate^>";
+
+/// Log in with a hostname that has Floodgate data in it like Geyser sends, but
+/// that can't be decrypted. Servers with Floodgate kick us with one of its
+/// messages, other servers ignore it and the login goes on like normal.
+pub struct MinecraftFloodgate {
+    protocol_versions: HashMap<SocketAddr, i32>,
+    username: String,
+    uuid: u128,
+}
+
+impl MinecraftFloodgate {
+    pub fn new(protocol_versions: HashMap<SocketAddr, i32>, username: &str) -> Self {
+        Self {
+            protocol_versions,
+            username: username.to_string(),
+            uuid: rand::random(),
+        }
+    }
+}
+
+impl Protocol for MinecraftFloodgate {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let Some(&protocol_version) = self.protocol_versions.get(&address) else {
+            return vec![];
+        };
+        // the data is base64, this is long enough that it's not rejected for
+        // being too short before it's decrypted
+        let hostname = format!("{}\0{FLOODGATE_HEADER}{}", address.ip(), "A".repeat(64));
+        build_login_request(
+            &hostname,
+            address.port(),
+            protocol_version,
+            &self.username,
+            self.uuid,
+        )
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        parse_login_packet(response)
+    }
+}
+
+/// Parse the first packet that the server sent after our login start and
+/// return its id and data.
+pub(super) fn parse_login_packet(response: Response) -> Result<Vec<u8>, ParseResponseError> {
+    let response = match response {
+        Response::Data(r) => r,
+        Response::Rst => return Err(ParseResponseError::Invalid),
+    };
+
+    let mut stream = Cursor::new(response);
+    let packet_length = read_varint(&mut stream).ok_or(ParseResponseError::Invalid)?;
+    if packet_length <= 0 {
+        return Err(ParseResponseError::Invalid);
+    }
+    let position = stream.position() as usize;
+    let response = stream.into_inner();
+    let Some(packet) = response.get(position..position + packet_length as usize) else {
+        return Err(ParseResponseError::Incomplete {
+            expected_length: packet_length as u32,
+        });
+    };
+
+    // the packet id and data, the processing figures out what it means
+    Ok(packet.to_vec())
+}
+
+/// Create a handshake with the next state set to login, followed by a login
+/// start packet.
+pub fn build_login_request(
+    hostname: &str,
+    port: u16,
+    protocol_version: i32,
+    username: &str,
+    uuid: u128,
+) -> Vec<u8> {
+    build_login_request_with_intent(
+        hostname,
+        port,
+        protocol_version,
+        username,
+        uuid,
+        HandshakeIntent::Login,
+    )
+}
+
+/// Same as [`build_login_request`], but with the given intent in the
+/// handshake.
+pub fn build_login_request_with_intent(
+    hostname: &str,
+    port: u16,
+    protocol_version: i32,
+    username: &str,
+    uuid: u128,
+    intent: HandshakeIntent,
+) -> Vec<u8> {
+    // buffer for the 1st packet's data part
+    let mut buffer = vec![
+        // 0 for handshake packet
+        0x00,
+    ];
+
+    write_varint(&mut buffer, protocol_version); // protocol version
+    write_varint(&mut buffer, hostname.len() as i32); // length of hostname as VarInt
+    buffer.extend_from_slice(hostname.as_bytes());
+    buffer.extend_from_slice(&[
+        (port >> 8) as u8,
+        (port & 0b1111_1111) as u8, // server port as unsigned short
+        intent as u8,               // next state: 2 (login) or 3 (transfer)
+    ]);
+    let mut full_buffer = vec![];
+    write_varint(&mut full_buffer, buffer.len() as i32); // length of 1st packet id + data as VarInt
+    full_buffer.append(&mut buffer);
+
+    // login start, its fields changed a few times in 1.19.x
+    let mut buffer = vec![
+        // 0 for login start
+        0x00,
+    ];
+    write_varint(&mut buffer, username.len() as i32);
+    buffer.extend_from_slice(username.as_bytes());
+    match protocol_version {
+        // 1.20.2+, uuid is required
+        v if has_configuration_phase(v) => buffer.extend_from_slice(&uuid.to_be_bytes()),
+        // 1.19.3-1.20.1, optional uuid
+        761..=763 => buffer.push(0x00),
+        // 1.19.1-1.19.2, optional signature and optional uuid
+        760 => buffer.extend_from_slice(&[0x00, 0x00]),
+        // 1.19, optional signature
+        759 => buffer.push(0x00),
+        _ => {}
+    }
+    write_varint(&mut full_buffer, buffer.len() as i32);
+    full_buffer.append(&mut buffer);
+
+    full_buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fields in login start after the username.
+    fn login_start_tail(protocol_version: i32) -> Vec<u8> {
+        let request = build_login_request("localhost", 25565, protocol_version, "a", 1);
+        let handshake_length = request[0] as usize + 1;
+        // length, packet id, username length, username
+        request[handshake_length + 4..].to_vec()
+    }
+
+    #[test]
+    fn test_transfer_payload() {
+        let address = SocketAddr::from(([1, 2, 3, 4], 25565));
+        let transfer = MinecraftTransfer::new(HashMap::from([(address, 766)]), "a");
+
+        let payload = transfer.payload(address);
+        let handshake_length = payload[0] as usize + 1;
+        // the intent is the last byte of the handshake
+        assert_eq!(
+            payload[handshake_length - 1],
+            HandshakeIntent::Transfer as u8
+        );
+        assert_eq!(
+            payload[handshake_length..],
+            build_login_request("1.2.3.4", 25565, 766, "a", transfer.uuid)[handshake_length..]
+        );
+
+        // we only know which version to use for servers that we've pinged
+        assert!(transfer
+            .payload(SocketAddr::from(([1, 2, 3, 5], 25565)))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_floodgate_payload() {
+        let address = SocketAddr::from(([1, 2, 3, 4], 25565));
+        let floodgate = MinecraftFloodgate::new(HashMap::from([(address, 767)]), "a");
+
+        let payload = floodgate.payload(address);
+        // packet length, packet id, protocol version (2 bytes), hostname length
+        let hostname_length = payload[4] as usize;
+        let hostname = std::str::from_utf8(&payload[5..5 + hostname_length]).unwrap();
+        let (ip, data) = hostname.split_once('\0').unwrap();
+        assert_eq!(ip, "1.2.3.4");
+        assert!(data.starts_with(FLOODGATE_HEADER));
+    }
+
+    #[test]
+    fn test_login_start_uuid() {
+        let uuid = 1u128.to_be_bytes().to_vec();
+        assert_eq!(login_start_tail(758), Vec::<u8>::new());
+        assert_eq!(login_start_tail(759), vec![0x00]);
+        assert_eq!(login_start_tail(760), vec![0x00, 0x00]);
+        assert_eq!(login_start_tail(763), vec![0x00]);
+        assert_eq!(login_start_tail(764), uuid);
+        assert_eq!(login_start_tail(769), uuid);
+
+        // snapshots from before and after 23w31a
+        assert_eq!(login_start_tail(SNAPSHOT_PROTOCOL_BASE), Vec::<u8>::new());
+        assert_eq!(login_start_tail(0x40000000 + 143), Vec::<u8>::new());
+        assert_eq!(login_start_tail(0x40000000 + 144), uuid);
+        assert_eq!(login_start_tail(0x40000000 + 250), uuid);
+    }
+}
@@ -0,0 +1,106 @@
+use std::net::SocketAddr;
+
+use super::{
+    minecraft::{build_latest_request, parse_status},
+    minecraft_login::{build_login_request, parse_login_packet},
+    ParseResponseError, Protocol, Response,
+};
+
+/// Find out which protocol versions a server accepts by connecting with a
+/// version that isn't its own. Servers with ViaVersion (or proxies that support
+/// multiple versions) send back our version in the status if they support it.
+pub struct MinecraftProtocolRange {
+    candidate: i32,
+    method: ProbeMethod,
+    username: String,
+    uuid: u128,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProbeMethod {
+    /// Do a server list ping and check whether the version in the status is
+    /// the one we sent.
+    Status,
+    /// Start logging in and check whether we get disconnected for being on
+    /// the wrong version. This shows up in the server's console.
+    Login,
+}
+
+impl MinecraftProtocolRange {
+    pub fn new(candidate: i32, method: ProbeMethod, username: &str) -> Self {
+        Self {
+            candidate,
+            method,
+            username: username.to_string(),
+            uuid: rand::random(),
+        }
+    }
+}
+
+impl Protocol for MinecraftProtocolRange {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        let hostname = address.ip().to_string();
+        match self.method {
+            ProbeMethod::Status => build_latest_request(&hostname, address.port(), self.candidate),
+            ProbeMethod::Login => build_login_request(
+                &hostname,
+                address.port(),
+                self.candidate,
+                &self.username,
+                self.uuid,
+            ),
+        }
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let data = match self.method {
+            ProbeMethod::Status => parse_status(response)?,
+            ProbeMethod::Login => parse_login_packet(response)?,
+        };
+
+        // the processing doesn't have access to the protocol, so it needs to be
+        // told what we sent
+        let mut probe = Vec::with_capacity(data.len() + 5);
+        probe.push(self.method as u8);
+        probe.extend_from_slice(&self.candidate.to_be_bytes());
+        probe.extend_from_slice(&data);
+        Ok(probe)
+    }
+}
+
+/// Split the data that was queued by [`MinecraftProtocolRange`] back into the
+/// method, the protocol version that we sent, and the response.
+pub fn parse_probe(data: &[u8]) -> Option<(ProbeMethod, i32, &[u8])> {
+    let method = match *data.first()? {
+        0 => ProbeMethod::Status,
+        1 => ProbeMethod::Login,
+        _ => return None,
+    };
+    let candidate = i32::from_be_bytes(data.get(1..5)?.try_into().ok()?);
+    Some((method, candidate, &data[5..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_probe_roundtrip() {
+        let protocol = MinecraftProtocolRange::new(340, ProbeMethod::Status, "matscan");
+        let status = br#"{"version":{"name":"1.12.2","protocol":340}}"#;
+        let mut response = vec![status.len() as u8 + 2, 0x00, status.len() as u8];
+        response.extend_from_slice(status);
+
+        let data = protocol.parse_response(Response::Data(response)).unwrap();
+        let (method, candidate, status_data) = parse_probe(&data).unwrap();
+        assert_eq!(method, ProbeMethod::Status);
+        assert_eq!(candidate, 340);
+        assert_eq!(status_data, status);
+
+        // the handshake has the candidate instead of the server's version
+        let payload = protocol.payload(SocketAddr::from((Ipv4Addr::LOCALHOST, 25565)));
+        assert_eq!(&payload[2..4], &[0xd4, 0x02]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::{Exchange, Minecraft, ParseResponseError, Protocol, Response, Session};
+use crate::{
+    net::interface::{source_index, Network},
+    scanner::{cookie, SourcePort},
+};
+
+/// The signature at the start of every PROXY protocol v2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProxyHeaderVersion {
+    /// The human-readable header, like `PROXY TCP4 ...\r\n`.
+    V1,
+    /// The binary header.
+    V2,
+}
+
+/// The normal server list ping, but with a PROXY protocol header in front of
+/// it. Servers behind things like TCPShield only accept connections from the
+/// proxy, so they ignore the ping unless it has the header.
+#[derive(Clone)]
+pub struct MinecraftProxyProtocol {
+    minecraft: Minecraft,
+    version: ProxyHeaderVersion,
+    /// The header says the connection is from us, so these are the same as
+    /// what the scanner sends from.
+    source_ipv4s: Vec<Ipv4Addr>,
+    source_ipv6s: Vec<Ipv6Addr>,
+    source_port: SourcePort,
+    /// The scanner's seed, the source port is picked from the cookie.
+    seed: u64,
+}
+
+impl MinecraftProxyProtocol {
+    pub fn new(
+        minecraft: Minecraft,
+        version: ProxyHeaderVersion,
+        network: &Network,
+        source_port: SourcePort,
+        seed: u64,
+    ) -> Self {
+        Self {
+            minecraft,
+            version,
+            source_ipv4s: network.source_ipv4s.clone(),
+            source_ipv6s: network.source_ipv6s.clone(),
+            source_port,
+            seed,
+        }
+    }
+
+    /// The address that the scanner connects to the target from, picked the
+    /// same way as the writer does. None for IPv6 targets if we don't have an
+    /// IPv6 address.
+    fn source(&self, address: SocketAddr) -> Option<SocketAddr> {
+        let ip = match address {
+            SocketAddr::V4(_) => {
+                IpAddr::V4(self.source_ipv4s[source_index(address, self.source_ipv4s.len())])
+            }
+            SocketAddr::V6(_) => IpAddr::V6(
+                *self
+                    .source_ipv6s
+                    .get(source_index(address, self.source_ipv6s.len()))?,
+            ),
+        };
+        let port = self.source_port.pick(cookie(&address, self.seed));
+        Some(SocketAddr::new(ip, port))
+    }
+}
+
+impl Protocol for MinecraftProxyProtocol {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        // an empty payload skips the target
+        let Some(source) = self.source(address) else {
+            return vec![];
+        };
+        let mut payload = build_proxy_header(self.version, source, address);
+        payload.extend(self.minecraft.payload(address));
+        payload
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        self.minecraft.parse_response(response)
+    }
+
+    fn handle_response(
+        &self,
+        session: &mut Session,
+        response: Response,
+    ) -> Result<Exchange, ParseResponseError> {
+        // the header is only sent once, after that it's a normal connection
+        self.minecraft.handle_response(session, response)
+    }
+}
+
+/// Build a PROXY protocol header. The source and destination must be the same
+/// family.
+pub fn build_proxy_header(
+    version: ProxyHeaderVersion,
+    source: SocketAddr,
+    destination: SocketAddr,
+) -> Vec<u8> {
+    match version {
+        ProxyHeaderVersion::V1 => format!(
+            "PROXY {} {} {} {} {}\r\n",
+            if source.is_ipv4() { "TCP4" } else { "TCP6" },
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port()
+        )
+        .into_bytes(),
+        ProxyHeaderVersion::V2 => {
+            let mut buffer = Vec::with_capacity(52);
+            buffer.extend_from_slice(&V2_SIGNATURE);
+            buffer.push(0x21); // version 2, PROXY command
+            match (source.ip(), destination.ip()) {
+                (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+                    buffer.push(0x11); // TCP over IPv4
+                    buffer.extend_from_slice(&12u16.to_be_bytes()); // length of the addresses
+                    buffer.extend_from_slice(&source_ip.octets());
+                    buffer.extend_from_slice(&destination_ip.octets());
+                }
+                (IpAddr::V6(source_ip), IpAddr::V6(destination_ip)) => {
+                    buffer.push(0x21); // TCP over IPv6
+                    buffer.extend_from_slice(&36u16.to_be_bytes());
+                    buffer.extend_from_slice(&source_ip.octets());
+                    buffer.extend_from_slice(&destination_ip.octets());
+                }
+                _ => panic!("the source and destination must be the same family"),
+            }
+            buffer.extend_from_slice(&source.port().to_be_bytes());
+            buffer.extend_from_slice(&destination.port().to_be_bytes());
+            buffer
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pnet::{
+        datalink::NetworkInterface,
+        packet::{ipv4::Ipv4Packet, tcp::TcpPacket, Packet},
+    };
+
+    use super::*;
+    use crate::net::{fingerprint::TcpFingerprint, tcp::SynTemplates};
+
+    #[test]
+    fn test_source() {
+        let network = Network {
+            interface: NetworkInterface {
+                name: "eth0".to_string(),
+                description: String::new(),
+                index: 0,
+                mac: None,
+                ips: vec![],
+                flags: 0,
+            },
+            source_ipv4s: vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)],
+            source_ipv6s: vec![],
+            gateway_mac: None,
+            gateway_mac_v6: None,
+        };
+        let source_port = SourcePort::Range {
+            min: 61000,
+            max: 62000,
+        };
+        let protocol = MinecraftProxyProtocol::new(
+            Minecraft::new("localhost", 25565, 47, Default::default()),
+            ProxyHeaderVersion::V1,
+            &network,
+            source_port,
+            1234,
+        );
+
+        // the header has to match the syn that the scanner sends
+        let mut syn_templates =
+            SynTemplates::new(&network, source_port, &TcpFingerprint::default());
+        for i in 0..16 {
+            let destination = SocketAddr::from((Ipv4Addr::new(198, 51, 100, i), 25565));
+            let source = protocol.source(destination).unwrap();
+            // no interface mac means no ethernet header
+            let syn = syn_templates
+                .build(destination, cookie(&destination, 1234))
+                .unwrap();
+            let ip = Ipv4Packet::new(syn).unwrap();
+            let tcp = TcpPacket::new(ip.payload()).unwrap();
+            assert_eq!(
+                source,
+                SocketAddr::from((ip.get_source(), tcp.get_source()))
+            );
+            assert!(protocol
+                .payload(destination)
+                .starts_with(format!("PROXY TCP4 {} ", source.ip()).as_bytes()));
+        }
+
+        // no ipv6 address means no ipv6 targets
+        let destination = SocketAddr::from(("2001:db8::2".parse::<Ipv6Addr>().unwrap(), 25565));
+        assert_eq!(protocol.source(destination), None);
+        assert!(protocol.payload(destination).is_empty());
+    }
+
+    #[test]
+    fn test_build_proxy_header() {
+        let source = SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), 61000));
+        let destination = SocketAddr::from((Ipv4Addr::new(198, 51, 100, 2), 25565));
+
+        assert_eq!(
+            build_proxy_header(ProxyHeaderVersion::V1, source, destination),
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 61000 25565\r\n"
+        );
+
+        let header = build_proxy_header(ProxyHeaderVersion::V2, source, destination);
+        assert_eq!(header.len(), 28);
+        assert_eq!(header[..12], V2_SIGNATURE);
+        assert_eq!(header[16..20], [192, 0, 2, 1]);
+        assert_eq!(header[26..28], 25565u16.to_be_bytes());
+
+        let source = SocketAddr::from(("2001:db8::1".parse::<Ipv6Addr>().unwrap(), 61000));
+        let destination = SocketAddr::from(("2001:db8::2".parse::<Ipv6Addr>().unwrap(), 25565));
+
+        assert_eq!(
+            build_proxy_header(ProxyHeaderVersion::V1, source, destination),
+            b"PROXY TCP6 2001:db8::1 2001:db8::2 61000 25565\r\n"
+        );
+
+        let header = build_proxy_header(ProxyHeaderVersion::V2, source, destination);
+        assert_eq!(header.len(), 52);
+        assert_eq!(header[13], 0x21);
+        assert_eq!(header[50..52], 25565u16.to_be_bytes());
+    }
+}
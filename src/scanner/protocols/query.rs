@@ -0,0 +1,161 @@
+use std::net::SocketAddr;
+
+use super::{ParseResponseError, UdpProtocol};
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+
+/// The padding after the header in full stat responses, `splitnum\0\x80\0`.
+const FULL_STAT_PADDING: usize = 11;
+/// The padding between the key-values and the players, `\x01player_\0\0`.
+const PLAYERS_PADDING: &[u8] = b"\x01player_\0\0";
+
+/// The GameSpy4 query protocol, which servers have if they set
+/// `enable-query=true`. Getting the full stat takes two round trips, first
+/// we ask for a challenge token and then we send it back with the full stat
+/// request.
+///
+/// The session id is our cookie, but the server only keeps the lower 4 bits of
+/// every byte.
+#[derive(Clone)]
+pub struct Query;
+
+fn session_id(cookie: u32) -> u32 {
+    cookie & 0x0f0f0f0f
+}
+
+/// Check the type and session id of a response and return the rest of it.
+fn check_header(cookie: u32, kind: u8, response: &[u8]) -> Option<&[u8]> {
+    if response.len() < 5 || response[0] != kind {
+        return None;
+    }
+    let session = u32::from_be_bytes(response[1..5].try_into().unwrap());
+    if session != session_id(cookie) {
+        return None;
+    }
+    Some(&response[5..])
+}
+
+impl UdpProtocol for Query {
+    fn payload(&self, _address: SocketAddr, cookie: u32) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(7);
+        buffer.extend_from_slice(&MAGIC);
+        buffer.push(TYPE_HANDSHAKE);
+        buffer.extend_from_slice(&session_id(cookie).to_be_bytes());
+        buffer
+    }
+
+    fn reply(&self, cookie: u32, response: &[u8]) -> Option<Vec<u8>> {
+        let challenge = check_header(cookie, TYPE_HANDSHAKE, response)?;
+        // the challenge token is a null-terminated number as a string
+        let challenge = challenge.split(|&b| b == 0).next()?;
+        let challenge = std::str::from_utf8(challenge).ok()?.parse::<i32>().ok()?;
+
+        let mut buffer = Vec::with_capacity(15);
+        buffer.extend_from_slice(&MAGIC);
+        buffer.push(TYPE_STAT);
+        buffer.extend_from_slice(&session_id(cookie).to_be_bytes());
+        buffer.extend_from_slice(&challenge.to_be_bytes());
+        // padding, this is what makes it a full stat instead of a basic stat
+        buffer.extend_from_slice(&[0, 0, 0, 0]);
+        Some(buffer)
+    }
+
+    fn parse_response(&self, cookie: u32, response: &[u8]) -> Result<Vec<u8>, ParseResponseError> {
+        let Some(stat) = check_header(cookie, TYPE_STAT, response) else {
+            return Err(ParseResponseError::Invalid);
+        };
+        let Some(stat) = stat.get(FULL_STAT_PADDING..) else {
+            return Err(ParseResponseError::Invalid);
+        };
+        // make sure it actually parses before we bother processing it
+        if parse_full_stat(stat).is_none() {
+            return Err(ParseResponseError::Invalid);
+        }
+
+        Ok(stat.to_vec())
+    }
+}
+
+pub struct FullStat {
+    pub key_values: Vec<(String, String)>,
+    pub players: Vec<String>,
+}
+
+/// Parse the body of a full stat response (after the padding) into the
+/// key-values and the list of player names.
+pub fn parse_full_stat(data: &[u8]) -> Option<FullStat> {
+    let mut key_values = Vec::new();
+
+    let mut rest = data;
+    loop {
+        let key_end = rest.iter().position(|&b| b == 0)?;
+        let key = &rest[..key_end];
+        rest = &rest[key_end + 1..];
+        if key.is_empty() {
+            break;
+        }
+
+        let value_end = rest.iter().position(|&b| b == 0)?;
+        let value = &rest[..value_end];
+        rest = &rest[value_end + 1..];
+
+        key_values.push((
+            String::from_utf8_lossy(key).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        ));
+    }
+
+    // the player list isn't required, some servers cut the response short
+    let mut players = Vec::new();
+    if let Some(mut rest) = rest.strip_prefix(PLAYERS_PADDING) {
+        while let Some(name_end) = rest.iter().position(|&b| b == 0) {
+            if name_end == 0 {
+                break;
+            }
+            players.push(String::from_utf8_lossy(&rest[..name_end]).into_owned());
+            rest = &rest[name_end + 1..];
+        }
+    }
+
+    Some(FullStat {
+        key_values,
+        players,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_stat() {
+        let cookie = 0x12345678;
+
+        let mut handshake = vec![TYPE_HANDSHAKE];
+        handshake.extend_from_slice(&session_id(cookie).to_be_bytes());
+        handshake.extend_from_slice(b"9513307\0");
+        let reply = Query.reply(cookie, &handshake).unwrap();
+        assert_eq!(reply[7..11], 9513307i32.to_be_bytes());
+        // wrong session id
+        assert!(Query.reply(0x87654321, &handshake).is_none());
+
+        let mut response = vec![TYPE_STAT];
+        response.extend_from_slice(&session_id(cookie).to_be_bytes());
+        response.extend_from_slice(b"splitnum\0\x80\0");
+        response.extend_from_slice(b"hostname\0A Minecraft Server\0gametype\0SMP\0map\0world\0plugins\0Paper on 1.21: WorldEdit 7.3.0; Essentials 2.20\0\0");
+        response.extend_from_slice(PLAYERS_PADDING);
+        response.extend_from_slice(b"Notch\0jeb_\0\0");
+
+        let stat = Query.parse_response(cookie, &response).unwrap();
+        let full_stat = parse_full_stat(&stat).unwrap();
+        assert_eq!(full_stat.key_values.len(), 4);
+        assert_eq!(
+            full_stat.key_values[2],
+            ("map".to_string(), "world".to_string())
+        );
+        assert_eq!(full_stat.players, vec!["Notch".to_string(), "jeb_".to_string()]);
+    }
+}
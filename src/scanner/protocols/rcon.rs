@@ -0,0 +1,146 @@
+use std::net::SocketAddr;
+
+use super::{ParseResponseError, Protocol, Response};
+use crate::scanner::targets::ip_to_u128;
+
+const TYPE_AUTH: i32 = 3;
+const TYPE_AUTH_RESPONSE: i32 = 2;
+/// The id of our auth packet, the server replies with the same id if the
+/// password was right and -1 if it wasn't.
+const REQUEST_ID: i32 = 0x6d73;
+/// Servers don't send anything bigger than this, so if the length says
+/// otherwise then it's not rcon.
+const MAX_PACKET_LENGTH: i32 = 4096 + 10;
+
+/// Check whether RCON is exposed. We send one auth packet with a random
+/// password and only look at whether the server rejects it, we never try to
+/// actually log in.
+pub struct Rcon {
+    secret: u128,
+}
+
+impl Rcon {
+    pub fn new() -> Self {
+        Self {
+            secret: rand::random(),
+        }
+    }
+}
+
+impl Default for Rcon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RconAuth {
+    Rejected,
+    /// The password was right, which should never happen.
+    Accepted,
+}
+
+impl Protocol for Rcon {
+    fn payload(&self, address: SocketAddr) -> Vec<u8> {
+        // the password has to be the same for every payload sent to the
+        // address, but different for every address and every run
+        let address_bits = ip_to_u128(address.ip()).rotate_left(16) ^ address.port() as u128;
+        let password = format!(
+            "matscan-{:032x}",
+            self.secret ^ address_bits.wrapping_mul(0x9e3779b97f4a7c15f39cc0605cedc835)
+        );
+        build_auth_packet(&password)
+    }
+
+    fn parse_response(&self, response: Response) -> Result<Vec<u8>, ParseResponseError> {
+        let response = match response {
+            Response::Data(r) => r,
+            Response::Rst => return Err(ParseResponseError::Invalid),
+        };
+
+        // some servers send an empty response value before the auth response,
+        // so skip packets until we get it
+        let mut rest = response.as_slice();
+        loop {
+            let Some(length) = rest.get(..4) else {
+                return Err(ParseResponseError::Incomplete { expected_length: 4 });
+            };
+            let length = i32::from_le_bytes(length.try_into().unwrap());
+            if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+                return Err(ParseResponseError::Invalid);
+            }
+            let Some(packet) = rest.get(4..4 + length as usize) else {
+                return Err(ParseResponseError::Incomplete {
+                    expected_length: length as u32,
+                });
+            };
+            let packet_type = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+            if packet_type == TYPE_AUTH_RESPONSE {
+                return Ok(packet.to_vec());
+            }
+            rest = &rest[4 + length as usize..];
+        }
+    }
+}
+
+/// Read the auth response packet that the protocol returned.
+pub fn parse_auth_response(data: &[u8]) -> Option<RconAuth> {
+    let request_id = i32::from_le_bytes(data.get(..4)?.try_into().unwrap());
+    match request_id {
+        -1 => Some(RconAuth::Rejected),
+        REQUEST_ID => Some(RconAuth::Accepted),
+        _ => None,
+    }
+}
+
+fn build_auth_packet(password: &str) -> Vec<u8> {
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&REQUEST_ID.to_le_bytes());
+    packet.extend_from_slice(&TYPE_AUTH.to_le_bytes());
+    packet.extend_from_slice(password.as_bytes());
+    // null-terminated body and then an empty string
+    packet.extend_from_slice(&[0, 0]);
+
+    let mut buffer = (packet.len() as i32).to_le_bytes().to_vec();
+    buffer.append(&mut packet);
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn build_packet(request_id: i32, packet_type: i32) -> Vec<u8> {
+        let mut buffer = 10i32.to_le_bytes().to_vec();
+        buffer.extend_from_slice(&request_id.to_le_bytes());
+        buffer.extend_from_slice(&packet_type.to_le_bytes());
+        buffer.extend_from_slice(&[0, 0]);
+        buffer
+    }
+
+    #[test]
+    fn test_parse_rcon_response() {
+        let protocol = Rcon::new();
+        let address = SocketAddr::from((Ipv4Addr::new(1, 2, 3, 4), 25575));
+        assert_eq!(protocol.payload(address), protocol.payload(address));
+
+        // an empty response value and then the failed auth response
+        let mut response = build_packet(REQUEST_ID, 0);
+        response.extend(build_packet(-1, TYPE_AUTH_RESPONSE));
+        let data = protocol
+            .parse_response(Response::Data(response.clone()))
+            .unwrap();
+        assert_eq!(parse_auth_response(&data), Some(RconAuth::Rejected));
+
+        assert!(matches!(
+            protocol.parse_response(Response::Data(response[..20].to_vec())),
+            Err(ParseResponseError::Incomplete { .. })
+        ));
+        assert!(matches!(
+            protocol.parse_response(Response::Data(b"HTTP/1.1 400 Bad Request".to_vec())),
+            Err(ParseResponseError::Invalid)
+        ));
+    }
+}
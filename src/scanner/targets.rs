@@ -1,19 +1,59 @@
 use std::{
     mem,
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 
+/// A range of addresses and ports. The start and end addresses must be the
+/// same family, and IPv6 ranges should be small since we can't enumerate
+/// them like we do with IPv4.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ScanRange {
-    pub addr_start: Ipv4Addr,
-    pub addr_end: Ipv4Addr,
+    pub addr_start: IpAddr,
+    pub addr_end: IpAddr,
     pub port_start: u16,
     pub port_end: u16,
 }
 
+/// The address as a number, so the range math is the same for IPv4 and IPv6.
+pub fn ip_to_u128(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr) as u128,
+        IpAddr::V6(addr) => u128::from(addr),
+    }
+}
+
+/// Get the address that's `offset` after `addr`, in the same family as
+/// `addr`.
+pub fn ip_add(addr: IpAddr, offset: u128) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(
+            u32::from(addr).wrapping_add(offset as u32),
+        )),
+        IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(u128::from(addr).wrapping_add(offset))),
+    }
+}
+
+/// Get the address that's `offset` before `addr`, in the same family as
+/// `addr`.
+pub fn ip_sub(addr: IpAddr, offset: u128) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(
+            u32::from(addr).wrapping_sub(offset as u32),
+        )),
+        IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(u128::from(addr).wrapping_sub(offset))),
+    }
+}
+
+/// The number of addresses from `start` to `end` (inclusive), capped at
+/// `usize::MAX` for huge IPv6 ranges.
+fn count_between(start: IpAddr, end: IpAddr) -> usize {
+    let count = (ip_to_u128(end) - ip_to_u128(start)).saturating_add(1);
+    usize::try_from(count).unwrap_or(usize::MAX)
+}
+
 impl ScanRange {
     pub fn count_addresses(&self) -> usize {
-        (u32::from(self.addr_end) as u64 - u32::from(self.addr_start) as u64 + 1) as usize
+        count_between(self.addr_start, self.addr_end)
     }
 
     pub fn count_ports(&self) -> usize {
@@ -22,20 +62,21 @@ impl ScanRange {
 
     /// Count the number of combinations of addresses and ports in this range.
     pub fn count(&self) -> usize {
-        self.count_addresses() * self.count_ports()
+        self.count_addresses().saturating_mul(self.count_ports())
     }
 
     /// Get the address and port at the given index.
-    pub fn index(&self, index: usize) -> SocketAddrV4 {
+    pub fn index(&self, index: usize) -> SocketAddr {
         let port_count = self.count_ports();
         let addr_index = index / port_count;
         let port_index = index % port_count;
-        let addr = u32::from(self.addr_start) + addr_index as u32;
+        let addr = ip_add(self.addr_start, addr_index as u128);
         let port = self.port_start + port_index as u16;
-        SocketAddrV4::new(Ipv4Addr::from(addr), port)
+        SocketAddr::new(addr, port)
     }
 
-    pub fn single(addr: Ipv4Addr, port: u16) -> Self {
+    pub fn single(addr: impl Into<IpAddr>, port: u16) -> Self {
+        let addr = addr.into();
         Self {
             addr_start: addr,
             addr_end: addr,
@@ -43,15 +84,16 @@ impl ScanRange {
             port_end: port,
         }
     }
-    pub fn single_port(addr_start: Ipv4Addr, addr_end: Ipv4Addr, port: u16) -> Self {
+    pub fn single_port(addr_start: impl Into<IpAddr>, addr_end: impl Into<IpAddr>, port: u16) -> Self {
         Self {
-            addr_start,
-            addr_end,
+            addr_start: addr_start.into(),
+            addr_end: addr_end.into(),
             port_start: port,
             port_end: port,
         }
     }
-    pub fn single_address(addr: Ipv4Addr, port_start: u16, port_end: u16) -> Self {
+    pub fn single_address(addr: impl Into<IpAddr>, port_start: u16, port_end: u16) -> Self {
+        let addr = addr.into();
         Self {
             addr_start: addr,
             addr_end: addr,
@@ -82,9 +124,9 @@ impl ScanRanges {
 
     /// Remove the given ranges from this set of ranges. Returns the ranges that
     /// were renoved.
-    pub fn apply_exclude(&mut self, exclude_ranges: &Ipv4Ranges) -> Vec<Ipv4Range> {
+    pub fn apply_exclude(&mut self, exclude_ranges: &IpRanges) -> Vec<IpRange> {
         let mut ranges: Vec<ScanRange> = Vec::new();
-        let mut removed_ranges: Vec<Ipv4Range> = Vec::new();
+        let mut removed_ranges: Vec<IpRange> = Vec::new();
 
         let mut scan_ranges = mem::take(&mut self.ranges).into_iter();
         let mut exclude_ranges = exclude_ranges.ranges.iter();
@@ -121,21 +163,21 @@ impl ScanRanges {
                 // scan_range contains exclude_range
                 ranges.push(ScanRange {
                     addr_start: scan_range.addr_start,
-                    addr_end: Ipv4Addr::from(u32::from(exclude_range.start) - 1),
+                    addr_end: ip_sub(exclude_range.start, 1),
                     port_start: scan_range.port_start,
                     port_end: scan_range.port_end,
                 });
                 removed_ranges.push(*exclude_range);
-                scan_range.addr_start = Ipv4Addr::from(u32::from(exclude_range.end) + 1);
+                scan_range.addr_start = ip_add(exclude_range.end, 1);
             } else if scan_range.addr_start < exclude_range.start {
                 // cut off the right side
                 ranges.push(ScanRange {
                     addr_start: scan_range.addr_start,
-                    addr_end: Ipv4Addr::from(u32::from(exclude_range.start) - 1),
+                    addr_end: ip_sub(exclude_range.start, 1),
                     port_start: scan_range.port_start,
                     port_end: scan_range.port_end,
                 });
-                removed_ranges.push(Ipv4Range {
+                removed_ranges.push(IpRange {
                     start: exclude_range.start,
                     end: scan_range.addr_end,
                 });
@@ -145,14 +187,14 @@ impl ScanRanges {
                 };
             } else if scan_range.addr_end > exclude_range.end {
                 // cut off the left side
-                removed_ranges.push(Ipv4Range {
+                removed_ranges.push(IpRange {
                     start: scan_range.addr_start,
                     end: exclude_range.end,
                 });
-                scan_range.addr_start = Ipv4Addr::from(u32::from(exclude_range.end) + 1);
+                scan_range.addr_start = ip_add(exclude_range.end, 1);
             } else {
                 // scan_range is contained within exclude_range
-                removed_ranges.push(Ipv4Range {
+                removed_ranges.push(IpRange {
                     start: scan_range.addr_start,
                     end: scan_range.addr_end,
                 });
@@ -171,7 +213,7 @@ impl ScanRanges {
     /// Get the address and port at the given index.
     ///
     /// You should use [`Self::to_static`] and then call index on that.
-    pub fn slow_index(&self, index: usize) -> SocketAddrV4 {
+    pub fn slow_index(&self, index: usize) -> SocketAddr {
         let mut i = 0;
         let mut index = index;
         while i < self.ranges.len() {
@@ -188,9 +230,9 @@ impl ScanRanges {
 
     /// Count the total number of targets that are going to be scanned.
     pub fn count(&self) -> usize {
-        let mut total = 0;
+        let mut total: usize = 0;
         for range in &self.ranges {
-            total += range.count();
+            total = total.saturating_add(range.count());
         }
         total
     }
@@ -233,7 +275,7 @@ pub struct StaticScanRange {
 }
 
 impl StaticScanRanges {
-    pub fn index(&self, index: usize) -> SocketAddrV4 {
+    pub fn index(&self, index: usize) -> SocketAddr {
         // binary search to find the range that contains the index
         let mut start = 0;
         let mut end = self.ranges.len();
@@ -252,14 +294,16 @@ impl StaticScanRanges {
     }
 }
 
+/// A range of addresses, the start and end must be the same family.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-pub struct Ipv4Range {
-    pub start: Ipv4Addr,
-    pub end: Ipv4Addr,
+pub struct IpRange {
+    pub start: IpAddr,
+    pub end: IpAddr,
 }
 
-impl Ipv4Range {
-    pub fn single(addr: Ipv4Addr) -> Self {
+impl IpRange {
+    pub fn single(addr: impl Into<IpAddr>) -> Self {
+        let addr = addr.into();
         Self {
             start: addr,
             end: addr,
@@ -268,17 +312,18 @@ impl Ipv4Range {
 }
 
 #[derive(Default, Debug)]
-pub struct Ipv4Ranges {
-    ranges: Vec<Ipv4Range>,
+pub struct IpRanges {
+    ranges: Vec<IpRange>,
 }
 
-impl Ipv4Ranges {
-    pub fn new(mut ranges: Vec<Ipv4Range>) -> Self {
+impl IpRanges {
+    pub fn new(mut ranges: Vec<IpRange>) -> Self {
         ranges.sort_by_key(|r| r.start);
         Self { ranges }
     }
 
-    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+    pub fn contains(&self, addr: impl Into<IpAddr>) -> bool {
+        let addr = addr.into();
         let mut start = 0;
         let mut end = self.ranges.len();
         while start < end {
@@ -299,16 +344,16 @@ impl Ipv4Ranges {
         self.ranges.is_empty()
     }
 
-    pub fn ranges(&self) -> &Vec<Ipv4Range> {
+    pub fn ranges(&self) -> &Vec<IpRange> {
         &self.ranges
     }
 
     pub fn count(&self) -> usize {
-        let mut total: u64 = 0;
+        let mut total: usize = 0;
         for range in &self.ranges {
-            total += (u32::from(range.end) as u64) - (u32::from(range.start) as u64) + 1;
+            total = total.saturating_add(count_between(range.start, range.end));
         }
-        total as usize
+        total
     }
 }
 
@@ -326,9 +371,9 @@ mod test {
             0,
         )]);
 
-        let excluded_ranges = ranges.apply_exclude(&Ipv4Ranges::new(vec![Ipv4Range {
-            start: Ipv4Addr::new(1, 64, 64, 64),
-            end: Ipv4Addr::new(1, 96, 96, 96),
+        let excluded_ranges = ranges.apply_exclude(&IpRanges::new(vec![IpRange {
+            start: Ipv4Addr::new(1, 64, 64, 64).into(),
+            end: Ipv4Addr::new(1, 96, 96, 96).into(),
         }]));
 
         assert_eq!(
@@ -350,9 +395,9 @@ mod test {
         );
         assert_eq!(
             excluded_ranges,
-            vec![Ipv4Range {
-                start: Ipv4Addr::new(1, 64, 64, 64),
-                end: Ipv4Addr::new(1, 96, 96, 96),
+            vec![IpRange {
+                start: Ipv4Addr::new(1, 64, 64, 64).into(),
+                end: Ipv4Addr::new(1, 96, 96, 96).into(),
             }]
         );
     }
@@ -367,9 +412,9 @@ mod test {
             0,
         )]);
 
-        let ranges_to_exclude = Ipv4Ranges::new(vec![
-            Ipv4Range::single(Ipv4Addr::new(1, 1, 1, 1)),
-            Ipv4Range::single(Ipv4Addr::new(1, 1, 1, 2)),
+        let ranges_to_exclude = IpRanges::new(vec![
+            IpRange::single(Ipv4Addr::new(1, 1, 1, 1)),
+            IpRange::single(Ipv4Addr::new(1, 1, 1, 2)),
         ]);
         let excluded_ranges = ranges.apply_exclude(&ranges_to_exclude);
 
@@ -389,8 +434,8 @@ mod test {
         assert_eq!(
             excluded_ranges,
             vec![
-                Ipv4Range::single(Ipv4Addr::new(1, 1, 1, 1)),
-                Ipv4Range::single(Ipv4Addr::new(1, 1, 1, 2))
+                IpRange::single(Ipv4Addr::new(1, 1, 1, 1)),
+                IpRange::single(Ipv4Addr::new(1, 1, 1, 2))
             ]
         );
     }
@@ -405,9 +450,9 @@ mod test {
             0,
         )]);
 
-        let excluded_ranges = ranges.apply_exclude(&Ipv4Ranges::new(vec![Ipv4Range {
-            start: Ipv4Addr::new(1, 32, 32, 32),
-            end: Ipv4Addr::new(1, 96, 96, 96),
+        let excluded_ranges = ranges.apply_exclude(&IpRanges::new(vec![IpRange {
+            start: Ipv4Addr::new(1, 32, 32, 32).into(),
+            end: Ipv4Addr::new(1, 96, 96, 96).into(),
         }]));
 
         assert_eq!(
@@ -422,9 +467,9 @@ mod test {
         );
         assert_eq!(
             excluded_ranges,
-            vec![Ipv4Range {
-                start: Ipv4Addr::new(1, 32, 32, 32),
-                end: Ipv4Addr::new(1, 96, 96, 96),
+            vec![IpRange {
+                start: Ipv4Addr::new(1, 32, 32, 32).into(),
+                end: Ipv4Addr::new(1, 96, 96, 96).into(),
             }]
         );
     }
@@ -438,9 +483,9 @@ mod test {
             0,
         )]);
 
-        let excluded_ranges = ranges.apply_exclude(&Ipv4Ranges::new(vec![Ipv4Range {
-            start: Ipv4Addr::new(1, 96, 96, 96),
-            end: Ipv4Addr::new(1, 128, 128, 128),
+        let excluded_ranges = ranges.apply_exclude(&IpRanges::new(vec![IpRange {
+            start: Ipv4Addr::new(1, 96, 96, 96).into(),
+            end: Ipv4Addr::new(1, 128, 128, 128).into(),
         }]));
 
         assert_eq!(
@@ -455,9 +500,9 @@ mod test {
         );
         assert_eq!(
             excluded_ranges,
-            vec![Ipv4Range {
-                start: Ipv4Addr::new(1, 96, 96, 96),
-                end: Ipv4Addr::new(1, 128, 128, 128),
+            vec![IpRange {
+                start: Ipv4Addr::new(1, 96, 96, 96).into(),
+                end: Ipv4Addr::new(1, 128, 128, 128).into(),
             }]
         );
     }
@@ -474,7 +519,34 @@ mod test {
 
     #[test]
     fn contains_but_is_empty() {
-        let ranges = Ipv4Ranges::new(vec![]);
+        let ranges = IpRanges::new(vec![]);
         assert!(!ranges.contains(Ipv4Addr::new(1, 2, 3, 4)));
     }
+
+    #[test]
+    fn test_subtract_ipv6() {
+        let mut ranges = ScanRanges::new();
+        ranges.extend(vec![
+            ScanRange::single_port(Ipv4Addr::new(1, 2, 3, 0), Ipv4Addr::new(1, 2, 3, 255), 0),
+            ScanRange::single_port(
+                "2001:db8::".parse::<Ipv6Addr>().unwrap(),
+                "2001:db8::ff".parse::<Ipv6Addr>().unwrap(),
+                0,
+            ),
+        ]);
+
+        ranges.apply_exclude(&IpRanges::new(vec![IpRange::single(
+            "2001:db8::10".parse::<Ipv6Addr>().unwrap(),
+        )]));
+
+        assert_eq!(ranges.count(), 256 + 255);
+        assert_eq!(
+            ranges.ranges()[2].addr_start,
+            "2001:db8::11".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            ranges.slow_index(256),
+            "[2001:db8::]:0".parse::<SocketAddr>().unwrap()
+        );
+    }
 }
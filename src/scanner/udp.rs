@@ -0,0 +1,88 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, RwLock};
+use pnet::packet::Packet;
+use tracing::trace;
+
+use super::{
+    cookie,
+    protocols::{ConnectionInfo, UdpProtocol},
+    report_statistics,
+};
+use crate::{
+    net::udp::{StatelessUdpReadHalf, StatelessUdpWriteHalf},
+    processing::SharedData,
+};
+
+/// Receives replies for UDP protocols. Unlike with TCP there's no connection
+/// state to keep, we just check the cookie and queue the response (or send
+/// the protocol's reply, if it needs another round trip).
+pub struct UdpScannerReceiver {
+    pub protocol: Arc<RwLock<Box<dyn UdpProtocol>>>,
+    pub shared_process_data: Arc<Mutex<SharedData>>,
+    pub read: StatelessUdpReadHalf,
+    /// Used for protocols that have to reply to the server.
+    pub write: StatelessUdpWriteHalf,
+    /// Should be the same as [`Scanner::seed`](super::Scanner::seed).
+    pub seed: u64,
+    pub has_ended: Arc<AtomicBool>,
+}
+
+impl UdpScannerReceiver {
+    pub fn recv_loop(&mut self) {
+        let mut last_statistics = Instant::now();
+
+        loop {
+            if self.has_ended.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // this waits for packets, so there's no need to sleep
+            let protocol = self.protocol.clone();
+            let protocol = protocol.read();
+            let mut responses = Vec::new();
+            let write = &mut self.write;
+            let seed = self.seed;
+            self.read.recv_with(Duration::from_millis(50), |ip, udp| {
+                let address = SocketAddr::new(ip.source, udp.get_source());
+                let payload = udp.payload();
+
+                let cookie = cookie(&address, seed);
+                if let Some(reply) = protocol.reply(cookie, payload) {
+                    trace!("replying to {address}");
+                    write.send_udp(address, cookie, &reply);
+                    return;
+                }
+                match protocol.parse_response(cookie, payload) {
+                    Ok(data) => {
+                        trace!("UDP response from {address}");
+                        responses.push((address, data, ConnectionInfo::default()));
+                    }
+                    Err(e) => {
+                        trace!("invalid UDP response from {address}: {e:?}");
+                    }
+                }
+            });
+            drop(protocol);
+
+            // one lock for everything we got in this poll
+            if !responses.is_empty() {
+                self.shared_process_data.lock().queue.extend(responses);
+            }
+
+            if last_statistics.elapsed() > Duration::from_secs(60) {
+                if let Some(statistics) = self.read.statistics() {
+                    report_statistics(statistics);
+                }
+                last_statistics = Instant::now();
+            }
+        }
+    }
+}